@@ -0,0 +1,194 @@
+//! Stateless HTTP endpoints, for integrations which don't speak websocket.
+
+use actix_web::{error::InternalError, web, HttpResponse};
+use failure::Error;
+use serde::{Deserialize, Serialize};
+
+use crate::game::{Alliance, Assignment, GameRules, Role, RoleSet};
+
+/// Request body of `POST /deal`
+#[derive(Deserialize)]
+pub struct DealRequest {
+    /// Player names, in seat order
+    pub names: Vec<String>,
+    /// Seed for a reproducible deal
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Custom deck, the standard one is used if absent
+    #[serde(default)]
+    pub roleset: Option<Vec<Role>>,
+}
+
+/// Response body of `POST /deal`
+#[derive(Serialize)]
+pub struct DealResponse {
+    pub seats: Vec<SeatAssignment>,
+    /// Dubious traits of the deck, the deal still goes on
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct SeatAssignment {
+    pub seat: usize,
+    pub name: String,
+    pub role: Role,
+    pub alliance: Alliance,
+    /// Hint text seen by this seat
+    pub sees: String,
+}
+
+#[derive(Serialize)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+/// Body config of the endpoints, a malformed body is answered with an
+/// `ErrorResponse` like any other bad request
+pub fn json_config() -> web::JsonConfig {
+    web::JsonConfig::default().error_handler(|err, _| {
+        let response = HttpResponse::BadRequest().json(ErrorResponse {
+            error: err.to_string(),
+        });
+        InternalError::from_response(err, response).into()
+    })
+}
+
+/// Handler for `POST /deal`, deal roles without touching any room.
+pub async fn deal(req: web::Json<DealRequest>) -> HttpResponse {
+    let DealRequest {
+        names,
+        seed,
+        roleset,
+    } = req.into_inner();
+
+    let role_set = match roleset {
+        Some(roles) => {
+            let role_set = RoleSet::new(roles);
+            role_set
+                .is_playable(names.len())
+                .map(|_| role_set)
+                .map_err(Error::from)
+        }
+        None => RoleSet::standard(names.len()),
+    };
+    let assignment = role_set.and_then(|role_set| {
+        let warnings = role_set.warnings().iter().map(|w| w.to_string()).collect();
+        Assignment::with_role_set(names.into_iter(), &role_set, &GameRules::default(), seed)
+            .map(|assignment| (assignment, warnings))
+    });
+
+    match assignment {
+        Ok((assignment, warnings)) => {
+            let seats = assignment
+                .players
+                .iter()
+                .enumerate()
+                .map(|(seat, (name, role))| SeatAssignment {
+                    seat,
+                    name: name.clone(),
+                    role: *role,
+                    alliance: role.alliance(),
+                    sees: assignment.see_from_role(*role).text_from_player(seat),
+                })
+                .collect();
+
+            HttpResponse::Ok().json(DealResponse { seats, warnings })
+        }
+        Err(err) => HttpResponse::BadRequest().json(ErrorResponse {
+            error: err.to_string(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use actix_web::{http::StatusCode, test, App};
+    use serde_json::{json, Value};
+
+    #[actix_rt::test]
+    async fn test_deal() {
+        let mut app = test::init_service(
+            App::new().service(
+                web::resource("/deal")
+                    .app_data(json_config())
+                    .route(web::post().to(deal)),
+            ),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/deal")
+            .set_json(&json!({
+                "names": ["a", "b", "c", "d", "e"],
+                "seed": 42,
+            }))
+            .to_request();
+        let resp: Value = test::read_response_json(&mut app, req).await;
+        let seats = resp["seats"].as_array().unwrap();
+        assert_eq!(seats.len(), 5);
+        assert_eq!(seats[0]["name"], "a");
+        assert!(seats.iter().any(|seat| seat["role"] == "merlin"));
+        assert!(resp.get("warnings").is_none());
+
+        let req = test::TestRequest::post()
+            .uri("/deal")
+            .set_json(&json!({
+                "names": ["a", "b", "c", "d", "e"],
+                "roleset": ["merlin", "percival", "loyal", "assassin", "mordred"],
+            }))
+            .to_request();
+        let resp: Value = test::read_response_json(&mut app, req).await;
+        assert_eq!(
+            resp["warnings"],
+            json!(["percival sees a single candidate, who is no longer ambiguous"])
+        );
+
+        let req = test::TestRequest::post()
+            .uri("/deal")
+            .set_json(&json!({
+                "names": ["a", "b", "c", "d", "e"],
+                "roleset": ["merlin", "loyal", "loyal", "loyal", "assassin"],
+            }))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body: Value = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+        assert!(body["error"].as_str().unwrap().contains("unbalanced"));
+
+        let req = test::TestRequest::post()
+            .uri("/deal")
+            .set_json(&json!({
+                "names": ["a", "b", "c", "d", "e"],
+                "roleset": ["merlin", "loyal", "loyal", "loyal", "loyal"],
+            }))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body: Value = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+        assert_eq!(body["error"], "role set has no spies");
+
+        let req = test::TestRequest::post()
+            .uri("/deal")
+            .set_json(&json!({ "names": ["a", "b"] }))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        for body in &[json!({ "seed": 42 }), json!({ "names": "a" })] {
+            let req = test::TestRequest::post()
+                .uri("/deal")
+                .set_json(body)
+                .to_request();
+            let resp = test::call_service(&mut app, req).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+            let body: Value = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+            assert!(body["error"]
+                .as_str()
+                .unwrap()
+                .starts_with("Json deserialize error"));
+        }
+    }
+}
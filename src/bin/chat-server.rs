@@ -1,46 +1,37 @@
-use std::time::Instant;
+use std::time::Duration;
 
 use actix::*;
 use actix_files as fs;
-use actix_web::{web, App, Error, HttpRequest, HttpResponse, HttpServer};
-use actix_web_actors::ws;
+use actix_web::{web, App, HttpResponse, HttpServer};
 
 use argh::FromArgs;
 
+use avalon_rs::api;
+use avalon_rs::name::{Blocklist, NamePolicy};
+use avalon_rs::outcome::FileSink;
+use avalon_rs::room_name::RoomNames;
 use avalon_rs::server;
 use avalon_rs::session;
 
-/// Entry point for our route
-async fn chat_route(
-    req: HttpRequest,
-    stream: web::Payload,
-    srv: web::Data<Addr<server::ChatServer>>,
-) -> Result<HttpResponse, Error> {
-    ws::start(
-        session::WsChatSession {
-            id: 0,
-            hb: Instant::now(),
-            name: None,
-            addr: srv.get_ref().clone(),
-        },
-        &req,
-        stream,
-    )
-}
-
 #[actix_rt::main]
 async fn main() -> std::io::Result<()> {
     env_logger::init();
 
-    let addr = get_opts();
+    let (addr, config, server_config, outcomes) = get_opts();
 
     // Start chat server actor
-    let server = server::ChatServer::default().start();
+    let mut server = server::ChatServer::new(server_config);
+    if let Some(path) = outcomes {
+        let sink = FileSink::open(path).expect("failed to open the outcome file");
+        server = server.with_outcome_sink(Box::new(sink));
+    }
+    let server = server.start();
 
     // Create Http server with websocket support
     HttpServer::new(move || {
         App::new()
             .data(server.clone())
+            .data(config.clone())
             // redirect to websocket.html
             .service(web::resource("/").route(web::get().to(|| {
                 HttpResponse::Found()
@@ -48,7 +39,13 @@ async fn main() -> std::io::Result<()> {
                     .finish()
             })))
             // websocket
-            .service(web::resource("/ws/").to(chat_route))
+            .service(web::resource("/ws/").to(session::chat_route))
+            // stateless dealing
+            .service(
+                web::resource("/deal")
+                    .app_data(api::json_config())
+                    .route(web::post().to(api::deal)),
+            )
             // static resources
             .service(fs::Files::new("/static/", "static/"))
     })
@@ -63,11 +60,116 @@ struct Opt {
     /// sets the listen address
     #[argh(option, short = 'a')]
     addr: Option<String>,
+    /// sets the message sent to new connections
+    #[argh(option)]
+    welcome: Option<String>,
+    /// sets the gap in milliseconds between role reveals of seats
+    #[argh(option, default = "0")]
+    reveal_gap: u64,
+    /// sets the file of words not allowed in names, one per line
+    #[argh(option)]
+    blocklist: Option<String>,
+    /// sets the max number of characters in a name
+    #[argh(option, default = "avalon_rs::name::MAX_NAME_LEN")]
+    max_name_len: usize,
+    /// sets the file where outcomes of finished games are recorded
+    #[argh(option)]
+    outcomes: Option<String>,
+    /// sets the token for admin commands, they are disabled if absent
+    #[argh(option)]
+    admin_token: Option<String>,
+    /// sets how many seconds an empty room is kept for a rejoin
+    #[argh(option, default = "0")]
+    empty_room_grace: u64,
+    /// sets the max number of open connections from one IP, unlimited if
+    /// absent
+    #[argh(option)]
+    max_connections_per_ip: Option<usize>,
+    /// sets the max number of sessions connected at once, unlimited if
+    /// absent
+    #[argh(option)]
+    max_sessions: Option<usize>,
+    /// sets the max number of rooms one session owns at once, 0 for
+    /// unlimited
+    #[argh(option, default = "1")]
+    max_rooms_per_owner: usize,
+    /// sets the payload of heartbeat pings, empty if absent
+    #[argh(option)]
+    ping_payload: Option<String>,
+    /// sets the text telling players their role, where the role name
+    /// replaces the role placeholder
+    #[argh(option)]
+    role_template: Option<String>,
+    /// sets the text told to roles which see nothing
+    #[argh(option)]
+    no_hint_template: Option<String>,
+    /// name rooms by word pairs like brave-otter, instead of numbers
+    #[argh(switch)]
+    word_room_names: bool,
+    /// take client IPs from the forwarding headers, only behind a trusted
+    /// proxy
+    #[argh(switch)]
+    trust_proxy: bool,
+    /// speak JSON to new connections, instead of bare text
+    #[argh(switch)]
+    json: bool,
 }
 
 #[inline]
-fn get_opts() -> String {
+fn get_opts() -> (
+    String,
+    session::SessionConfig,
+    server::ServerConfig,
+    Option<String>,
+) {
     let opt: Opt = argh::from_env();
 
-    opt.addr.unwrap_or("127.0.0.1:8080".to_owned())
+    let names = NamePolicy {
+        blocklist: match opt.blocklist {
+            Some(path) => Blocklist::load(path).expect("failed to load the blocklist"),
+            None => Blocklist::default(),
+        },
+        max_len: opt.max_name_len,
+    };
+
+    let mut config = session::SessionConfig {
+        names: names.clone(),
+        trust_forwarded: opt.trust_proxy,
+        ..session::SessionConfig::default()
+    };
+    if opt.json {
+        config.protocol = session::Protocol::Json;
+    }
+    if let Some(welcome) = opt.welcome {
+        config.welcome = welcome;
+    }
+    if let Some(payload) = opt.ping_payload {
+        config.ping_payload = payload.into_bytes();
+    }
+    let server_config = server::ServerConfig {
+        reveal_gap: Duration::from_millis(opt.reveal_gap),
+        names,
+        admin_token: opt.admin_token,
+        empty_room_grace: Duration::from_secs(opt.empty_room_grace),
+        max_connections_per_ip: opt.max_connections_per_ip,
+        max_sessions: opt.max_sessions,
+        room_names: if opt.word_room_names {
+            RoomNames::WordPair
+        } else {
+            RoomNames::Numeric
+        },
+        max_rooms_per_owner: Some(opt.max_rooms_per_owner).filter(|&max| max > 0),
+        templates: server::Templates {
+            role: opt.role_template,
+            no_hint: opt.no_hint_template,
+        },
+        ..server::ServerConfig::default()
+    };
+
+    (
+        opt.addr.unwrap_or_else(|| "127.0.0.1:8080".to_owned()),
+        config,
+        server_config,
+        opt.outcomes,
+    )
 }
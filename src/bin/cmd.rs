@@ -1,11 +1,30 @@
 use std::io::prelude::*;
 use std::iter::Iterator;
 
+use argh::FromArgs;
+use failure::Error;
+
 use avalon_rs::game::*;
 
+#[derive(FromArgs)]
+/// Deal roles to names read from stdin, one per line
+struct Opt {
+    /// print the role distribution of every player count instead
+    #[argh(switch)]
+    table: bool,
+}
+
 fn main() {
+    let opt: Opt = argh::from_env();
+    if opt.table {
+        for players in legal_player_counts() {
+            println!("{}", table_row(players).unwrap());
+        }
+        return;
+    }
+
     let stdin = std::io::stdin();
-    let names = stdin.lock().lines().filter_map(Result::ok);
+    let names = stdin.lock().lines().map_while(Result::ok);
     let assignment = Assignment::new(names).unwrap();
     println!("{}", assignment.see_from_role(Role::Merlin).text());
     println!("# ===================================== #");
@@ -26,3 +45,41 @@ fn main() {
         }
     }
 }
+
+/// Alliances, mission sizes and special roles of a game of `players`
+fn table_row(players: usize) -> Result<String, Error> {
+    let evil = expected_evil(players)?;
+    let missions: Vec<String> = mission_sizes(players)?
+        .iter()
+        .map(|size| size.to_string())
+        .collect();
+    let specials: Vec<&str> = RoleSet::standard(players)?
+        .roles()
+        .iter()
+        .filter(|&&role| role != Role::Loyal)
+        .map(|role| role.name())
+        .collect();
+
+    Ok(format!(
+        "{} 人：{} 好人，{} 坏人，任务 {}，特殊角色 {}",
+        players,
+        players - evil,
+        evil,
+        missions.join("-"),
+        specials.join("、")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_row() {
+        assert_eq!(
+            table_row(5).unwrap(),
+            "5 人：3 好人，2 坏人，任务 2-3-2-3-3，特殊角色 梅林、刺客、派西维尔、莫甘娜"
+        );
+        assert!(table_row(4).is_err());
+    }
+}
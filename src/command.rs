@@ -0,0 +1,666 @@
+//! Slash commands peers send over the websocket.
+
+use std::fmt::{self, Display};
+
+use failure::Fail;
+
+use crate::game;
+use crate::server::RoomOptions;
+use crate::session::Protocol;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Command {
+    List,
+    Open,
+    /// Rooms with a game underway, with their scoreboards
+    Games,
+    Limits,
+    /// Sessions and rooms on the server
+    Load,
+    /// Seated players of the current room
+    Roster,
+    Join {
+        room: String,
+    },
+    Watch {
+        room: String,
+    },
+    /// Take back the held seat of the session name
+    Rejoin {
+        room: String,
+    },
+    /// Whether the seat of the session name is still held
+    ReconnectStatus {
+        room: String,
+    },
+    Review {
+        room: String,
+    },
+    /// `/create <size> [seed] [shuffle] [reveal] [onefail] [lake] [simple] [suffix] [manual] [ready] [secret] [min=<size>] [key=<key>] [lang=<zh|en>]`
+    Create {
+        size: u8,
+        options: RoomOptions,
+        key: Option<String>,
+    },
+    Help,
+    /// Versions of the server and of the JSON protocol
+    Version,
+    /// Deal a game of `size` to nobody, to look at every seat
+    Sandbox {
+        size: u8,
+    },
+    /// What a seat of the last sandbox deal sees, counted from 1
+    See {
+        seat: usize,
+    },
+    Leave,
+    WhoAmI,
+    /// Show the room one's own role
+    Reveal,
+    /// Seats left before the room is full
+    Need,
+    AddBot,
+    /// Name of the current room, to share it again
+    Code,
+    /// Roles the current room deals
+    Deck,
+    /// What each role of the current room does
+    Rules,
+    /// Chat to the room
+    Say {
+        text: String,
+    },
+    /// `/mute` is `muted: true`, `/unmute` is `muted: false`
+    Mute {
+        name: String,
+        muted: bool,
+    },
+    /// Sample deal of the current room, owner only
+    Preview,
+    /// Start before the room is full, owner only
+    Start,
+    /// Scrap the game in play, owner only
+    Abort,
+    /// Confirm the seat is ready to play
+    Ready,
+    /// Permute the seats before the game starts, owner only
+    Shuffle,
+    /// Scoreboard of the game in play
+    Status,
+    /// How long the current room has existed
+    Elapsed,
+    Resize {
+        size: u8,
+    },
+    RevealOnEnd(bool),
+    /// `/pause` is `Paused(true)`, `/resume` is `Paused(false)`
+    Paused(bool),
+    Stats,
+    Overflow,
+    Host {
+        name: String,
+    },
+    Propose {
+        names: Vec<String>,
+    },
+    Vote {
+        approve: bool,
+    },
+    Mission {
+        fail: bool,
+    },
+    /// The assassin names a merlin
+    Assassinate {
+        name: String,
+    },
+    /// The lady of the lake holder inspects a player
+    Lake {
+        name: String,
+    },
+    Protocol(Protocol),
+    Name {
+        name: String,
+    },
+    /// Admin only
+    Close {
+        room: String,
+    },
+    Auth {
+        token: String,
+    },
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ParseError {
+    UnknownCommand(String),
+    /// The named argument is missing
+    Required(&'static str),
+    InvalidSize(String),
+    UnsupportedSize(usize),
+    UnknownOption(String),
+    /// `min=` of `/create` is over the room size
+    MinStartOverSize {
+        min_start: usize,
+        size: usize,
+    },
+    /// The argument isn't one of the choices
+    Expected {
+        what: &'static str,
+        choices: &'static str,
+    },
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::UnknownCommand(text) => write!(f, "unknown command: {:?}", text),
+            ParseError::Required(what) => write!(f, "{} is required", what),
+            ParseError::InvalidSize(size) => write!(f, "invalid room size: {}", size),
+            ParseError::UnsupportedSize(size) => write!(
+                f,
+                "room size {} is not supported. it should be in range {}-{}",
+                size,
+                game::LOWER_ROOM_SIZE,
+                game::UPPER_ROOM_SIZE,
+            ),
+            ParseError::UnknownOption(option) => write!(f, "unknown option: {}", option),
+            ParseError::MinStartOverSize { min_start, size } => {
+                write!(f, "min start {} is over the room size {}", min_start, size)
+            }
+            ParseError::Expected { what, choices } => write!(f, "{} should be {}", what, choices),
+        }
+    }
+}
+
+impl Fail for ParseError {}
+
+/// Parse a line from the peer, everything after the command name is its
+/// argument.
+pub fn parse(text: &str) -> Result<Command, ParseError> {
+    let m = text.trim();
+    let mut v = m.splitn(2, ' ');
+    let name = v.next().unwrap_or_default();
+    let arg = v.next().map(str::trim).filter(|arg| !arg.is_empty());
+
+    let command = match (name, arg) {
+        ("/list", _) => Command::List,
+        ("/open", _) => Command::Open,
+        ("/games", _) => Command::Games,
+        ("/limits", _) => Command::Limits,
+        ("/load", _) => Command::Load,
+        ("/roster", _) => Command::Roster,
+        ("/help", _) => Command::Help,
+        ("/version", _) => Command::Version,
+        ("/leave", _) => Command::Leave,
+        ("/whoami", _) => Command::WhoAmI,
+        ("/reveal", _) => Command::Reveal,
+        ("/need", _) => Command::Need,
+        ("/addbot", _) => Command::AddBot,
+        ("/code", _) => Command::Code,
+        ("/deck", _) => Command::Deck,
+        ("/rules", _) => Command::Rules,
+        ("/preview", _) => Command::Preview,
+        ("/shuffle", _) => Command::Shuffle,
+        ("/start", _) => Command::Start,
+        ("/abort", _) => Command::Abort,
+        ("/ready", _) => Command::Ready,
+        ("/status", _) => Command::Status,
+        ("/elapsed", _) => Command::Elapsed,
+        ("/stats", _) => Command::Stats,
+        ("/overflow", _) => Command::Overflow,
+        ("/join", Some(room)) => Command::Join {
+            room: room.to_owned(),
+        },
+        ("/watch", Some(room)) => Command::Watch {
+            room: room.to_owned(),
+        },
+        ("/rejoin", Some(room)) => Command::Rejoin {
+            room: room.to_owned(),
+        },
+        ("/reconnect-status", Some(room)) => Command::ReconnectStatus {
+            room: room.to_owned(),
+        },
+        ("/review", Some(room)) => Command::Review {
+            room: room.to_owned(),
+        },
+        ("/join", None)
+        | ("/watch", None)
+        | ("/rejoin", None)
+        | ("/reconnect-status", None)
+        | ("/review", None) => return Err(ParseError::Required("room name")),
+        ("/create", Some(args)) => parse_create(args)?,
+        ("/create", None) | ("/resize", None) => return Err(ParseError::Required("size")),
+        ("/resize", Some(size)) => match size.parse::<u8>() {
+            Ok(size) => Command::Resize { size },
+            _ => return Err(ParseError::InvalidSize(size.to_owned())),
+        },
+        ("/sandbox", Some(size)) => match size.parse::<u8>() {
+            Ok(size) => Command::Sandbox { size },
+            _ => return Err(ParseError::InvalidSize(size.to_owned())),
+        },
+        ("/sandbox", None) => return Err(ParseError::Required("size")),
+        ("/see", Some(seat)) => match seat.parse::<usize>() {
+            Ok(seat) => Command::See { seat },
+            _ => {
+                return Err(ParseError::Expected {
+                    what: "seat",
+                    choices: "a number",
+                })
+            }
+        },
+        ("/see", None) => return Err(ParseError::Required("seat")),
+        ("/pause", _) => Command::Paused(true),
+        ("/resume", _) => Command::Paused(false),
+        ("/revealall", Some("on")) => Command::RevealOnEnd(true),
+        ("/revealall", Some("off")) => Command::RevealOnEnd(false),
+        ("/revealall", _) => {
+            return Err(ParseError::Expected {
+                what: "it",
+                choices: "on or off",
+            })
+        }
+        ("/host", Some(name)) => Command::Host {
+            name: name.to_owned(),
+        },
+        ("/host", None) | ("/name", None) => return Err(ParseError::Required("name")),
+        ("/propose", Some(names)) => Command::Propose {
+            names: names.split_whitespace().map(str::to_owned).collect(),
+        },
+        ("/propose", None) => return Err(ParseError::Required("team")),
+        ("/vote", Some("approve")) => Command::Vote { approve: true },
+        ("/vote", Some("reject")) => Command::Vote { approve: false },
+        ("/vote", _) => {
+            return Err(ParseError::Expected {
+                what: "vote",
+                choices: "approve or reject",
+            })
+        }
+        ("/mission", Some("success")) => Command::Mission { fail: false },
+        ("/mission", Some("fail")) => Command::Mission { fail: true },
+        ("/mission", _) => {
+            return Err(ParseError::Expected {
+                what: "mission card",
+                choices: "success or fail",
+            })
+        }
+        ("/assassinate", Some(name)) => Command::Assassinate {
+            name: name.to_owned(),
+        },
+        ("/assassinate", None) => return Err(ParseError::Required("name")),
+        ("/say", Some(text)) => Command::Say {
+            text: text.to_owned(),
+        },
+        ("/say", None) => return Err(ParseError::Required("text")),
+        ("/mute", Some(name)) => Command::Mute {
+            name: name.to_owned(),
+            muted: true,
+        },
+        ("/unmute", Some(name)) => Command::Mute {
+            name: name.to_owned(),
+            muted: false,
+        },
+        ("/mute", None) | ("/unmute", None) => return Err(ParseError::Required("name")),
+        ("/lake", Some(name)) => Command::Lake {
+            name: name.to_owned(),
+        },
+        ("/lake", None) => return Err(ParseError::Required("name")),
+        ("/protocol", Some("text")) => Command::Protocol(Protocol::Text),
+        ("/protocol", Some("json")) => Command::Protocol(Protocol::Json),
+        ("/protocol", _) => {
+            return Err(ParseError::Expected {
+                what: "protocol",
+                choices: "text or json",
+            })
+        }
+        ("/name", Some(name)) => Command::Name {
+            name: name.to_owned(),
+        },
+        ("/close", Some(room)) => Command::Close {
+            room: room.to_owned(),
+        },
+        ("/close", None) => return Err(ParseError::Required("room name")),
+        ("/auth", Some(token)) => Command::Auth {
+            token: token.to_owned(),
+        },
+        ("/auth", None) => return Err(ParseError::Required("token")),
+        _ => return Err(ParseError::UnknownCommand(m.to_owned())),
+    };
+
+    Ok(command)
+}
+
+/// Arguments of `/create`, the size comes first
+fn parse_create(args: &str) -> Result<Command, ParseError> {
+    let mut args = args.split_whitespace();
+    let size_arg = args.next().unwrap_or_default();
+    let mut options = RoomOptions::default();
+    let mut key = None;
+    for arg in args {
+        match arg.parse::<u64>() {
+            Ok(seed) => options.seed = Some(seed),
+            _ if arg == "shuffle" => options.shuffle_seats = true,
+            _ if arg == "reveal" => options.reveal_on_end = true,
+            _ if arg == "onefail" => options.rules.two_fail_mission_four = false,
+            _ if arg == "lake" => options.rules.lady_of_the_lake = true,
+            _ if arg == "simple" => options.deck.percival_morgana = false,
+            _ if arg == "suffix" => options.suffix_duplicate_names = true,
+            _ if arg == "manual" => options.manual_start = true,
+            _ if arg == "ready" => options.require_ready = true,
+            _ if arg == "secret" => options.secret_votes = true,
+            _ if arg.starts_with("min=") => {
+                let min_start = arg["min=".len()..]
+                    .parse::<usize>()
+                    .map_err(|_| ParseError::UnknownOption(arg.to_owned()))?;
+                if !game::legal_player_counts().contains(&min_start) {
+                    return Err(ParseError::UnsupportedSize(min_start));
+                }
+                options.min_start = Some(min_start as u8);
+            }
+            _ if arg.starts_with("key=") => {
+                key = Some(arg["key=".len()..].to_owned());
+            }
+            _ if arg.starts_with("lang=") => {
+                options.locale = arg["lang=".len()..]
+                    .parse()
+                    .map_err(|_| ParseError::UnknownOption(arg.to_owned()))?;
+            }
+            _ => return Err(ParseError::UnknownOption(arg.to_owned())),
+        }
+    }
+
+    match size_arg.parse::<usize>() {
+        Ok(size) if options.min_start.is_some_and(|min| min as usize > size) => {
+            Err(ParseError::MinStartOverSize {
+                min_start: options.min_start.unwrap_or_default() as usize,
+                size,
+            })
+        }
+        Ok(size) if game::legal_player_counts().contains(&size) => Ok(Command::Create {
+            size: size as u8,
+            options,
+            key,
+        }),
+        Ok(size) => Err(ParseError::UnsupportedSize(size)),
+        _ => Err(ParseError::InvalidSize(size_arg.to_owned())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::game::{DefaultDeckOptions, GameRules};
+    use crate::locale::Locale;
+
+    #[test]
+    fn test_parse_simple() {
+        assert_eq!(parse("/list"), Ok(Command::List));
+        assert_eq!(parse(" /open "), Ok(Command::Open));
+        assert_eq!(parse("/games"), Ok(Command::Games));
+        assert_eq!(parse("/limits"), Ok(Command::Limits));
+        assert_eq!(parse("/load"), Ok(Command::Load));
+        assert_eq!(parse("/roster"), Ok(Command::Roster));
+        assert_eq!(parse("/help"), Ok(Command::Help));
+        assert_eq!(parse("/version"), Ok(Command::Version));
+        assert_eq!(parse("/leave"), Ok(Command::Leave));
+        assert_eq!(parse("/whoami"), Ok(Command::WhoAmI));
+        assert_eq!(parse("/reveal"), Ok(Command::Reveal));
+        assert_eq!(parse("/need"), Ok(Command::Need));
+        assert_eq!(parse("/addbot"), Ok(Command::AddBot));
+        assert_eq!(parse("/code"), Ok(Command::Code));
+        assert_eq!(parse("/deck"), Ok(Command::Deck));
+        assert_eq!(parse("/rules"), Ok(Command::Rules));
+        assert_eq!(parse("/preview"), Ok(Command::Preview));
+        assert_eq!(parse("/shuffle"), Ok(Command::Shuffle));
+        assert_eq!(parse("/start"), Ok(Command::Start));
+        assert_eq!(parse("/abort"), Ok(Command::Abort));
+        assert_eq!(parse("/ready"), Ok(Command::Ready));
+        assert_eq!(parse("/status"), Ok(Command::Status));
+        assert_eq!(parse("/elapsed"), Ok(Command::Elapsed));
+        assert_eq!(parse("/stats"), Ok(Command::Stats));
+        assert_eq!(parse("/overflow"), Ok(Command::Overflow));
+        assert_eq!(
+            parse("/nope"),
+            Err(ParseError::UnknownCommand("/nope".to_owned()))
+        );
+        assert_eq!(
+            parse("hello"),
+            Err(ParseError::UnknownCommand("hello".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_parse_room() {
+        assert_eq!(
+            parse("/join 42"),
+            Ok(Command::Join {
+                room: "42".to_owned()
+            })
+        );
+        assert_eq!(
+            parse("/watch 42"),
+            Ok(Command::Watch {
+                room: "42".to_owned()
+            })
+        );
+        assert_eq!(
+            parse("/review 42"),
+            Ok(Command::Review {
+                room: "42".to_owned()
+            })
+        );
+        assert_eq!(parse("/join"), Err(ParseError::Required("room name")));
+        assert_eq!(parse("/watch "), Err(ParseError::Required("room name")));
+        assert_eq!(parse("/review"), Err(ParseError::Required("room name")));
+        assert_eq!(
+            parse("/rejoin 42"),
+            Ok(Command::Rejoin {
+                room: "42".to_owned()
+            })
+        );
+        assert_eq!(
+            parse("/reconnect-status 42"),
+            Ok(Command::ReconnectStatus {
+                room: "42".to_owned()
+            })
+        );
+        assert_eq!(
+            parse("/reconnect-status"),
+            Err(ParseError::Required("room name"))
+        );
+    }
+
+    #[test]
+    fn test_parse_create() {
+        assert_eq!(
+            parse("/create 5"),
+            Ok(Command::Create {
+                size: 5,
+                options: RoomOptions::default(),
+                key: None,
+            })
+        );
+        assert_eq!(
+            parse("/create 7 42 shuffle reveal onefail lake simple suffix manual ready secret min=6 key=abc lang=en"),
+            Ok(Command::Create {
+                size: 7,
+                options: RoomOptions {
+                    seed: Some(42),
+                    shuffle_seats: true,
+                    reveal_on_end: true,
+                    locale: Locale::En,
+                    rules: GameRules {
+                        two_fail_mission_four: false,
+                        lady_of_the_lake: true,
+                        ..GameRules::default()
+                    },
+                    deck: DefaultDeckOptions {
+                        percival_morgana: false,
+                    },
+                    suffix_duplicate_names: true,
+                    min_start: Some(6),
+                    manual_start: true,
+                    require_ready: true,
+                    secret_votes: true,
+                },
+                key: Some("abc".to_owned()),
+            })
+        );
+        assert_eq!(parse("/create"), Err(ParseError::Required("size")));
+        assert_eq!(parse("/create 11"), Err(ParseError::UnsupportedSize(11)));
+        assert_eq!(
+            parse("/create five"),
+            Err(ParseError::InvalidSize("five".to_owned()))
+        );
+        assert_eq!(
+            parse("/create 5 fast"),
+            Err(ParseError::UnknownOption("fast".to_owned()))
+        );
+        assert_eq!(
+            parse("/create 7 min=8"),
+            Err(ParseError::MinStartOverSize {
+                min_start: 8,
+                size: 7
+            })
+        );
+        assert_eq!(
+            parse("/create 7 min=4"),
+            Err(ParseError::UnsupportedSize(4))
+        );
+        assert_eq!(
+            parse("/create 11").unwrap_err().to_string(),
+            "room size 11 is not supported. it should be in range 5-10"
+        );
+    }
+
+    #[test]
+    fn test_parse_resize() {
+        assert_eq!(parse("/resize 6"), Ok(Command::Resize { size: 6 }));
+        assert_eq!(parse("/resize"), Err(ParseError::Required("size")));
+        assert_eq!(
+            parse("/resize 600"),
+            Err(ParseError::InvalidSize("600".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_parse_sandbox() {
+        assert_eq!(parse("/sandbox 7"), Ok(Command::Sandbox { size: 7 }));
+        assert_eq!(parse("/sandbox"), Err(ParseError::Required("size")));
+        assert_eq!(parse("/see 3"), Ok(Command::See { seat: 3 }));
+        assert_eq!(
+            parse("/see me"),
+            Err(ParseError::Expected {
+                what: "seat",
+                choices: "a number",
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_choices() {
+        assert_eq!(parse("/revealall on"), Ok(Command::RevealOnEnd(true)));
+        assert_eq!(parse("/revealall off"), Ok(Command::RevealOnEnd(false)));
+        assert!(parse("/revealall").is_err());
+        assert_eq!(parse("/pause"), Ok(Command::Paused(true)));
+        assert_eq!(parse("/resume"), Ok(Command::Paused(false)));
+        assert_eq!(parse("/vote approve"), Ok(Command::Vote { approve: true }));
+        assert_eq!(parse("/vote reject"), Ok(Command::Vote { approve: false }));
+        assert_eq!(
+            parse("/vote yes").unwrap_err().to_string(),
+            "vote should be approve or reject"
+        );
+        assert_eq!(
+            parse("/mission success"),
+            Ok(Command::Mission { fail: false })
+        );
+        assert_eq!(parse("/mission fail"), Ok(Command::Mission { fail: true }));
+        assert!(parse("/mission").is_err());
+        assert_eq!(
+            parse("/protocol json"),
+            Ok(Command::Protocol(Protocol::Json))
+        );
+        assert_eq!(
+            parse("/protocol text"),
+            Ok(Command::Protocol(Protocol::Text))
+        );
+        assert!(parse("/protocol xml").is_err());
+    }
+
+    #[test]
+    fn test_parse_names() {
+        assert_eq!(
+            parse("/name 小明"),
+            Ok(Command::Name {
+                name: "小明".to_owned()
+            })
+        );
+        assert_eq!(parse("/name"), Err(ParseError::Required("name")));
+        assert_eq!(
+            parse("/host guest"),
+            Ok(Command::Host {
+                name: "guest".to_owned()
+            })
+        );
+        assert_eq!(parse("/host"), Err(ParseError::Required("name")));
+        assert_eq!(
+            parse("/assassinate a"),
+            Ok(Command::Assassinate {
+                name: "a".to_owned()
+            })
+        );
+        assert_eq!(parse("/assassinate"), Err(ParseError::Required("name")));
+        assert_eq!(
+            parse("/say hello  there"),
+            Ok(Command::Say {
+                text: "hello  there".to_owned()
+            })
+        );
+        assert_eq!(parse("/say"), Err(ParseError::Required("text")));
+        assert_eq!(
+            parse("/mute a"),
+            Ok(Command::Mute {
+                name: "a".to_owned(),
+                muted: true
+            })
+        );
+        assert_eq!(
+            parse("/unmute a"),
+            Ok(Command::Mute {
+                name: "a".to_owned(),
+                muted: false
+            })
+        );
+        assert_eq!(
+            parse("/lake a"),
+            Ok(Command::Lake {
+                name: "a".to_owned()
+            })
+        );
+        assert_eq!(
+            parse("/propose a  b"),
+            Ok(Command::Propose {
+                names: vec!["a".to_owned(), "b".to_owned()]
+            })
+        );
+        assert_eq!(parse("/propose"), Err(ParseError::Required("team")));
+    }
+
+    #[test]
+    fn test_parse_admin() {
+        assert_eq!(
+            parse("/close 42"),
+            Ok(Command::Close {
+                room: "42".to_owned()
+            })
+        );
+        assert_eq!(parse("/close"), Err(ParseError::Required("room name")));
+        assert_eq!(
+            parse("/auth secret"),
+            Ok(Command::Auth {
+                token: "secret".to_owned()
+            })
+        );
+        assert_eq!(parse("/auth"), Err(ParseError::Required("token")));
+    }
+}
@@ -1,11 +1,20 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
 use std::fmt::{self, Display};
 use std::iter::Iterator;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
 
-use failure::{format_err, Error};
+use failure::{format_err, Error, Fail};
 use itertools::join;
 use rand::prelude::*;
+use rand::rngs::StdRng;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+use crate::locale::Locale;
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(into = "&'static str", try_from = "String")]
 pub enum Role {
     Assassin,
     Merlin,
@@ -14,9 +23,11 @@ pub enum Role {
     Oberon,
     Percival,
     Loyal,
+    Cleric,
 }
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Alliance {
     Resistance,
     Spy,
@@ -25,21 +36,210 @@ pub enum Alliance {
 use self::Alliance::*;
 use self::Role::*;
 
+#[derive(Debug)]
+pub enum GameError {
+    InvalidPlayerNumber(usize),
+    RoleCountMismatch {
+        roles: usize,
+        players: usize,
+    },
+    UnbalancedRoleSet {
+        spies: usize,
+        players: usize,
+        expected: usize,
+    },
+    InvalidEvilCount {
+        count: usize,
+        players: usize,
+    },
+    /// An Assassin has no Merlin to assassinate
+    AssassinWithoutMerlin,
+    /// A Percival has neither Merlin nor Morgana to see
+    PercivalWithoutTargets,
+    /// Nobody plays for the spies
+    NoSpies,
+    /// Nobody plays for the resistance
+    NoResistance,
+    /// The spies are as many as the resistance or more
+    SpyMajority {
+        spies: usize,
+        players: usize,
+    },
+    /// The action isn't allowed in the current phase
+    WrongPhase,
+    NotLeader,
+    WrongTeamSize {
+        size: usize,
+        expected: usize,
+    },
+    InvalidSeat(usize),
+    DuplicatedSeat(usize),
+    AlreadyVoted,
+    NotOnTeam,
+    /// Only spies may fail a mission
+    CannotFail,
+    /// No action is taken while the game is paused
+    Paused,
+    /// Only the assassin may name merlin
+    NotAssassin,
+    NotLadyHolder,
+    /// The lady of the lake can't go back to a former holder
+    CannotInspect(usize),
+    /// A saved game breaks the rules of play, e.g. a seat out of the deal
+    CorruptSave(&'static str),
+}
+
+impl Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameError::InvalidPlayerNumber(number) => {
+                write!(f, "invalid player number: {}", number)
+            }
+            GameError::RoleCountMismatch { roles, players } => {
+                write!(f, "role set has {} roles for {} players", roles, players)
+            }
+            GameError::UnbalancedRoleSet {
+                spies,
+                players,
+                expected,
+            } => write!(
+                f,
+                "unbalanced role set: {} spies for {} players, expected {}",
+                spies, players, expected
+            ),
+            GameError::InvalidEvilCount { count, players } => write!(
+                f,
+                "invalid evil count: {} for {} players, it should be in range 1-{}",
+                count,
+                players,
+                players - 1
+            ),
+            GameError::AssassinWithoutMerlin => {
+                write!(f, "role set has an assassin but no merlin to assassinate")
+            }
+            GameError::PercivalWithoutTargets => {
+                write!(f, "role set has a percival but no merlin or morgana to see")
+            }
+            GameError::NoSpies => write!(f, "role set has no spies"),
+            GameError::NoResistance => write!(f, "role set has no resistance"),
+            GameError::SpyMajority { spies, players } => write!(
+                f,
+                "role set has {} spies for {} players, they can't be half or more",
+                spies, players
+            ),
+            GameError::WrongPhase => write!(f, "not allowed now"),
+            GameError::NotLeader => write!(f, "you are not the leader"),
+            GameError::WrongTeamSize { size, expected } => write!(
+                f,
+                "team has {} players, the mission needs {}",
+                size, expected
+            ),
+            GameError::InvalidSeat(seat) => write!(f, "invalid seat: {}", seat + 1),
+            GameError::DuplicatedSeat(seat) => {
+                write!(f, "seat {} is proposed more than once", seat + 1)
+            }
+            GameError::AlreadyVoted => write!(f, "you already voted"),
+            GameError::NotOnTeam => write!(f, "you are not on the team"),
+            GameError::CannotFail => write!(f, "only spies can fail a mission"),
+            GameError::Paused => write!(f, "game is paused"),
+            GameError::NotAssassin => write!(f, "only the assassin can assassinate"),
+            GameError::NotLadyHolder => write!(f, "you don't hold the lady of the lake"),
+            GameError::CannotInspect(seat) => write!(f, "seat {} can't be inspected", seat + 1),
+            GameError::CorruptSave(reason) => write!(f, "corrupt saved game: {}", reason),
+        }
+    }
+}
+
+impl Fail for GameError {}
+
 impl Display for Role {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.name())
     }
 }
 
+impl FromStr for Role {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Role, Error> {
+        all_roles()
+            .iter()
+            .find(|role| role.ident() == s)
+            .copied()
+            .ok_or_else(|| format_err!("unknown role: {}", s))
+    }
+}
+
+impl TryFrom<String> for Role {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Role, Error> {
+        s.parse()
+    }
+}
+
+impl From<Role> for &'static str {
+    fn from(role: Role) -> &'static str {
+        role.ident()
+    }
+}
+
+impl Display for Alliance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.localized_name(Locale::default()))
+    }
+}
+
+impl Alliance {
+    pub fn localized_name(self, locale: Locale) -> &'static str {
+        match (self, locale) {
+            (Resistance, Locale::Zh) => "好人",
+            (Spy, Locale::Zh) => "坏人",
+            (Resistance, Locale::En) => "Resistance",
+            (Spy, Locale::En) => "Evil",
+        }
+    }
+}
+
 impl Role {
     pub fn alliance(self) -> Alliance {
         match self {
-            Merlin | Percival | Loyal => Resistance,
+            Merlin | Percival | Loyal | Cleric => Resistance,
             Assassin | Mordred | Morgana | Oberon => Spy,
         }
     }
 
-    fn name(self) -> &'static str {
+    /// Stable ASCII identifier, for logs, configs and JSON
+    pub fn ident(self) -> &'static str {
+        match self {
+            Assassin => "assassin",
+            Merlin => "merlin",
+            Mordred => "mordred",
+            Morgana => "morgana",
+            Oberon => "oberon",
+            Percival => "percival",
+            Loyal => "loyal",
+            Cleric => "cleric",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        self.localized_name(Locale::default())
+    }
+
+    pub fn localized_name(self, locale: Locale) -> &'static str {
+        if locale == Locale::En {
+            return match self {
+                Assassin => "Assassin",
+                Merlin => "Merlin",
+                Mordred => "Mordred",
+                Morgana => "Morgana",
+                Oberon => "Oberon",
+                Percival => "Percival",
+                Loyal => "Loyal Servant",
+                Cleric => "Cleric",
+            };
+        }
         match self {
             Assassin => "刺客",
             Merlin => "梅林",
@@ -48,37 +248,175 @@ impl Role {
             Oberon => "奥伯伦",
             Percival => "派西维尔",
             Loyal => "忠臣",
+            Cleric => "牧师",
         }
     }
+
+    /// What the role knows and does, for a rules reference
+    pub fn description(self) -> &'static str {
+        self.localized_description(Locale::default())
+    }
+
+    pub fn localized_description(self, locale: Locale) -> &'static str {
+        if locale == Locale::En {
+            return match self {
+                Assassin => "Evil, knows the other evil players (except Oberon), may assassinate Merlin once the good win three missions",
+                Merlin => "Good, knows the evil players (except Mordred), the good lose if Merlin is assassinated",
+                Mordred => "Evil, knows the other evil players (except Oberon), hidden from Merlin",
+                Morgana => "Evil, knows the other evil players (except Oberon), looks like Merlin to Percival",
+                Oberon => "Evil, doesn't know the other evil players, and they don't know him",
+                Percival => "Good, knows who Merlin and Morgana are, but can't tell them apart",
+                Loyal => "Good, has no information",
+                Cleric => "Good, knows the alliance of the first leader, but not the role",
+            };
+        }
+        match self {
+            Assassin => "坏人，知道其他坏人（奥伯伦除外），好人赢下三个任务后可以刺杀梅林",
+            Merlin => "好人，知道所有坏人（莫德雷德除外），被刺杀则好人输",
+            Mordred => "坏人，知道其他坏人（奥伯伦除外），梅林看不到他",
+            Morgana => "坏人，知道其他坏人（奥伯伦除外），在派西维尔眼里像梅林",
+            Oberon => "坏人，不知道其他坏人，其他坏人也不知道他",
+            Percival => "好人，知道梅林和莫甘娜是谁，但分不清",
+            Loyal => "好人，没有任何信息",
+            Cleric => "好人，知道第一个队长的阵营，但不知道他的身份",
+        }
+    }
+
+    pub fn info(self) -> RoleInfo {
+        RoleInfo {
+            role: self,
+            name: self.name(),
+            alliance: self.alliance(),
+            description: self.description(),
+        }
+    }
+}
+
+/// Every role there is
+pub fn all_roles() -> &'static [Role] {
+    &[
+        Merlin, Percival, Loyal, Cleric, Assassin, Morgana, Mordred, Oberon,
+    ]
 }
 
+/// Metadata of a role, independent of any assignment
+#[derive(Clone, Copy, PartialEq, Debug, Serialize)]
+pub struct RoleInfo {
+    pub role: Role,
+    pub name: &'static str,
+    pub alliance: Alliance,
+    pub description: &'static str,
+}
+
+/// What a role sees at the start, serialized with a `kind` tag, e.g.
+/// `{"kind": "merlin", "resistance": [{"seat": 0, "name": "a"}], "spies": [...]}`
 #[derive(Clone, Debug)]
 pub enum SeeingBy {
     Normal,
     Spy(Vec<(usize, String)>),
     Merlin(Vec<(usize, String)>, Vec<(usize, String)>),
     Percival(Vec<(usize, String)>),
+    /// Alliance of the first leader
+    Cleric(Alliance),
+}
+
+#[derive(Serialize)]
+struct SeenPlayer<'a> {
+    seat: usize,
+    name: &'a str,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum SeeingByRepr<'a> {
+    Normal,
+    Spy {
+        spies: Vec<SeenPlayer<'a>>,
+    },
+    Merlin {
+        resistance: Vec<SeenPlayer<'a>>,
+        spies: Vec<SeenPlayer<'a>>,
+    },
+    Percival {
+        candidates: Vec<SeenPlayer<'a>>,
+    },
+    Cleric {
+        first_leader: Alliance,
+    },
+}
+
+impl Serialize for SeeingBy {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        fn seen(players: &[(usize, String)]) -> Vec<SeenPlayer<'_>> {
+            players
+                .iter()
+                .map(|(seat, name)| SeenPlayer { seat: *seat, name })
+                .collect()
+        }
+
+        let repr = match self {
+            SeeingBy::Normal => SeeingByRepr::Normal,
+            SeeingBy::Spy(spies) => SeeingByRepr::Spy { spies: seen(spies) },
+            SeeingBy::Merlin(resistance, spies) => SeeingByRepr::Merlin {
+                resistance: seen(resistance),
+                spies: seen(spies),
+            },
+            SeeingBy::Percival(candidates) => SeeingByRepr::Percival {
+                candidates: seen(candidates),
+            },
+            SeeingBy::Cleric(alliance) => SeeingByRepr::Cleric {
+                first_leader: *alliance,
+            },
+        };
+        repr.serialize(serializer)
+    }
 }
 
 impl SeeingBy {
     pub fn text(&self) -> String {
-        self.text_with_formatter(|&(_, ref name)| name.clone())
+        self.text_with_formatter(Locale::default(), |(_, name)| name.clone())
     }
 
     pub fn text_from_player(&self, id: usize) -> String {
-        self.text_with_formatter(move |&(i, ref name)| {
+        self.localized_text_from_player(id, Locale::default())
+    }
+
+    pub fn localized_text_from_player(&self, id: usize, locale: Locale) -> String {
+        let you = match locale {
+            Locale::Zh => "你",
+            Locale::En => "you",
+        };
+        self.text_with_formatter(locale, move |&(i, ref name)| {
             if i == id {
-                "你".to_owned()
+                you.to_owned()
             } else {
                 name.clone()
             }
         })
     }
 
-    fn text_with_formatter<F>(&self, f: F) -> String
+    fn text_with_formatter<F>(&self, locale: Locale, f: F) -> String
     where
         F: FnMut(&(usize, String)) -> String + Copy,
     {
+        if locale == Locale::En {
+            return match self {
+                SeeingBy::Normal => "".to_owned(),
+                SeeingBy::Spy(spies) => format!("{} are evil", join(spies.iter().map(f), ", ")),
+                SeeingBy::Merlin(resistances, spies) => format!(
+                    "{} are good\n{} are evil",
+                    join(resistances.iter().map(f), ", "),
+                    join(spies.iter().map(f), ", "),
+                ),
+                SeeingBy::Percival(merlin_list) => format!(
+                    "one of {} is Merlin, the other is Morgana",
+                    join(merlin_list.iter().map(f), " and "),
+                ),
+                SeeingBy::Cleric(alliance) => {
+                    format!("the first leader is {}", alliance.localized_name(locale))
+                }
+            };
+        }
         match self {
             SeeingBy::Normal => "".to_owned(),
             SeeingBy::Spy(spies) => format!("{} 都是坏人", join(spies.iter().map(f), "、")),
@@ -91,10 +429,22 @@ impl SeeingBy {
                 "{} 当中有一个是梅林，另一个是莫甘娜",
                 join(merlin_list.iter().map(f), " 和 "),
             ),
+            SeeingBy::Cleric(alliance) => format!("第一个队长是{}", alliance),
         }
     }
 }
 
+/// What a single seat is allowed to know, safe to send to that seat
+#[derive(Clone, Debug, Serialize)]
+pub struct PlayerView {
+    pub seat: usize,
+    pub name: String,
+    pub role: Role,
+    pub alliance: Alliance,
+    pub seeing: SeeingBy,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Assignment {
     pub players: Vec<(String, Role)>,
 }
@@ -103,9 +453,69 @@ impl Assignment {
     pub fn new<T>(names: T) -> Result<Assignment, Error>
     where
         T: Iterator<Item = String>,
+    {
+        Assignment::with_seed(names, None)
+    }
+
+    /// Same as `new`, but the deal is reproducible if `seed` is given.
+    pub fn with_seed<T>(names: T, seed: Option<u64>) -> Result<Assignment, Error>
+    where
+        T: Iterator<Item = String>,
+    {
+        let names_array: Vec<_> = names.collect();
+        let roles = match seed {
+            Some(seed) => deal_with_seed(names_array.len(), seed)?,
+            None => deal(names_array.len())?,
+        };
+
+        Ok(Assignment {
+            players: names_array.into_iter().zip(roles).collect(),
+        })
+    }
+
+    /// Seat `roles` onto `names` in the given order, without any shuffle.
+    /// The roles must make a legal deck for that many players.
+    pub fn from_exact(names: Vec<String>, roles: Vec<Role>) -> Result<Assignment, Error> {
+        let role_set = RoleSet::new(roles);
+        role_set.validate(names.len(), &GameRules::default())?;
+
+        Ok(Assignment {
+            players: names.into_iter().zip(role_set.roles).collect(),
+        })
+    }
+
+    /// Deal `role_set` onto `names`, shuffled by `seed` if given, or randomly
+    /// otherwise.
+    pub fn with_role_set<T>(
+        names: T,
+        role_set: &RoleSet,
+        rules: &GameRules,
+        seed: Option<u64>,
+    ) -> Result<Assignment, Error>
+    where
+        T: Iterator<Item = String>,
+    {
+        match seed {
+            Some(seed) => {
+                Assignment::with_rng(names, role_set, rules, &mut StdRng::seed_from_u64(seed))
+            }
+            None => Assignment::with_rng(names, role_set, rules, &mut rand::thread_rng()),
+        }
+    }
+
+    /// Deal `role_set` onto `names`, shuffled by `rng`.
+    pub fn with_rng<T, R>(
+        names: T,
+        role_set: &RoleSet,
+        rules: &GameRules,
+        rng: &mut R,
+    ) -> Result<Assignment, Error>
+    where
+        T: Iterator<Item = String>,
+        R: Rng + ?Sized,
     {
         let names_array: Vec<_> = names.collect();
-        let roles = deal(names_array.len())?;
+        let roles = role_set.deal(names_array.len(), rules, rng)?;
 
         Ok(Assignment {
             players: names_array.into_iter().zip(roles).collect(),
@@ -122,6 +532,47 @@ impl Assignment {
             .map(|&(ref name, role)| (name.as_ref(), role))
     }
 
+    /// Seat of the first player holding `role`
+    pub fn seat_of(&self, role: Role) -> Option<usize> {
+        self.players.iter().position(|&(_, r)| r == role)
+    }
+
+    pub fn merlin_seat(&self) -> Option<usize> {
+        self.seat_of(Merlin)
+    }
+
+    pub fn assassin_seat(&self) -> Option<usize> {
+        self.seat_of(Assassin)
+    }
+
+    /// Own role of `seat` and what it sees, other roles are left out.
+    pub fn view_for(&self, seat: usize) -> Option<PlayerView> {
+        self.get_player(seat).map(|(name, role)| PlayerView {
+            seat,
+            name: name.to_owned(),
+            role,
+            alliance: role.alliance(),
+            seeing: self.see_from_role(role),
+        })
+    }
+
+    /// Seats `seat` knows to be evil, itself left out. Only spies and
+    /// Merlin know any, Percival's candidates are not telling.
+    pub fn suspected_evil_for(&self, seat: usize) -> Vec<usize> {
+        let spies = match self
+            .get_player(seat)
+            .map(|(_, role)| self.see_from_role(role))
+        {
+            Some(SeeingBy::Spy(spies)) | Some(SeeingBy::Merlin(_, spies)) => spies,
+            _ => Vec::new(),
+        };
+        spies
+            .into_iter()
+            .map(|(i, _)| i)
+            .filter(|&i| i != seat)
+            .collect()
+    }
+
     pub fn see_from_role(&self, role: Role) -> SeeingBy {
         match role {
             Assassin | Morgana | Mordred => {
@@ -135,10 +586,13 @@ impl Assignment {
 
                 SeeingBy::Merlin(resistances, spies)
             }
-            Percival => SeeingBy::Percival(self.filter_players(|role| match role {
-                Merlin | Morgana => true,
-                _ => false,
-            })),
+            Percival => {
+                SeeingBy::Percival(self.filter_players(|role| matches!(role, Merlin | Morgana)))
+            }
+            Cleric => match self.players.get(FIRST_LEADER) {
+                Some(&(_, leader)) => SeeingBy::Cleric(leader.alliance()),
+                None => SeeingBy::Normal,
+            },
             Oberon | Loyal => SeeingBy::Normal,
         }
     }
@@ -161,36 +615,1633 @@ impl Assignment {
     }
 }
 
+/// Seat leading the first proposal
+pub const FIRST_LEADER: usize = 0;
+
 pub const LOWER_ROOM_SIZE: usize = 5;
 pub const UPPER_ROOM_SIZE: usize = ROLES.len();
 
-const ROLES: &'static [Role] = &[
+/// Player counts a game can be dealt for
+pub fn legal_player_counts() -> RangeInclusive<usize> {
+    LOWER_ROOM_SIZE..=UPPER_ROOM_SIZE
+}
+
+const ROLES: &[Role] = &[
     Merlin, Assassin, Percival, Morgana, Loyal, Loyal, Oberon, Loyal, Loyal, Mordred,
 ];
 
+/// Choices over the default deck of small games
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DefaultDeckOptions {
+    /// Deal percival and morgana to games of 5 or 6, otherwise they are
+    /// replaced by a loyal servant and oberon, whom merlin still sees
+    pub percival_morgana: bool,
+}
+
+impl Default for DefaultDeckOptions {
+    fn default() -> DefaultDeckOptions {
+        DefaultDeckOptions {
+            percival_morgana: true,
+        }
+    }
+}
+
+/// Number of spies a balanced game of `players` has.
+pub fn expected_evil(players: usize) -> Result<usize, Error> {
+    match players {
+        5 | 6 => Ok(2),
+        7..=9 => Ok(3),
+        10 => Ok(4),
+        _ => Err(GameError::InvalidPlayerNumber(players).into()),
+    }
+}
+
+/// House rules a table plays with, the standard ones by default.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameRules {
+    /// Number of spies, overriding the standard one
+    pub evil_count: Option<usize>,
+    /// The 4th mission needs two fails in games of 7 or more
+    pub two_fail_mission_four: bool,
+    /// The lady of the lake inspects a player after the 2nd, 3rd and 4th
+    /// missions
+    pub lady_of_the_lake: bool,
+}
+
+impl Default for GameRules {
+    fn default() -> GameRules {
+        GameRules {
+            evil_count: None,
+            two_fail_mission_four: true,
+            lady_of_the_lake: false,
+        }
+    }
+}
+
+impl GameRules {
+    /// Number of spies a game of `players` has under these rules.
+    pub fn expected_evil(&self, players: usize) -> Result<usize, Error> {
+        let standard = expected_evil(players)?;
+        match self.evil_count {
+            Some(count) if count == 0 || count >= players => {
+                Err(GameError::InvalidEvilCount { count, players }.into())
+            }
+            Some(count) => Ok(count),
+            None => Ok(standard),
+        }
+    }
+
+    /// Number of fail cards which sabotage the mission of `index`, 0-4.
+    pub fn fails_required(&self, players: usize, index: usize) -> usize {
+        if self.two_fail_mission_four && index == 3 && players >= 7 {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Fails required by each mission for `players`, pairs with
+    /// `mission_sizes`.
+    pub fn fails_schedule(&self, players: usize) -> Result<[usize; 5], Error> {
+        mission_sizes(players)?;
+        let mut schedule = [0; 5];
+        for (index, fails) in schedule.iter_mut().enumerate() {
+            *fails = self.fails_required(players, index);
+        }
+
+        Ok(schedule)
+    }
+}
+
+/// Something legal but likely unintended about a deck, up to the host.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RoleSetWarning {
+    /// Percival sees only one of Merlin and Morgana
+    SinglePercivalCandidate,
+}
+
+impl Display for RoleSetWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RoleSetWarning::SinglePercivalCandidate => {
+                write!(
+                    f,
+                    "percival sees a single candidate, who is no longer ambiguous"
+                )
+            }
+        }
+    }
+}
+
+/// How far a deck leans to the spies, advice for the host apart from the hard
+/// checks of `is_playable`
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BalanceSeverity {
+    /// No more spies than the standard deck
+    Ok,
+    /// More spies than the standard deck, still a minority
+    Tight,
+    /// No spies, or no fewer spies than resistance
+    Illegal,
+}
+
+/// Alliance counts of a deck and how they balance
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BalanceReport {
+    pub resistance: usize,
+    pub spies: usize,
+    pub severity: BalanceSeverity,
+}
+
+/// A deck of roles to be dealt, one per player.
+#[derive(Clone, PartialEq, Debug)]
+pub struct RoleSet {
+    roles: Vec<Role>,
+}
+
+impl RoleSet {
+    pub fn new(roles: Vec<Role>) -> RoleSet {
+        RoleSet { roles }
+    }
+
+    /// The default deck for `players`.
+    pub fn standard(players: usize) -> Result<RoleSet, Error> {
+        RoleSet::standard_with(players, &DefaultDeckOptions::default())
+    }
+
+    /// The default deck for `players`, tuned by `options`.
+    pub fn standard_with(players: usize, options: &DefaultDeckOptions) -> Result<RoleSet, Error> {
+        if !legal_player_counts().contains(&players) {
+            return Err(GameError::InvalidPlayerNumber(players).into());
+        }
+
+        let simple = !options.percival_morgana && players <= 6;
+        let roles = ROLES[..players]
+            .iter()
+            .map(|&role| match role {
+                Percival if simple => Loyal,
+                Morgana if simple => Oberon,
+                role => role,
+            })
+            .collect();
+        Ok(RoleSet::new(roles))
+    }
+
+    pub fn roles(&self) -> &[Role] {
+        &self.roles
+    }
+
+    /// Roles in the deck with their counts, nothing about who holds them
+    pub fn composition(&self, locale: Locale) -> String {
+        let counts = all_roles().iter().filter_map(|&role| {
+            match self.roles.iter().filter(|&&r| r == role).count() {
+                0 => None,
+                1 => Some(role.localized_name(locale).to_owned()),
+                count => Some(format!("{}x {}", count, role.localized_name(locale))),
+            }
+        });
+        match locale {
+            Locale::Zh => join(counts, "、"),
+            Locale::En => join(counts, ", "),
+        }
+    }
+
+    /// Check the deck can be dealt to `players`, with the number of spies
+    /// `rules` expects.
+    pub fn validate(&self, players: usize, rules: &GameRules) -> Result<(), Error> {
+        let expected = rules.expected_evil(players)?;
+        if self.roles.len() != players {
+            return Err(GameError::RoleCountMismatch {
+                roles: self.roles.len(),
+                players,
+            }
+            .into());
+        }
+        let spies = self
+            .roles
+            .iter()
+            .filter(|role| role.alliance() == Spy)
+            .count();
+        if spies != expected {
+            return Err(GameError::UnbalancedRoleSet {
+                spies,
+                players,
+                expected,
+            }
+            .into());
+        }
+        let has = |role| self.roles.contains(&role);
+        if has(Assassin) && !has(Merlin) {
+            return Err(GameError::AssassinWithoutMerlin.into());
+        }
+        if has(Percival) && !has(Merlin) && !has(Morgana) {
+            return Err(GameError::PercivalWithoutTargets.into());
+        }
+
+        Ok(())
+    }
+
+    /// Check the deck makes a winnable game for both sides, whatever the
+    /// house rules.
+    pub fn is_playable(&self, players: usize) -> Result<(), GameError> {
+        if self.roles.len() != players {
+            return Err(GameError::RoleCountMismatch {
+                roles: self.roles.len(),
+                players,
+            });
+        }
+        let spies = self
+            .roles
+            .iter()
+            .filter(|role| role.alliance() == Spy)
+            .count();
+        if spies == 0 {
+            return Err(GameError::NoSpies);
+        }
+        if spies == players {
+            return Err(GameError::NoResistance);
+        }
+        if spies * 2 >= players {
+            return Err(GameError::SpyMajority { spies, players });
+        }
+
+        Ok(())
+    }
+
+    /// Count both alliances of the deck, and compare the spies with the
+    /// standard deck for `players`.
+    pub fn balance_report(&self, players: usize) -> BalanceReport {
+        let spies = self
+            .roles
+            .iter()
+            .filter(|role| role.alliance() == Spy)
+            .count();
+        let resistance = self.roles.len() - spies;
+        let severity = if spies == 0 || spies >= resistance {
+            BalanceSeverity::Illegal
+        } else if expected_evil(players).is_ok_and(|expected| spies > expected) {
+            BalanceSeverity::Tight
+        } else {
+            BalanceSeverity::Ok
+        };
+
+        BalanceReport {
+            resistance,
+            spies,
+            severity,
+        }
+    }
+
+    /// Dubious but playable traits of the deck, empty for a sound one.
+    pub fn warnings(&self) -> Vec<RoleSetWarning> {
+        let mut warnings = Vec::new();
+        let candidates = self
+            .roles
+            .iter()
+            .filter(|&&role| role == Merlin || role == Morgana)
+            .count();
+        if self.roles.contains(&Percival) && candidates == 1 {
+            warnings.push(RoleSetWarning::SinglePercivalCandidate);
+        }
+        warnings
+    }
+
+    /// Validate the deck and shuffle it with `rng`.
+    pub fn deal<R: Rng + ?Sized>(
+        &self,
+        players: usize,
+        rules: &GameRules,
+        rng: &mut R,
+    ) -> Result<Vec<Role>, Error> {
+        self.validate(players, rules)?;
+        let mut roles = self.roles.clone();
+        roles.shuffle(rng);
+
+        Ok(roles)
+    }
+}
+
 pub fn deal(number: usize) -> Result<Vec<Role>, Error> {
-    if number < LOWER_ROOM_SIZE || number > UPPER_ROOM_SIZE {
-        return Err(format_err!("invalid player number: {}", number));
+    deal_with_rng(number, &mut rand::thread_rng())
+}
+
+/// Same as `deal`, but the shuffle is reproducible from `seed`.
+pub fn deal_with_seed(number: usize, seed: u64) -> Result<Vec<Role>, Error> {
+    deal_with_rng(number, &mut StdRng::seed_from_u64(seed))
+}
+
+/// Same as `deal`, but shuffled by `rng`.
+pub fn deal_with_rng<R: Rng + ?Sized>(number: usize, rng: &mut R) -> Result<Vec<Role>, Error> {
+    RoleSet::standard(number)?.deal(number, &GameRules::default(), rng)
+}
+
+/// Consecutive rejected proposals which hand the game to the spies
+pub const MAX_REJECTS: usize = 5;
+
+/// Team size of each mission for `players`.
+pub fn mission_sizes(players: usize) -> Result<[usize; 5], Error> {
+    match players {
+        5 => Ok([2, 3, 2, 3, 3]),
+        6 => Ok([2, 3, 4, 3, 4]),
+        7 => Ok([2, 3, 3, 4, 4]),
+        8..=10 => Ok([3, 4, 4, 5, 5]),
+        _ => Err(GameError::InvalidPlayerNumber(players).into()),
     }
-    let mut roles = (&ROLES[..number]).to_owned();
-    let mut rng = rand::thread_rng();
-    roles.shuffle(&mut rng);
+}
 
-    Ok(roles)
+/// The mission of `index`, 0-4, with its team size, e.g. `第 3 个任务需要 2 人`
+/// or `mission 3 needs 2 players`.
+pub fn localized_mission(index: usize, team_size: usize, locale: Locale) -> String {
+    match locale {
+        Locale::Zh => format!("第 {} 个任务需要 {} 人", index + 1, team_size),
+        Locale::En => format!("mission {} needs {} players", index + 1, team_size),
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Number of fail cards which sabotage the mission of `index`, 0-4.
+///
+/// The 4th mission needs two fails in games of 7 or more.
+pub fn fails_required(players: usize, index: usize) -> usize {
+    GameRules::default().fails_required(players, index)
+}
 
-    #[test]
-    fn test_join() {
-        let str_list = &["hello", "world"];
-        assert_eq!("hello world".to_owned(), join(str_list, " "));
+/// Fails required by each mission for `players`, pairs with
+/// `mission_sizes`.
+pub fn fails_schedule(players: usize) -> Result<[usize; 5], Error> {
+    GameRules::default().fails_schedule(players)
+}
 
-        assert_eq!(
-            "hello world".to_owned(),
-            join(&vec!["hello".to_owned(), "world".to_owned()], " "),
+/// Missions won by an alliance to win the game
+pub const MISSIONS_TO_WIN: usize = 3;
+
+/// Approvals a team needs among `players` votes, a strict majority.
+pub fn approvals_needed(players: usize) -> usize {
+    players / 2 + 1
+}
+
+/// Whether `approvals` out of `players` votes send the team on the mission.
+pub fn is_team_approved(approvals: usize, players: usize) -> bool {
+    approvals >= approvals_needed(players)
+}
+
+/// Whether more than half of `votes` approve.
+pub fn majority(votes: &[bool]) -> bool {
+    let approvals = votes.iter().filter(|&&approve| approve).count();
+    is_team_approved(approvals, votes.len())
+}
+
+/// Where the game is at
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Phase {
+    /// The leader picks a team
+    Propose,
+    /// Everyone votes on the proposed team
+    Vote,
+    /// The approved team is on the mission
+    Mission,
+    /// The lady of the lake holder inspects a player before the proposal
+    Inspect,
+    /// The resistance passed its missions, the assassin names a merlin
+    Assassinate,
+    /// The game is won by the alliance
+    Over(Alliance),
+}
+
+/// Result of a finished team vote
+#[derive(Clone, PartialEq, Debug)]
+pub struct VoteTally {
+    pub approvals: usize,
+    pub rejections: usize,
+    /// Vote of each seat, true for approve
+    pub votes: Vec<bool>,
+}
+
+impl VoteTally {
+    pub fn approved(&self) -> bool {
+        is_team_approved(self.approvals, self.approvals + self.rejections)
+    }
+}
+
+/// Result of a finished mission, without who played which card
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MissionResult {
+    pub successes: usize,
+    pub fails: usize,
+    pub passed: bool,
+}
+
+/// A team put to the vote
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Proposal {
+    /// Index of the mission, 0-4
+    pub mission: usize,
+    pub leader: usize,
+    pub team: Vec<usize>,
+    pub approved: bool,
+}
+
+/// State of a game in play, seats are indices of the assignment.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Game {
+    assignment: Assignment,
+    phase: Phase,
+    leader: usize,
+    /// Index of the current mission, 0-4
+    mission: usize,
+    /// Proposed team of the current round
+    team: Vec<usize>,
+    votes: BTreeMap<usize, bool>,
+    /// Mission cards of the team, true for fail
+    cards: BTreeMap<usize, bool>,
+    /// Consecutive rejected proposals
+    reject_streak: usize,
+    /// All rejected proposals of the game
+    rejected_votes: usize,
+    /// Whether each finished mission passed
+    quests: Vec<bool>,
+    /// Voted proposals, in order
+    proposals: Vec<Proposal>,
+    /// Actions are rejected while paused
+    #[serde(default)]
+    paused: bool,
+    #[serde(default)]
+    rules: GameRules,
+    /// Seat holding the lady of the lake, if played
+    #[serde(default)]
+    lady: Option<usize>,
+    /// Seats which held the lady of the lake, the current one included
+    #[serde(default)]
+    lady_holders: Vec<usize>,
+}
+
+impl Game {
+    /// Start a game from the first seat as the leader.
+    pub fn new(assignment: Assignment) -> Result<Game, Error> {
+        Game::with_rules(assignment, GameRules::default())
+    }
+
+    /// Same as `new`, but played with house `rules`.
+    pub fn with_rules(assignment: Assignment, rules: GameRules) -> Result<Game, Error> {
+        mission_sizes(assignment.player_number())?;
+        // the lady of the lake starts on the right of the first leader
+        let lady = if rules.lady_of_the_lake {
+            Some((FIRST_LEADER + assignment.player_number() - 1) % assignment.player_number())
+        } else {
+            None
+        };
+
+        Ok(Game {
+            assignment,
+            phase: Phase::Propose,
+            leader: FIRST_LEADER,
+            mission: 0,
+            team: Vec::new(),
+            votes: BTreeMap::new(),
+            cards: BTreeMap::new(),
+            reject_streak: 0,
+            rejected_votes: 0,
+            quests: Vec::new(),
+            proposals: Vec::new(),
+            paused: false,
+            rules,
+            lady,
+            lady_holders: lady.into_iter().collect(),
+        })
+    }
+
+    /// Restore a game saved by `to_serialized`, a save which couldn't come
+    /// out of play is refused.
+    pub fn from_serialized(data: &str) -> Result<Game, Error> {
+        let game: Game = serde_json::from_str(data)?;
+        game.validate()?;
+        Ok(game)
+    }
+
+    /// Check the invariants kept by play, so that a restored game can't
+    /// index out of its seats or missions
+    fn validate(&self) -> Result<(), GameError> {
+        let players = self.assignment.player_number();
+        let sizes = mission_sizes(players)
+            .map_err(|_| GameError::CorruptSave("unsupported player number"))?;
+        let seat_ok = |seat: &usize| *seat < players;
+        if !seat_ok(&self.leader) {
+            return Err(GameError::CorruptSave("leader out of the seats"));
+        }
+        if self.mission >= sizes.len() {
+            return Err(GameError::CorruptSave("mission out of range"));
+        }
+        let distinct = |team: &[usize]| team.iter().collect::<BTreeSet<_>>().len() == team.len();
+        if !self.team.iter().all(seat_ok) || !distinct(&self.team) {
+            return Err(GameError::CorruptSave("team seat out of the seats"));
+        }
+        if !self.votes.keys().all(seat_ok)
+            || !self.cards.keys().all(|seat| self.team.contains(seat))
+        {
+            return Err(GameError::CorruptSave("vote of a seat out of the seats"));
+        }
+        let proposal_ok = |proposal: &Proposal| {
+            proposal.mission < sizes.len()
+                && seat_ok(&proposal.leader)
+                && proposal.team.iter().all(seat_ok)
+        };
+        if !self.proposals.iter().all(proposal_ok) {
+            return Err(GameError::CorruptSave("proposal out of the seats"));
+        }
+        if !self.lady.iter().chain(&self.lady_holders).all(seat_ok) {
+            return Err(GameError::CorruptSave("lady holder out of the seats"));
+        }
+
+        let won = |passed| self.quests.iter().filter(|&&p| p == passed).count();
+        let consistent = match self.phase {
+            // a mission is played after the others finished
+            Phase::Propose | Phase::Inspect => {
+                self.quests.len() == self.mission && self.team.is_empty()
+            }
+            Phase::Vote | Phase::Mission => {
+                self.quests.len() == self.mission && self.team.len() == sizes[self.mission]
+            }
+            Phase::Assassinate => {
+                self.quests.len() == self.mission + 1 && won(true) >= MISSIONS_TO_WIN
+            }
+            // over on the last mission, or on rejected proposals
+            Phase::Over(_) => {
+                self.quests.len() == self.mission || self.quests.len() == self.mission + 1
+            }
+        };
+        let playing = !matches!(self.phase, Phase::Assassinate | Phase::Over(_));
+        if !consistent
+            || (playing && (won(true) >= MISSIONS_TO_WIN || won(false) >= MISSIONS_TO_WIN))
+        {
+            return Err(GameError::CorruptSave("phase doesn't match the missions"));
+        }
+        Ok(())
+    }
+
+    /// Save the whole state, to be restored by `from_serialized`.
+    pub fn to_serialized(&self) -> Result<String, Error> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn assignment(&self) -> &Assignment {
+        &self.assignment
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    pub fn leader(&self) -> usize {
+        self.leader
+    }
+
+    pub fn mission_index(&self) -> usize {
+        self.mission
+    }
+
+    pub fn reject_streak(&self) -> usize {
+        self.reject_streak
+    }
+
+    pub fn rejected_votes(&self) -> usize {
+        self.rejected_votes
+    }
+
+    pub fn team(&self) -> &[usize] {
+        &self.team
+    }
+
+    /// Results of finished missions, true for passed
+    pub fn quests(&self) -> &[bool] {
+        &self.quests
+    }
+
+    pub fn proposals(&self) -> &[Proposal] {
+        &self.proposals
+    }
+
+    pub fn rules(&self) -> &GameRules {
+        &self.rules
+    }
+
+    pub fn lady_holder(&self) -> Option<usize> {
+        self.lady
+    }
+
+    /// Seats which held the lady of the lake, they can't be inspected
+    pub fn lady_holders(&self) -> &[usize] {
+        &self.lady_holders
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Pause or resume the game, a game over can't be paused.
+    pub fn set_paused(&mut self, paused: bool) -> Result<(), Error> {
+        if let Phase::Over(_) = self.phase {
+            return Err(GameError::WrongPhase.into());
+        }
+        self.paused = paused;
+
+        Ok(())
+    }
+
+    /// One line summary for a status line, e.g.
+    /// `✔ ✘ ✔ · · leader: c, mission: 4, rejects: 0`
+    pub fn scoreboard(&self) -> String {
+        let quests: Vec<&str> = (0..5)
+            .map(|index| match self.quests.get(index) {
+                Some(true) => "✔",
+                Some(false) => "✘",
+                None => "·",
+            })
+            .collect();
+        let leader = self
+            .assignment
+            .get_player(self.leader)
+            .map(|(name, _)| name)
+            .unwrap_or_default();
+        format!(
+            "{} leader: {}, mission: {}, rejects: {}",
+            quests.join(" "),
+            leader,
+            self.mission + 1,
+            self.reject_streak
+        )
+    }
+
+    /// Team size the current mission needs
+    pub fn team_size(&self) -> usize {
+        mission_sizes(self.assignment.player_number())
+            .map(|sizes| sizes[self.mission])
+            .unwrap_or_default()
+    }
+
+    /// The leader in `seat` puts `team` to the vote.
+    pub fn propose_team(&mut self, seat: usize, team: Vec<usize>) -> Result<(), Error> {
+        if self.paused {
+            return Err(GameError::Paused.into());
+        }
+        if self.phase != Phase::Propose {
+            return Err(GameError::WrongPhase.into());
+        }
+        if seat != self.leader {
+            return Err(GameError::NotLeader.into());
+        }
+        if team.len() != self.team_size() {
+            return Err(GameError::WrongTeamSize {
+                size: team.len(),
+                expected: self.team_size(),
+            }
+            .into());
+        }
+        for (i, &member) in team.iter().enumerate() {
+            if member >= self.assignment.player_number() {
+                return Err(GameError::InvalidSeat(member).into());
+            }
+            if team[..i].contains(&member) {
+                return Err(GameError::DuplicatedSeat(member).into());
+            }
+        }
+
+        self.team = team;
+        self.votes.clear();
+        self.phase = Phase::Vote;
+        Ok(())
+    }
+
+    /// Record the vote of `seat`, the tally is returned once everyone voted.
+    ///
+    /// An approved team goes on the mission, otherwise the next seat leads,
+    /// and the spies win after `MAX_REJECTS` rejections in a row.
+    pub fn cast_vote(&mut self, seat: usize, approve: bool) -> Result<Option<VoteTally>, Error> {
+        if self.paused {
+            return Err(GameError::Paused.into());
+        }
+        if self.phase != Phase::Vote {
+            return Err(GameError::WrongPhase.into());
+        }
+        if seat >= self.assignment.player_number() {
+            return Err(GameError::InvalidSeat(seat).into());
+        }
+        if self.votes.contains_key(&seat) {
+            return Err(GameError::AlreadyVoted.into());
+        }
+        self.votes.insert(seat, approve);
+        if self.votes.len() < self.assignment.player_number() {
+            return Ok(None);
+        }
+
+        let votes: Vec<bool> = self.votes.values().cloned().collect();
+        let approvals = votes.iter().filter(|&&approve| approve).count();
+        let tally = VoteTally {
+            approvals,
+            rejections: votes.len() - approvals,
+            votes: votes.clone(),
+        };
+        self.proposals.push(Proposal {
+            mission: self.mission,
+            leader: self.leader,
+            team: self.team.clone(),
+            approved: majority(&votes),
+        });
+        if majority(&votes) {
+            self.reject_streak = 0;
+            self.cards.clear();
+            self.phase = Phase::Mission;
+        } else {
+            self.reject_streak += 1;
+            self.rejected_votes += 1;
+            self.team.clear();
+            if self.reject_streak >= MAX_REJECTS {
+                self.phase = Phase::Over(Spy);
+            } else {
+                self.next_leader();
+                self.phase = Phase::Propose;
+            }
+        }
+
+        Ok(Some(tally))
+    }
+
+    /// Record the mission card of `seat`, the result is returned once the
+    /// whole team played.
+    pub fn submit_mission(
+        &mut self,
+        seat: usize,
+        fail: bool,
+    ) -> Result<Option<MissionResult>, Error> {
+        if self.paused {
+            return Err(GameError::Paused.into());
+        }
+        if self.phase != Phase::Mission {
+            return Err(GameError::WrongPhase.into());
+        }
+        if !self.team.contains(&seat) {
+            return Err(GameError::NotOnTeam.into());
+        }
+        if self.cards.contains_key(&seat) {
+            return Err(GameError::AlreadyVoted.into());
+        }
+        let is_spy = self
+            .assignment
+            .get_player(seat)
+            .map(|(_, role)| role.alliance() == Spy)
+            .unwrap_or_default();
+        if fail && !is_spy {
+            return Err(GameError::CannotFail.into());
+        }
+        self.cards.insert(seat, fail);
+        if self.cards.len() < self.team.len() {
+            return Ok(None);
+        }
+
+        let fails = self.cards.values().filter(|&&fail| fail).count();
+        let passed = fails
+            < self
+                .rules
+                .fails_required(self.assignment.player_number(), self.mission);
+        let result = MissionResult {
+            successes: self.cards.len() - fails,
+            fails,
+            passed,
+        };
+        self.quests.push(passed);
+        self.cards.clear();
+        self.team.clear();
+
+        let won = |alliance_passed| {
+            self.quests
+                .iter()
+                .filter(|&&p| p == alliance_passed)
+                .count()
+        };
+        if won(true) >= MISSIONS_TO_WIN {
+            let has_target = self.assignment.assassin_seat().is_some()
+                && self.assignment.merlin_seat().is_some();
+            self.phase = if has_target {
+                Phase::Assassinate
+            } else {
+                Phase::Over(Resistance)
+            };
+        } else if won(false) >= MISSIONS_TO_WIN {
+            self.phase = Phase::Over(Spy);
+        } else {
+            // the lady of the lake is used after the 2nd to the 4th missions
+            let inspect = self.lady.is_some() && (1..=3).contains(&self.mission);
+            self.mission += 1;
+            self.next_leader();
+            self.phase = if inspect {
+                Phase::Inspect
+            } else {
+                Phase::Propose
+            };
+        }
+
+        Ok(Some(result))
+    }
+
+    /// Whether `seat` may name merlin now, it must hold the assassin.
+    pub fn assassin_may_act(&self, seat: usize) -> bool {
+        self.phase == Phase::Assassinate && self.assignment.assassin_seat() == Some(seat)
+    }
+
+    /// The assassin in `seat` names `target` as merlin, the winner is
+    /// returned: the spies if right, the resistance otherwise.
+    pub fn assassinate(&mut self, seat: usize, target: usize) -> Result<Alliance, Error> {
+        if self.paused {
+            return Err(GameError::Paused.into());
+        }
+        if self.phase != Phase::Assassinate {
+            return Err(GameError::WrongPhase.into());
+        }
+        if !self.assassin_may_act(seat) {
+            return Err(GameError::NotAssassin.into());
+        }
+        let winner = match self.assignment.get_player(target) {
+            Some((_, Merlin)) => Spy,
+            Some(_) => Resistance,
+            None => return Err(GameError::InvalidSeat(target).into()),
+        };
+        self.phase = Phase::Over(winner);
+
+        Ok(winner)
+    }
+
+    /// The lady of the lake holder in `seat` learns the alliance of `target`,
+    /// who holds the lady next.
+    pub fn inspect_loyalty(&mut self, seat: usize, target: usize) -> Result<Alliance, Error> {
+        if self.paused {
+            return Err(GameError::Paused.into());
+        }
+        if self.phase != Phase::Inspect {
+            return Err(GameError::WrongPhase.into());
+        }
+        if self.lady != Some(seat) {
+            return Err(GameError::NotLadyHolder.into());
+        }
+        let alliance = match self.assignment.get_player(target) {
+            Some((_, role)) => role.alliance(),
+            None => return Err(GameError::InvalidSeat(target).into()),
+        };
+        if self.lady_holders.contains(&target) {
+            return Err(GameError::CannotInspect(target).into());
+        }
+        self.lady = Some(target);
+        self.lady_holders.push(target);
+        self.phase = Phase::Propose;
+
+        Ok(alliance)
+    }
+
+    fn next_leader(&mut self) {
+        self.leader = (self.leader + 1) % self.assignment.player_number();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::rngs::mock::StepRng;
+
+    #[test]
+    #[allow(clippy::needless_borrows_for_generic_args)]
+    fn test_join() {
+        let str_list = &["hello", "world"];
+        assert_eq!("hello world".to_owned(), join(str_list, " "));
+
+        assert_eq!(
+            "hello world".to_owned(),
+            join(&vec!["hello".to_owned(), "world".to_owned()], " "),
+        );
+    }
+
+    #[test]
+    fn test_alliance_name() {
+        assert_eq!(Resistance.to_string(), "好人");
+        assert_eq!(Spy.to_string(), "坏人");
+        assert_eq!(Resistance.localized_name(Locale::Zh), "好人");
+        assert_eq!(Spy.localized_name(Locale::Zh), "坏人");
+        assert_eq!(Resistance.localized_name(Locale::En), "Resistance");
+        assert_eq!(Spy.localized_name(Locale::En), "Evil");
+    }
+
+    #[test]
+    fn test_localized_mission() {
+        assert_eq!(localized_mission(2, 2, Locale::Zh), "第 3 个任务需要 2 人");
+        assert_eq!(
+            localized_mission(2, 2, Locale::En),
+            "mission 3 needs 2 players"
+        );
+    }
+
+    #[test]
+    fn test_legal_player_counts() {
+        assert_eq!(legal_player_counts(), 5..=10);
+        assert!(legal_player_counts().all(|players| RoleSet::standard(players).is_ok()));
+        assert!(RoleSet::standard(4).is_err());
+        assert!(RoleSet::standard(11).is_err());
+    }
+
+    #[test]
+    fn test_deal_with_seed() {
+        assert_eq!(
+            deal_with_seed(7, 42).unwrap(),
+            deal_with_seed(7, 42).unwrap()
+        );
+        assert!(deal_with_seed(4, 42).is_err());
+    }
+
+    #[test]
+    fn test_deal_with_rng() {
+        // always picking the first card rotates the deck by one
+        let mut rng = StepRng::new(0, 0);
+        assert_eq!(
+            deal_with_rng(5, &mut rng).unwrap(),
+            vec![Assassin, Percival, Morgana, Loyal, Merlin]
+        );
+    }
+
+    #[test]
+    fn test_from_exact() {
+        let names: Vec<String> = ["a", "b", "c", "d", "e"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        let assignment = Assignment::from_exact(
+            names.clone(),
+            vec![Merlin, Assassin, Loyal, Morgana, Percival],
+        )
+        .unwrap();
+        assert_eq!(assignment, five_players());
+
+        let err = Assignment::from_exact(names.clone(), vec![Merlin, Assassin, Loyal, Morgana])
+            .unwrap_err();
+        match err.downcast_ref::<GameError>() {
+            Some(GameError::RoleCountMismatch {
+                roles: 4,
+                players: 5,
+            }) => {}
+            _ => panic!("unexpected error: {}", err),
+        }
+
+        assert!(
+            Assignment::from_exact(names, vec![Merlin, Loyal, Loyal, Loyal, Assassin]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_validate_role_set() {
+        let rules = GameRules::default();
+        assert!(RoleSet::standard(8).unwrap().validate(8, &rules).is_ok());
+        assert!(RoleSet::new(vec![Merlin, Loyal, Loyal, Loyal, Assassin])
+            .validate(5, &rules)
+            .is_err());
+        assert!(RoleSet::standard(5).unwrap().validate(6, &rules).is_err());
+    }
+
+    #[test]
+    fn test_is_playable() {
+        assert!(RoleSet::standard(7).unwrap().is_playable(7).is_ok());
+        assert!(matches!(
+            RoleSet::new(vec![Merlin, Percival, Loyal, Loyal, Loyal]).is_playable(5),
+            Err(GameError::NoSpies)
+        ));
+        assert!(matches!(
+            RoleSet::new(vec![Merlin, Loyal, Assassin, Morgana, Mordred]).is_playable(5),
+            Err(GameError::SpyMajority {
+                spies: 3,
+                players: 5
+            })
+        ));
+        assert!(matches!(
+            RoleSet::new(vec![Assassin, Morgana, Mordred, Oberon, Assassin]).is_playable(5),
+            Err(GameError::NoResistance)
+        ));
+        assert!(matches!(
+            RoleSet::standard(5).unwrap().is_playable(6),
+            Err(GameError::RoleCountMismatch {
+                roles: 5,
+                players: 6
+            })
+        ));
+    }
+
+    #[test]
+    fn test_balance_report() {
+        assert_eq!(
+            RoleSet::standard(7).unwrap().balance_report(7),
+            BalanceReport {
+                resistance: 4,
+                spies: 3,
+                severity: BalanceSeverity::Ok,
+            }
+        );
+        let near_even = RoleSet::new(vec![
+            Merlin, Percival, Loyal, Loyal, Loyal, Assassin, Morgana, Mordred, Oberon,
+        ]);
+        assert_eq!(
+            near_even.balance_report(9),
+            BalanceReport {
+                resistance: 5,
+                spies: 4,
+                severity: BalanceSeverity::Tight,
+            }
+        );
+        let evil_majority = RoleSet::new(vec![Merlin, Loyal, Assassin, Morgana, Mordred]);
+        assert_eq!(
+            evil_majority.balance_report(5).severity,
+            BalanceSeverity::Illegal
+        );
+    }
+
+    #[test]
+    fn test_validate_role_set_semantics() {
+        let rules = GameRules::default();
+        let err = RoleSet::new(vec![Loyal, Loyal, Loyal, Assassin, Morgana])
+            .validate(5, &rules)
+            .unwrap_err();
+        match err.downcast_ref::<GameError>() {
+            Some(GameError::AssassinWithoutMerlin) => {}
+            _ => panic!("unexpected error: {}", err),
+        }
+
+        let err = RoleSet::new(vec![Percival, Loyal, Loyal, Mordred, Oberon])
+            .validate(5, &rules)
+            .unwrap_err();
+        match err.downcast_ref::<GameError>() {
+            Some(GameError::PercivalWithoutTargets) => {}
+            _ => panic!("unexpected error: {}", err),
+        }
+
+        assert!(
+            RoleSet::new(vec![Merlin, Percival, Loyal, Assassin, Mordred])
+                .validate(5, &rules)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_standard_without_percival_morgana() {
+        let options = DefaultDeckOptions {
+            percival_morgana: false,
+        };
+        let rules = GameRules::default();
+        for players in 5..=6 {
+            let role_set = RoleSet::standard_with(players, &options).unwrap();
+            assert!(!role_set.roles().contains(&Percival));
+            assert!(!role_set.roles().contains(&Morgana));
+            assert!(role_set.validate(players, &rules).is_ok());
+            assert!(role_set.warnings().is_empty());
+        }
+        assert_eq!(
+            RoleSet::standard_with(5, &options).unwrap().roles(),
+            &[Merlin, Assassin, Loyal, Oberon, Loyal]
+        );
+        // larger games keep the pair
+        assert_eq!(
+            RoleSet::standard_with(7, &options).unwrap(),
+            RoleSet::standard(7).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_composition() {
+        let role_set = RoleSet::standard(7).unwrap();
+        assert_eq!(
+            role_set.composition(Locale::En),
+            "Merlin, Percival, 2x Loyal Servant, Assassin, Morgana, Oberon"
+        );
+        assert_eq!(
+            role_set.composition(Locale::Zh),
+            "梅林、派西维尔、2x 忠臣、刺客、莫甘娜、奥伯伦"
+        );
+    }
+
+    #[test]
+    fn test_role_set_warnings() {
+        let role_set = RoleSet::new(vec![Merlin, Percival, Loyal, Assassin, Mordred]);
+        assert!(role_set.validate(5, &GameRules::default()).is_ok());
+        assert_eq!(
+            role_set.warnings(),
+            vec![RoleSetWarning::SinglePercivalCandidate]
+        );
+        assert!(RoleSet::standard(5).unwrap().warnings().is_empty());
+    }
+
+    #[test]
+    fn test_evil_count_override() {
+        let role_set = RoleSet::new(vec![
+            Merlin, Percival, Loyal, Loyal, Assassin, Morgana, Mordred, Oberon,
+        ]);
+        let rules = GameRules {
+            evil_count: Some(4),
+            ..GameRules::default()
+        };
+        assert!(role_set.validate(8, &rules).is_ok());
+        assert!(role_set.validate(8, &GameRules::default()).is_err());
+
+        let rules = GameRules {
+            evil_count: Some(0),
+            ..GameRules::default()
+        };
+        assert!(rules.expected_evil(8).is_err());
+        assert!(role_set.validate(8, &rules).is_err());
+    }
+
+    #[test]
+    fn test_cleric() {
+        let mut assignment = Assignment {
+            players: vec![
+                ("a".to_owned(), Assassin),
+                ("b".to_owned(), Merlin),
+                ("c".to_owned(), Cleric),
+                ("d".to_owned(), Morgana),
+                ("e".to_owned(), Percival),
+            ],
+        };
+        let game = Game::new(assignment.clone()).unwrap();
+        assert_eq!(game.leader(), FIRST_LEADER);
+        assert_eq!(assignment.see_from_role(Cleric).text(), "第一个队长是坏人");
+        assert_eq!(
+            assignment
+                .see_from_role(Cleric)
+                .localized_text_from_player(2, Locale::En),
+            "the first leader is Evil"
+        );
+
+        assignment.players.swap(0, 1);
+        assert_eq!(assignment.see_from_role(Cleric).text(), "第一个队长是好人");
+    }
+
+    #[test]
+    fn test_view_for() {
+        let assignment = Assignment {
+            players: vec![
+                ("a".to_owned(), Merlin),
+                ("b".to_owned(), Assassin),
+                ("c".to_owned(), Loyal),
+                ("d".to_owned(), Morgana),
+                ("e".to_owned(), Percival),
+            ],
+        };
+
+        let loyal = assignment.view_for(2).unwrap();
+        assert_eq!(loyal.role, Loyal);
+        assert_eq!(loyal.alliance, Resistance);
+        assert!(matches!(loyal.seeing, SeeingBy::Normal));
+
+        let merlin = assignment.view_for(0).unwrap();
+        match merlin.seeing {
+            SeeingBy::Merlin(_, spies) => {
+                assert_eq!(spies, vec![(1, "b".to_owned()), (3, "d".to_owned())])
+            }
+            seeing => panic!("unexpected {:?}", seeing),
+        }
+
+        assert!(assignment.view_for(5).is_none());
+    }
+
+    #[test]
+    fn test_suspected_evil_for() {
+        let names = ["a", "b", "c", "d", "e", "f", "g"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        let assignment = Assignment::from_exact(
+            names,
+            vec![Merlin, Assassin, Loyal, Mordred, Percival, Loyal, Morgana],
+        )
+        .unwrap();
+
+        // mordred in seat 3 is hidden from merlin
+        assert_eq!(assignment.suspected_evil_for(0), vec![1, 6]);
+        assert_eq!(assignment.suspected_evil_for(1), vec![3, 6]);
+        assert!(assignment.suspected_evil_for(2).is_empty());
+        assert!(assignment.suspected_evil_for(4).is_empty());
+        assert!(assignment.suspected_evil_for(7).is_empty());
+    }
+
+    #[test]
+    fn test_seeing_by_json() {
+        let assignment = five_players();
+        assert_eq!(
+            serde_json::to_value(assignment.see_from_role(Merlin)).unwrap(),
+            serde_json::json!({
+                "kind": "merlin",
+                "resistance": [
+                    {"seat": 0, "name": "a"},
+                    {"seat": 2, "name": "c"},
+                    {"seat": 4, "name": "e"},
+                ],
+                "spies": [
+                    {"seat": 1, "name": "b"},
+                    {"seat": 3, "name": "d"},
+                ],
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(assignment.see_from_role(Loyal)).unwrap(),
+            serde_json::json!({"kind": "normal"})
+        );
+    }
+
+    #[test]
+    fn test_role_seats() {
+        let names = ["a", "b", "c", "d", "e", "f", "g"];
+        let players = names
+            .iter()
+            .map(|name| name.to_string())
+            .zip(deal_with_seed(7, 42).unwrap())
+            .collect();
+        let assignment = Assignment { players };
+
+        let merlin = assignment.merlin_seat().unwrap();
+        assert_eq!(assignment.get_player(merlin).unwrap().1, Merlin);
+        let assassin = assignment.assassin_seat().unwrap();
+        assert_eq!(assignment.get_player(assassin).unwrap().1, Assassin);
+
+        let assignment = Assignment {
+            players: vec![("a".to_owned(), Loyal), ("b".to_owned(), Morgana)],
+        };
+        assert_eq!(assignment.merlin_seat(), None);
+        assert_eq!(assignment.assassin_seat(), None);
+    }
+
+    #[test]
+    fn test_deal_invariants() {
+        for players in legal_player_counts() {
+            let evil = expected_evil(players).unwrap();
+            for seed in 0..200 {
+                let roles = deal_with_seed(players, seed).unwrap();
+                assert_eq!(roles.len(), players);
+                assert_eq!(
+                    roles.iter().filter(|role| role.alliance() == Spy).count(),
+                    evil,
+                    "{} players, seed {}",
+                    players,
+                    seed
+                );
+                for special in &[Merlin, Assassin, Percival, Morgana, Oberon, Mordred] {
+                    assert!(roles.iter().filter(|&role| role == special).count() <= 1);
+                }
+            }
+        }
+    }
+
+    fn five_players() -> Assignment {
+        let names = ["a", "b", "c", "d", "e"];
+        let players = names
+            .iter()
+            .map(|name| name.to_string())
+            .zip(vec![Merlin, Assassin, Loyal, Morgana, Percival])
+            .collect();
+        Assignment { players }
+    }
+
+    #[test]
+    fn test_majority() {
+        assert!(majority(&[true, true, true, false, false]));
+        assert!(!majority(&[true, true, false, false]));
+        assert!(!majority(&[]));
+    }
+
+    #[test]
+    fn test_approvals_needed() {
+        assert_eq!(approvals_needed(5), 3);
+        assert_eq!(approvals_needed(10), 6);
+        assert!(is_team_approved(3, 5));
+        assert!(!is_team_approved(2, 5));
+        assert!(!is_team_approved(5, 10));
+        assert!(is_team_approved(6, 10));
+    }
+
+    #[test]
+    fn test_propose_and_vote() {
+        let mut game = Game::new(five_players()).unwrap();
+        assert_eq!(game.team_size(), 2);
+        assert!(game.propose_team(1, vec![0, 1]).is_err());
+        assert!(game.propose_team(0, vec![0, 1, 2]).is_err());
+        assert!(game.propose_team(0, vec![0, 0]).is_err());
+        assert!(game.propose_team(0, vec![0, 5]).is_err());
+        game.propose_team(0, vec![0, 1]).unwrap();
+        assert_eq!(game.phase(), Phase::Vote);
+
+        for seat in 0..4 {
+            assert_eq!(game.cast_vote(seat, seat < 2).unwrap(), None);
+        }
+        assert!(game.cast_vote(3, true).is_err());
+        let tally = game.cast_vote(4, false).unwrap().unwrap();
+        assert!(!tally.approved());
+        assert_eq!(game.reject_streak(), 1);
+        assert_eq!(game.leader(), 1);
+        assert_eq!(game.phase(), Phase::Propose);
+
+        game.propose_team(1, vec![1, 2]).unwrap();
+        for seat in 0..5 {
+            game.cast_vote(seat, true).unwrap();
+        }
+        assert_eq!(game.reject_streak(), 0);
+        assert_eq!(game.phase(), Phase::Mission);
+    }
+
+    #[test]
+    fn test_five_rejects() {
+        let mut game = Game::new(five_players()).unwrap();
+        for round in 0..MAX_REJECTS {
+            let leader = game.leader();
+            game.propose_team(leader, vec![0, 1]).unwrap();
+            for seat in 0..5 {
+                game.cast_vote(seat, false).unwrap();
+            }
+            assert_eq!(game.reject_streak(), round + 1);
+        }
+        assert_eq!(game.rejected_votes(), MAX_REJECTS);
+        assert_eq!(game.phase(), Phase::Over(Spy));
+    }
+
+    #[test]
+    fn test_pause() {
+        let mut game = Game::new(five_players()).unwrap();
+        game.propose_team(0, vec![0, 1]).unwrap();
+        game.set_paused(true).unwrap();
+        assert!(game.is_paused());
+        assert_eq!(
+            game.cast_vote(1, true).unwrap_err().to_string(),
+            "game is paused"
+        );
+
+        game.set_paused(false).unwrap();
+        assert_eq!(game.cast_vote(1, true).unwrap(), None);
+    }
+
+    /// Approve a team of `team` by everyone
+    fn approve(game: &mut Game, team: Vec<usize>) {
+        let leader = game.leader();
+        game.propose_team(leader, team).unwrap();
+        for seat in 0..game.assignment().player_number() {
+            game.cast_vote(seat, true).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_fails_required() {
+        assert_eq!(fails_required(5, 3), 1);
+        assert_eq!(fails_required(7, 3), 2);
+        assert_eq!(fails_required(7, 2), 1);
+    }
+
+    #[test]
+    fn test_fails_schedule() {
+        assert_eq!(fails_schedule(5).unwrap(), [1, 1, 1, 1, 1]);
+        assert_eq!(fails_schedule(9).unwrap(), [1, 1, 1, 2, 1]);
+        assert!(fails_schedule(4).is_err());
+    }
+
+    #[test]
+    fn test_two_fail_rule() {
+        let standard = GameRules::default();
+        assert_eq!(standard.fails_required(8, 3), 2);
+        assert_eq!(standard.fails_schedule(8).unwrap(), [1, 1, 1, 2, 1]);
+
+        let rules = GameRules {
+            two_fail_mission_four: false,
+            ..GameRules::default()
+        };
+        assert_eq!(rules.fails_required(8, 3), 1);
+        assert_eq!(rules.fails_schedule(8).unwrap(), [1, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_mission() {
+        // a: Merlin, b: Assassin, c: Loyal
+        let mut game = Game::new(five_players()).unwrap();
+        approve(&mut game, vec![0, 2]);
+        assert!(game.submit_mission(1, false).is_err());
+        assert!(game.submit_mission(0, true).is_err());
+        assert_eq!(game.submit_mission(0, false).unwrap(), None);
+        let result = game.submit_mission(2, false).unwrap().unwrap();
+        assert_eq!(
+            result,
+            MissionResult {
+                successes: 2,
+                fails: 0,
+                passed: true
+            }
+        );
+        assert_eq!(game.mission_index(), 1);
+        assert_eq!(game.phase(), Phase::Propose);
+
+        approve(&mut game, vec![0, 1, 2]);
+        game.submit_mission(0, false).unwrap();
+        game.submit_mission(1, true).unwrap();
+        let result = game.submit_mission(2, false).unwrap().unwrap();
+        assert!(!result.passed);
+        assert_eq!(result.fails, 1);
+        assert_eq!(game.quests(), &[true, false]);
+    }
+
+    #[test]
+    fn test_scoreboard() {
+        // a: Merlin, b: Assassin, c: Loyal
+        let mut game = Game::new(five_players()).unwrap();
+        assert_eq!(
+            game.scoreboard(),
+            "· · · · · leader: a, mission: 1, rejects: 0"
+        );
+        for &fail in &[false, true, false] {
+            let mut team = vec![0, 1, 2];
+            team.truncate(game.team_size());
+            approve(&mut game, team);
+            let team = game.team().to_vec();
+            for seat in team {
+                game.submit_mission(seat, fail && seat == 1).unwrap();
+            }
+        }
+        let leader = game.leader();
+        game.propose_team(leader, vec![0, 2, 4]).unwrap();
+        for seat in 0..5 {
+            game.cast_vote(seat, false).unwrap();
+        }
+        assert_eq!(
+            game.scoreboard(),
+            "✔ ✘ ✔ · · leader: e, mission: 4, rejects: 1"
+        );
+    }
+
+    #[test]
+    fn test_resistance_wins() {
+        let mut game = Game::new(five_players()).unwrap();
+        for _ in 0..MISSIONS_TO_WIN {
+            let mut team = vec![0, 2, 4];
+            team.truncate(game.team_size());
+            approve(&mut game, team);
+            let team = game.team().to_vec();
+            for seat in team {
+                game.submit_mission(seat, false).unwrap();
+            }
+        }
+        assert_eq!(game.phase(), Phase::Assassinate);
+        assert_eq!(game.assassinate(1, 2).unwrap(), Resistance);
+        assert_eq!(game.phase(), Phase::Over(Resistance));
+    }
+
+    #[test]
+    fn test_inspect_loyalty() {
+        // a: Merlin, b: Assassin, c: Loyal, d: Morgana, e: Percival
+        let rules = GameRules {
+            lady_of_the_lake: true,
+            ..GameRules::default()
+        };
+        let mut game = Game::with_rules(five_players(), rules).unwrap();
+        assert_eq!(game.lady_holder(), Some(4));
+        for _ in 0..2 {
+            let mut team = vec![0, 2, 4];
+            team.truncate(game.team_size());
+            approve(&mut game, team);
+            assert_eq!(game.phase(), Phase::Mission);
+            let team = game.team().to_vec();
+            for seat in team {
+                game.submit_mission(seat, false).unwrap();
+            }
+        }
+        assert_eq!(game.phase(), Phase::Inspect);
+        assert!(game.propose_team(game.leader(), vec![0, 2]).is_err());
+
+        let err = game.inspect_loyalty(0, 1).unwrap_err();
+        match err.downcast_ref::<GameError>() {
+            Some(GameError::NotLadyHolder) => {}
+            _ => panic!("unexpected error: {}", err),
+        }
+        assert!(game.inspect_loyalty(4, 4).is_err());
+        assert!(game.inspect_loyalty(4, 5).is_err());
+
+        assert_eq!(game.inspect_loyalty(4, 3).unwrap(), Spy);
+        assert_eq!(game.lady_holder(), Some(3));
+        assert_eq!(game.lady_holders(), &[4, 3]);
+        assert_eq!(game.phase(), Phase::Propose);
+        assert!(game.inspect_loyalty(3, 0).is_err());
+    }
+
+    #[test]
+    fn test_assassinate() {
+        // a: Merlin, b: Assassin
+        let mut game = Game::new(five_players()).unwrap();
+        assert!(!game.assassin_may_act(1));
+        assert!(game.assassinate(1, 0).is_err());
+        game.phase = Phase::Assassinate;
+
+        assert!(!game.assassin_may_act(3));
+        let err = game.assassinate(3, 0).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<GameError>(),
+            Some(GameError::NotAssassin)
+        ));
+        assert_eq!(game.phase(), Phase::Assassinate);
+
+        assert!(game.assassin_may_act(1));
+        assert!(game.assassinate(1, 5).is_err());
+        assert_eq!(game.assassinate(1, 0).unwrap(), Spy);
+        assert_eq!(game.phase(), Phase::Over(Spy));
+    }
+
+    #[test]
+    fn test_all_roles() {
+        // a new role fails to compile here, then fails below until it's
+        // listed in `all_roles`
+        let index = |role| match role {
+            Assassin => 0,
+            Merlin => 1,
+            Mordred => 2,
+            Morgana => 3,
+            Oberon => 4,
+            Percival => 5,
+            Loyal => 6,
+            Cleric => 7,
+        };
+        let mut indices: Vec<usize> = all_roles().iter().map(|&role| index(role)).collect();
+        indices.sort();
+        assert_eq!(indices, (0..8).collect::<Vec<_>>());
+
+        for &role in all_roles() {
+            assert_eq!(role.ident().parse::<Role>().unwrap(), role);
+            assert_eq!(
+                serde_json::to_string(&role).unwrap(),
+                format!("\"{}\"", role.ident())
+            );
+        }
+        assert!("梅林".parse::<Role>().is_err());
+
+        let info = Merlin.info();
+        assert_eq!(info.name, "梅林");
+        assert_eq!(info.alliance, Resistance);
+        assert!(!info.description.is_empty());
+    }
+
+    #[test]
+    fn test_serialize_game() {
+        let mut game = Game::new(five_players()).unwrap();
+        approve(&mut game, vec![0, 2]);
+        game.submit_mission(0, false).unwrap();
+        game.submit_mission(2, false).unwrap();
+        game.propose_team(1, vec![1, 2, 3]).unwrap();
+        for seat in 0..5 {
+            game.cast_vote(seat, false).unwrap();
+        }
+        game.propose_team(2, vec![1, 2, 3]).unwrap();
+        game.cast_vote(0, true).unwrap();
+
+        let data = game.to_serialized().unwrap();
+        let restored = Game::from_serialized(&data).unwrap();
+        assert_eq!(restored, game);
+        assert_eq!(restored.proposals().len(), 2);
+        assert_eq!(restored.quests(), &[true]);
+        assert_eq!(restored.reject_streak(), 1);
+        assert_eq!(restored.phase(), Phase::Vote);
+        assert!(Game::from_serialized("{}").is_err());
+    }
+
+    #[test]
+    fn test_tampered_save() {
+        let mut game = Game::new(five_players()).unwrap();
+        approve(&mut game, vec![0, 2]);
+        let data = game.to_serialized().unwrap();
+        let tampered = |key: &str, value: serde_json::Value| {
+            let mut save: serde_json::Value = serde_json::from_str(&data).unwrap();
+            save[key] = value;
+            Game::from_serialized(&save.to_string())
+                .unwrap_err()
+                .to_string()
+        };
+
+        assert_eq!(
+            tampered("leader", 5.into()),
+            "corrupt saved game: leader out of the seats"
+        );
+        assert_eq!(
+            tampered("mission", 5.into()),
+            "corrupt saved game: mission out of range"
+        );
+        assert_eq!(
+            tampered("team", serde_json::json!([0, 7])),
+            "corrupt saved game: team seat out of the seats"
+        );
+        assert_eq!(
+            tampered(
+                "proposals",
+                serde_json::json!([{ "mission": 0, "leader": 9, "team": [0, 2], "approved": true }])
+            ),
+            "corrupt saved game: proposal out of the seats"
+        );
+        assert_eq!(
+            tampered("quests", serde_json::json!([true])),
+            "corrupt saved game: phase doesn't match the missions"
+        );
+        assert_eq!(
+            tampered("team", serde_json::json!([0])),
+            "corrupt saved game: phase doesn't match the missions"
         );
     }
 }
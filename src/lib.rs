@@ -1,3 +1,10 @@
+pub mod api;
+pub mod command;
 pub mod game;
+pub mod locale;
+pub mod log_context;
+pub mod name;
+pub mod outcome;
+pub mod room_name;
 pub mod server;
 pub mod session;
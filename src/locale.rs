@@ -0,0 +1,47 @@
+//! Languages the dealer can speak.
+
+use std::str::FromStr;
+
+use failure::{format_err, Error};
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Locale {
+    #[default]
+    Zh,
+    En,
+}
+
+impl Locale {
+    /// Code of the locale, as accepted by `from_str`
+    pub fn code(self) -> &'static str {
+        match self {
+            Locale::Zh => "zh",
+            Locale::En => "en",
+        }
+    }
+
+    /// Put between the items of a list, e.g. the names of a team
+    pub fn list_separator(self) -> &'static str {
+        match self {
+            Locale::Zh => "、",
+            Locale::En => ", ",
+        }
+    }
+}
+
+/// Every locale there is
+pub fn all_locales() -> &'static [Locale] {
+    &[Locale::Zh, Locale::En]
+}
+
+impl FromStr for Locale {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Locale, Error> {
+        match s {
+            "zh" => Ok(Locale::Zh),
+            "en" => Ok(Locale::En),
+            _ => Err(format_err!("unknown locale: {}", s)),
+        }
+    }
+}
@@ -0,0 +1,69 @@
+//! Identifiers put in front of log lines, so the lines of a session or a
+//! room can be picked out of a busy log.
+
+use std::fmt::{self, Display};
+
+/// Session id and room name a log line is about, both optional
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct LogContext<'a> {
+    pub session: Option<usize>,
+    pub room: Option<&'a str>,
+}
+
+impl<'a> LogContext<'a> {
+    pub fn session(id: usize) -> LogContext<'a> {
+        LogContext {
+            session: Some(id),
+            room: None,
+        }
+    }
+
+    pub fn room(name: &'a str) -> LogContext<'a> {
+        LogContext {
+            session: None,
+            room: Some(name),
+        }
+    }
+
+    /// Same context, in room `name` too
+    pub fn in_room(self, name: &'a str) -> LogContext<'a> {
+        LogContext {
+            room: Some(name),
+            ..self
+        }
+    }
+}
+
+/// Written as `session=<id> room=<name>`, absent fields are skipped
+impl<'a> Display for LogContext<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut sep = "";
+        if let Some(id) = self.session {
+            write!(f, "session={}", id)?;
+            sep = " ";
+        }
+        if let Some(room) = self.room {
+            write!(f, "{}room={}", sep, room)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(LogContext::session(42).to_string(), "session=42");
+        assert_eq!(LogContext::room("7").to_string(), "room=7");
+        assert_eq!(
+            LogContext::session(42).in_room("7").to_string(),
+            "session=42 room=7"
+        );
+        assert_eq!(
+            format!("{} joined", LogContext::session(42).in_room("7")),
+            "session=42 room=7 joined"
+        );
+    }
+}
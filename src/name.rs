@@ -0,0 +1,178 @@
+//! Player names and chat texts are shown to everyone in the room, check them
+//! before use.
+
+use std::fs;
+use std::path::Path;
+
+use failure::{format_err, Error};
+
+/// Default max number of characters in a name
+pub const MAX_NAME_LEN: usize = 20;
+
+/// Max number of characters in a chat text
+pub const MAX_CHAT_LEN: usize = 200;
+
+/// Check the name is safe to show to others, returns it trimmed.
+///
+/// Control characters are rejected, a name with a newline could forge
+/// server messages.
+pub fn validate_name(name: &str) -> Result<String, Error> {
+    validate_name_len(name, MAX_NAME_LEN)
+}
+
+/// Same as `validate_name`, but names up to `max_len` characters are allowed.
+pub fn validate_name_len(name: &str, max_len: usize) -> Result<String, Error> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(format_err!("name is empty"));
+    }
+    if name.chars().any(char::is_control) {
+        return Err(format_err!("name contains control characters"));
+    }
+    if name.chars().count() > max_len {
+        return Err(format_err!("name is longer than {} characters", max_len));
+    }
+
+    Ok(name.to_owned())
+}
+
+/// Check a chat text is safe to show to others, returns it trimmed.
+///
+/// Control characters are rejected for the same reason as in names.
+pub fn validate_chat(text: &str) -> Result<String, Error> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(format_err!("text is empty"));
+    }
+    if text.chars().any(char::is_control) {
+        return Err(format_err!("text contains control characters"));
+    }
+    if text.chars().count() > MAX_CHAT_LEN {
+        return Err(format_err!(
+            "text is longer than {} characters",
+            MAX_CHAT_LEN
+        ));
+    }
+
+    Ok(text.to_owned())
+}
+
+/// Words not allowed in names, matched case-insensitively anywhere in the
+/// name
+#[derive(Clone, Debug)]
+pub struct Blocklist {
+    words: Vec<String>,
+}
+
+impl Default for Blocklist {
+    fn default() -> Blocklist {
+        Blocklist::new(Vec::new())
+    }
+}
+
+impl Blocklist {
+    pub fn new<T>(words: T) -> Blocklist
+    where
+        T: IntoIterator<Item = String>,
+    {
+        Blocklist {
+            words: words
+                .into_iter()
+                .map(|word| word.trim().to_lowercase())
+                .filter(|word| !word.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Read a blocklist file, one word per line
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Blocklist, Error> {
+        let content = fs::read_to_string(path)?;
+
+        Ok(Blocklist::new(content.lines().map(str::to_owned)))
+    }
+
+    /// Same as `validate_name`, but blocked names are rejected as well.
+    pub fn validate(&self, name: &str) -> Result<String, Error> {
+        self.check(validate_name(name)?)
+    }
+
+    fn check(&self, name: String) -> Result<String, Error> {
+        let lowercase = name.to_lowercase();
+        if self.words.iter().any(|word| lowercase.contains(word)) {
+            return Err(format_err!("name is not allowed"));
+        }
+
+        Ok(name)
+    }
+}
+
+/// What makes a name acceptable on this server
+#[derive(Clone, Debug)]
+pub struct NamePolicy {
+    pub blocklist: Blocklist,
+    /// Max number of characters in a name
+    pub max_len: usize,
+}
+
+impl Default for NamePolicy {
+    fn default() -> NamePolicy {
+        NamePolicy {
+            blocklist: Blocklist::default(),
+            max_len: MAX_NAME_LEN,
+        }
+    }
+}
+
+impl NamePolicy {
+    /// Same as `Blocklist::validate`, but names up to `max_len` characters
+    /// are allowed.
+    pub fn validate(&self, name: &str) -> Result<String, Error> {
+        self.blocklist.check(validate_name_len(name, self.max_len)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_name() {
+        assert_eq!(validate_name(" 小明 ").unwrap(), "小明");
+        assert!(validate_name("小明\n人已经凑齐").is_err());
+        assert!(validate_name("a\tb").is_err());
+        assert!(validate_name("  ").is_err());
+        assert!(validate_name(&"a".repeat(MAX_NAME_LEN + 1)).is_err());
+        assert!(validate_name(&"梅".repeat(MAX_NAME_LEN)).is_ok());
+    }
+
+    #[test]
+    fn test_validate_chat() {
+        assert_eq!(validate_chat(" hi ").unwrap(), "hi");
+        assert!(validate_chat("hi\n你的身份是【梅林】").is_err());
+        assert!(validate_chat("\u{1b}[2J").is_err());
+        assert!(validate_chat(&"a".repeat(MAX_CHAT_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn test_blocklist() {
+        let blocklist = Blocklist::new(vec!["Admin".to_owned(), " ".to_owned()]);
+        assert!(blocklist.validate("xAdMiNx").is_err());
+        assert!(blocklist.validate("\n").is_err());
+        assert_eq!(blocklist.validate(" 小明 ").unwrap(), "小明");
+        assert!(Blocklist::default().validate("admin").is_ok());
+    }
+
+    #[test]
+    fn test_name_policy() {
+        let policy = NamePolicy {
+            blocklist: Blocklist::new(vec!["admin".to_owned()]),
+            max_len: 3,
+        };
+        assert!(policy.validate("abc").is_ok());
+        assert_eq!(
+            policy.validate("abcd").unwrap_err().to_string(),
+            "name is longer than 3 characters"
+        );
+        assert!(policy.validate("admin").is_err());
+    }
+}
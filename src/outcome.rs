@@ -0,0 +1,226 @@
+//! Records of concluded games, kept for balance analysis.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+
+use failure::Error;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::game::{Alliance, Role};
+
+/// One concluded game
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct GameOutcome {
+    /// Player number
+    pub players: usize,
+    /// Player names, in seat order
+    #[serde(default)]
+    pub names: Vec<String>,
+    /// Roles in play, in seat order
+    pub roles: Vec<Role>,
+    /// Winning alliance
+    pub winner: Alliance,
+    /// Number of rejected team proposals
+    pub rejected_votes: usize,
+}
+
+/// Where outcomes of concluded games go
+pub trait OutcomeSink {
+    fn record(&mut self, outcome: &GameOutcome) -> Result<(), Error>;
+
+    /// Record of the player `name` over the outcomes recorded so far
+    fn stats(&self, name: &str) -> PlayerStats;
+}
+
+/// Append outcomes to a file, one JSON record per line, and keep the
+/// record of each player so that the file is read only once
+pub struct FileSink {
+    file: File,
+    tally: HashMap<String, PlayerStats>,
+}
+
+impl FileSink {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<FileSink, Error> {
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err.into()),
+        };
+        let mut tally = HashMap::new();
+        for (line_no, line) in content.lines().enumerate() {
+            match serde_json::from_str(line) {
+                Ok(outcome) => add_outcome(&mut tally, &outcome),
+                // a torn line doesn't lose the others
+                Err(err) => warn!("skipped outcome on line {}: {}", line_no + 1, err),
+            }
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+
+        Ok(FileSink { file, tally })
+    }
+}
+
+/// Count `outcome` in the record of each of its players
+fn add_outcome(tally: &mut HashMap<String, PlayerStats>, outcome: &GameOutcome) {
+    for name in &outcome.names {
+        tally.entry(name.clone()).or_default().add(outcome, name);
+    }
+}
+
+impl OutcomeSink for FileSink {
+    fn record(&mut self, outcome: &GameOutcome) -> Result<(), Error> {
+        let mut line = serde_json::to_string(outcome)?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes())?;
+        add_outcome(&mut self.tally, outcome);
+
+        Ok(())
+    }
+
+    fn stats(&self, name: &str) -> PlayerStats {
+        self.tally.get(name).copied().unwrap_or_default()
+    }
+}
+
+/// Keep outcomes in memory, they are lost on restart
+impl OutcomeSink for Vec<GameOutcome> {
+    fn record(&mut self, outcome: &GameOutcome) -> Result<(), Error> {
+        self.push(outcome.clone());
+        Ok(())
+    }
+
+    fn stats(&self, name: &str) -> PlayerStats {
+        PlayerStats::tally(self, name)
+    }
+}
+
+/// Record of a player over recorded games
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct PlayerStats {
+    pub played: usize,
+    pub wins: usize,
+    /// Games played and won on the resistance side
+    pub resistance: (usize, usize),
+    /// Games played and won on the spy side
+    pub spy: (usize, usize),
+}
+
+impl PlayerStats {
+    /// Tally the games of `name` in `outcomes`, all zeros if never played.
+    pub fn tally(outcomes: &[GameOutcome], name: &str) -> PlayerStats {
+        let mut stats = PlayerStats::default();
+        for outcome in outcomes {
+            stats.add(outcome, name);
+        }
+        stats
+    }
+
+    /// Count `outcome` if `name` played in it
+    fn add(&mut self, outcome: &GameOutcome, name: &str) {
+        let seat = match outcome.names.iter().position(|n| n == name) {
+            Some(seat) => seat,
+            None => return,
+        };
+        let alliance = match outcome.roles.get(seat) {
+            Some(role) => role.alliance(),
+            None => return,
+        };
+        let won = (alliance == outcome.winner) as usize;
+        self.played += 1;
+        self.wins += won;
+        let side = match alliance {
+            Alliance::Resistance => &mut self.resistance,
+            Alliance::Spy => &mut self.spy,
+        };
+        side.0 += 1;
+        side.1 += won;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use crate::game::Role::*;
+
+    #[test]
+    fn test_file_sink() {
+        let path = std::env::temp_dir().join(format!("avalon-outcome-{}.log", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let outcome = GameOutcome {
+            players: 5,
+            names: vec!["a", "b", "c", "d", "e"]
+                .into_iter()
+                .map(str::to_owned)
+                .collect(),
+            roles: vec![Merlin, Assassin, Percival, Morgana, Loyal],
+            winner: Alliance::Resistance,
+            rejected_votes: 2,
+        };
+        let mut sink = FileSink::open(&path).unwrap();
+        sink.record(&outcome).unwrap();
+        let stats = PlayerStats::tally(std::slice::from_ref(&outcome), "a");
+        assert_eq!(sink.stats("a"), stats);
+
+        let content = fs::read_to_string(&path).unwrap();
+        let lines: Vec<_> = content.lines().collect();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(
+            serde_json::from_str::<GameOutcome>(lines[0]).unwrap(),
+            outcome
+        );
+
+        // reopened, a torn line is skipped and the rest still counts
+        fs::write(&path, format!("{}\n{{\"players\":\n", lines[0])).unwrap();
+        let sink = FileSink::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(sink.stats("a"), stats);
+        assert_eq!(sink.stats("z"), PlayerStats::default());
+    }
+
+    #[test]
+    fn test_stats() {
+        let names: Vec<String> = vec!["a", "b", "c", "d", "e"]
+            .into_iter()
+            .map(str::to_owned)
+            .collect();
+        let mut sink = Vec::new();
+        for &(roles, winner) in &[
+            (
+                [Merlin, Assassin, Percival, Morgana, Loyal],
+                Alliance::Resistance,
+            ),
+            (
+                [Assassin, Merlin, Percival, Morgana, Loyal],
+                Alliance::Resistance,
+            ),
+            ([Assassin, Merlin, Percival, Morgana, Loyal], Alliance::Spy),
+        ] {
+            let outcome = GameOutcome {
+                players: 5,
+                names: names.clone(),
+                roles: roles.to_vec(),
+                winner,
+                rejected_votes: 0,
+            };
+            sink.record(&outcome).unwrap();
+        }
+
+        assert_eq!(
+            sink.stats("a"),
+            PlayerStats {
+                played: 3,
+                wins: 2,
+                resistance: (1, 1),
+                spy: (2, 1),
+            }
+        );
+        assert_eq!(sink.stats("z"), PlayerStats::default());
+    }
+}
@@ -0,0 +1,81 @@
+//! Room names are the codes players share to join, the chat server draws
+//! them from a `RoomNamer`.
+
+use rand::seq::SliceRandom;
+use rand::{Rng, RngCore};
+
+const ADJECTIVES: &[&str] = &[
+    "brave", "calm", "clever", "eager", "fair", "gentle", "happy", "honest", "jolly", "kind",
+    "lively", "loyal", "lucky", "merry", "mighty", "noble", "proud", "quick", "quiet", "silent",
+    "sly", "swift", "wise", "witty",
+];
+
+const ANIMALS: &[&str] = &[
+    "badger", "bear", "crane", "deer", "eagle", "falcon", "fox", "hare", "hawk", "heron", "lynx",
+    "moose", "otter", "owl", "raven", "seal", "stag", "swan", "tiger", "toad", "viper", "wolf",
+    "wren", "yak",
+];
+
+/// Draws a candidate room name, the chat server draws again if the name is
+/// taken
+pub trait RoomNamer {
+    fn generate(&self, rng: &mut dyn RngCore) -> String;
+}
+
+/// Numbers below 1000, e.g. `42`
+pub struct Numeric;
+
+impl RoomNamer for Numeric {
+    fn generate(&self, rng: &mut dyn RngCore) -> String {
+        let name: u32 = rng.gen_range(0, 1000);
+        name.to_string()
+    }
+}
+
+/// An adjective and an animal, e.g. `brave-otter`
+pub struct WordPair;
+
+impl RoomNamer for WordPair {
+    fn generate(&self, rng: &mut dyn RngCore) -> String {
+        let adjective = ADJECTIVES.choose(rng).copied().unwrap_or_default();
+        let animal = ANIMALS.choose(rng).copied().unwrap_or_default();
+        format!("{}-{}", adjective, animal)
+    }
+}
+
+/// Built-in namers, to pick one in the server config
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum RoomNames {
+    #[default]
+    Numeric,
+    WordPair,
+}
+
+impl RoomNames {
+    pub fn namer(self) -> Box<dyn RoomNamer> {
+        match self {
+            RoomNames::Numeric => Box::new(Numeric),
+            RoomNames::WordPair => Box::new(WordPair),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_word_pair() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for _ in 0..20 {
+            let name = WordPair.generate(&mut rng);
+            let (adjective, animal) = name.split_at(name.find('-').unwrap());
+            assert!(ADJECTIVES.contains(&adjective));
+            assert!(ANIMALS.contains(&&animal[1..]));
+        }
+        assert!(Numeric.generate(&mut rng).parse::<u32>().unwrap() < 1000);
+    }
+}
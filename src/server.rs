@@ -4,26 +4,193 @@
 
 use std::collections::{BTreeMap, BTreeSet};
 use std::iter::Iterator;
+use std::time::{Duration, Instant, SystemTime};
 
 use actix::prelude::*;
-use failure::Error;
+use failure::{format_err, Error};
 use log::*;
-use rand::{self, rngs::ThreadRng, Rng};
+use rand::seq::SliceRandom;
+use rand::{self, rngs::StdRng, Rng, RngCore, SeedableRng};
+use serde::Serialize;
+use tokio::sync::broadcast;
 
-use crate::game::Assignment;
+use crate::game::{
+    self, Alliance, Assignment, DefaultDeckOptions, Game, GameError, GameRules, Phase, Proposal,
+    Role, RoleSet, SeeingBy,
+};
+use crate::locale::Locale;
+use crate::log_context::LogContext;
+use crate::name::{validate_chat, NamePolicy};
+use crate::outcome::{GameOutcome, OutcomeSink};
+use crate::room_name::{RoomNamer, RoomNames};
+
+/// What a message sent to the session is about
+#[derive(Clone, Copy, PartialEq, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageKind {
+    /// Only for the receiver, e.g. the role hint
+    Private,
+    /// Sent to everyone in the room
+    Broadcast,
+    /// The request of the receiver failed
+    Error,
+    /// Reply of the server to the receiver
+    System,
+    /// `GameSummary` as JSON text, only JSON peers get it
+    Summary,
+}
+
+/// Stable code of a failed request, for clients to branch on rather than
+/// on the text
+#[derive(Clone, Copy, PartialEq, Debug, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    UnknownCommand,
+    /// The command is malformed, e.g. a missing argument
+    InvalidCommand,
+    NameRequired,
+    InvalidName,
+    /// The name is seated in the room already
+    NameTaken,
+    PermissionDenied,
+    WrongToken,
+    NotInRoom,
+    RoomNotFound,
+    RoomFull,
+    AlreadyInRoom,
+    CreateFailed,
+    InvalidSize,
+    NotOverCapacity,
+    /// More seats are taken than the room size, `/overflow` them first
+    OverCapacity,
+    /// The sender owns as many rooms as allowed
+    RoomLimit,
+    /// A bot or a player gone from the room can't own it
+    CannotOwn,
+    /// Fewer seats are taken than the room starts with
+    NotEnoughPlayers,
+    NotOwner,
+    NoSeatHeld,
+    NoFinishedGame,
+    StatsUnavailable,
+    GameNotStarted,
+    GameStarted,
+    NotSeated,
+    /// A named player isn't seated in the room
+    UnknownPlayer,
+    InvalidRoleSet,
+    WrongPhase,
+    Paused,
+    NotLeader,
+    /// The proposed team is malformed
+    InvalidTeam,
+    AlreadyVoted,
+    NotOnTeam,
+    CannotFail,
+    NotAssassin,
+    /// The room owner muted the chat of the sender
+    Muted,
+    NotLadyHolder,
+    /// The player can't be inspected by the lady of the lake
+    CannotInspect,
+    /// A seat number out of the deal
+    InvalidSeat,
+    /// `/see` before any `/sandbox` deal
+    NoSandbox,
+    /// The server has as many sessions as allowed
+    ServerFull,
+    /// Not one of the errors above
+    Other,
+}
+
+impl ErrorCode {
+    /// Code of a broken game rule
+    pub fn of_game(err: &GameError) -> ErrorCode {
+        match err {
+            GameError::InvalidPlayerNumber(_) => ErrorCode::InvalidSize,
+            GameError::RoleCountMismatch { .. }
+            | GameError::UnbalancedRoleSet { .. }
+            | GameError::InvalidEvilCount { .. }
+            | GameError::AssassinWithoutMerlin
+            | GameError::PercivalWithoutTargets
+            | GameError::NoSpies
+            | GameError::NoResistance
+            | GameError::SpyMajority { .. } => ErrorCode::InvalidRoleSet,
+            GameError::WrongPhase => ErrorCode::WrongPhase,
+            GameError::NotLeader => ErrorCode::NotLeader,
+            GameError::WrongTeamSize { .. }
+            | GameError::InvalidSeat(_)
+            | GameError::DuplicatedSeat(_) => ErrorCode::InvalidTeam,
+            GameError::AlreadyVoted => ErrorCode::AlreadyVoted,
+            GameError::NotOnTeam => ErrorCode::NotOnTeam,
+            GameError::CannotFail => ErrorCode::CannotFail,
+            GameError::Paused => ErrorCode::Paused,
+            GameError::NotAssassin => ErrorCode::NotAssassin,
+            GameError::NotLadyHolder => ErrorCode::NotLadyHolder,
+            GameError::CannotInspect(_) => ErrorCode::CannotInspect,
+            GameError::CorruptSave(_) => ErrorCode::Other,
+        }
+    }
+
+    /// Code of an error of the game module, `Other` unless a `GameError`
+    pub fn of_error(err: &Error) -> ErrorCode {
+        err.downcast_ref::<GameError>()
+            .map_or(ErrorCode::Other, ErrorCode::of_game)
+    }
+}
 
 /// Chat server sends this messages to session
-#[derive(Message)]
+#[derive(Message, Clone, Debug)]
 #[rtype(result = "()")]
-pub struct Message(pub String);
+pub struct Message {
+    pub kind: MessageKind,
+    /// Only for errors
+    pub code: Option<ErrorCode>,
+    pub text: String,
+    /// When the server sent it
+    pub time: SystemTime,
+}
+
+impl Message {
+    pub fn new(kind: MessageKind, text: String) -> Message {
+        Message {
+            kind,
+            code: None,
+            text,
+            time: SystemTime::now(),
+        }
+    }
+
+    /// A failed request, with the code for clients to branch on
+    pub fn error(code: ErrorCode, text: String) -> Message {
+        Message {
+            code: Some(code),
+            ..Message::new(MessageKind::Error, text)
+        }
+    }
+}
+
+// Message for chat server communications
 
-/// Message for chat server communications
+/// Id replied to `Connect` when the server is full, the session should stop
+pub const REFUSED_ID: usize = usize::MAX;
 
-/// New chat session is created
+/// New chat session is created, refused with `REFUSED_ID` past
+/// `max_sessions`
 #[derive(Message)]
 #[rtype(usize)]
 pub struct Connect {
     pub addr: Recipient<Message>,
+    /// Client IP admitted by `Admit`, released on disconnect
+    pub ip: Option<String>,
+}
+
+/// Count a new connection from `ip`, false if the IP already has
+/// `max_connections_per_ip` of them.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct Admit {
+    pub ip: String,
 }
 
 /// Session is disconnected
@@ -31,6 +198,18 @@ pub struct Connect {
 #[rtype(result = "()")]
 pub struct Disconnect {
     pub id: usize,
+    /// Told to the rooms of the session
+    pub reason: DisconnectReason,
+}
+
+/// Why a session is gone
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum DisconnectReason {
+    /// The peer closed the connection
+    #[default]
+    Left,
+    /// The peer stopped answering pings
+    Timeout,
 }
 
 /// List of available rooms
@@ -40,6 +219,34 @@ impl actix::Message for ListRooms {
     type Result = Vec<String>;
 }
 
+/// Supported range of room size, as (lower, upper)
+pub struct Limits;
+
+impl actix::Message for Limits {
+    type Result = (usize, usize);
+}
+
+/// Current load of the server, as (sessions, rooms)
+pub struct Load;
+
+impl actix::Message for Load {
+    type Result = (usize, usize);
+}
+
+/// List of rooms which still have free seats and no game in progress
+pub struct ListOpenRooms;
+
+impl actix::Message for ListOpenRooms {
+    type Result = Vec<String>;
+}
+
+/// Rooms with a game underway, each as `<room>: <scoreboard>`
+pub struct ListGames;
+
+impl actix::Message for ListGames {
+    type Result = Vec<String>;
+}
+
 /// Join room, room must exist.
 #[derive(Message)]
 #[rtype(result = "()")]
@@ -52,6 +259,41 @@ pub struct Join {
     pub name: String,
 }
 
+/// Take back the abandoned seat of the same name in a started game, within
+/// `seat_hold_ttl`.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Rejoin {
+    /// Client id
+    pub id: usize,
+    /// Client name, the name of the seat
+    pub session_name: String,
+    /// Room name
+    pub name: String,
+}
+
+/// Whether a seat is still held for `Rejoin`. There is no separate rejoin
+/// token, a seat is reclaimed by the room name and the seat name.
+#[derive(Message)]
+#[rtype(result = "TokenState")]
+pub struct TokenStatus {
+    /// Client name, the name of the seat
+    pub session_name: String,
+    /// Room name
+    pub name: String,
+}
+
+/// Reply of `TokenStatus`
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TokenState {
+    /// The seat is held, `Rejoin` would succeed
+    Valid,
+    /// The seat was abandoned longer than `seat_hold_ttl` ago
+    Expired,
+    /// No such room, or no abandoned seat of this name in it
+    Unknown,
+}
+
 /// Create room, create and join a new room.
 #[derive(Message)]
 #[rtype(result = "()")]
@@ -62,266 +304,6422 @@ pub struct Create {
     pub session_name: String,
     /// Room size
     pub size: u8,
+    pub options: RoomOptions,
+    /// Client supplied key, a retried Create with the same key gets the room
+    /// created before
+    pub key: Option<String>,
 }
 
-/// `ChatServer` manages chat rooms and responsible for coordinating chat
-/// session. implementation is super primitive
-pub struct ChatServer {
-    sessions: BTreeMap<usize, Recipient<Message>>,
-    rooms: BTreeMap<String, Room>,
-    rng: ThreadRng,
+/// Options a room is created with
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct RoomOptions {
+    /// Seed to reproduce the deal, random if absent
+    pub seed: Option<u64>,
+    /// Shuffle seats before dealing, so roles don't follow the join order
+    pub shuffle_seats: bool,
+    /// Tell everyone's role once the game is over
+    pub reveal_on_end: bool,
+    /// Language of the role hints
+    pub locale: Locale,
+    /// House rules of the game
+    pub rules: GameRules,
+    /// Tuning of the default deck
+    pub deck: DefaultDeckOptions,
+    /// A name already seated gets a numeric suffix, e.g. `Alice(2)`,
+    /// instead of being refused
+    pub suffix_duplicate_names: bool,
+    /// Fewest seats the owner may `/start` with, the room size if absent
+    pub min_start: Option<u8>,
+    /// A full room waits for the owner to `/start`, instead of dealing
+    pub manual_start: bool,
+    /// A full room deals only once every seat sent `Ready`
+    pub require_ready: bool,
+    /// Tell only the counts of a team vote, not who voted how
+    pub secret_votes: bool,
 }
 
-pub struct Room {
-    sessions: BTreeSet<usize>,
-    /// Room size
-    size: u8,
-    /// Client id and name pair list
-    seats: Vec<(usize, String)>,
+/// Change room size, only the room owner can do it.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Resize {
+    /// Client id
+    pub id: usize,
+    /// New room size
+    pub size: u8,
 }
 
-impl Room {
-    fn is_full(&self) -> bool {
-        return self.sessions.len() == self.size as usize;
-    }
+/// Hand the room over to another seated player, only the room owner can do
+/// it.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct TransferHost {
+    /// Client id
+    pub id: usize,
+    /// Name of the new owner
+    pub target_name: String,
 }
 
-impl Default for ChatServer {
-    fn default() -> ChatServer {
-        // default room
-        let rooms = BTreeMap::new();
-
-        ChatServer {
-            sessions: BTreeMap::new(),
-            rooms,
-            rng: rand::thread_rng(),
-        }
-    }
+/// The leader proposes a team for the current mission.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ProposeTeam {
+    /// Client id
+    pub id: usize,
+    /// Names of the team members
+    pub names: Vec<String>,
 }
 
-impl ChatServer {
-    /// Send message to all users in the room
-    fn broadcast_message(&self, room: &str, message: &str, skip_id: Option<usize>) {
-        if let Some(Room { sessions, .. }) = self.rooms.get(room) {
-            for &id in sessions {
-                if skip_id == Some(id) {
-                    continue;
-                }
-                if let Some(addr) = self.sessions.get(&id) {
-                    let _ = addr.do_send(Message(message.to_owned()));
-                }
-            }
-        }
-    }
+/// Approve or reject the proposed team.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct CastVote {
+    /// Client id
+    pub id: usize,
+    pub approve: bool,
+}
 
-    /// Send message to a specified user in the room
-    fn send_message_to_user(&self, id: usize, message: String) {
-        if let Some(addr) = self.sessions.get(&id) {
-            let _ = addr.do_send(Message(message));
-        }
-    }
+/// A team member plays a mission card.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SubmitMission {
+    /// Client id
+    pub id: usize,
+    pub fail: bool,
+}
 
-    fn assign_and_notify(&self, room: &str) -> Result<(), Error> {
-        if let Some(Room { ref seats, .. }) = self.rooms.get(room) {
-            let assignment = Assignment::new(seats.iter().map(|(_, name)| name.clone()))?;
+/// The assassin names a merlin once the resistance passed its missions.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Assassinate {
+    /// Client id
+    pub id: usize,
+    /// Name of the seat named as merlin
+    pub target_name: String,
+}
 
-            for (seat_no, &(_, role)) in assignment.players.iter().enumerate() {
-                let id = seats[seat_no].0;
-                self.send_message_to_user(id, format!("你的身份是【{}】，", role));
-                let assignment_text = assignment.see_from_role(role).text_from_player(seat_no);
-                if assignment_text.is_empty() {
-                    self.send_message_to_user(id, format!("你没有提示"));
-                } else {
-                    self.send_message_to_user(id, assignment_text);
-                }
-            }
-        }
+/// The lady of the lake holder inspects the alliance of a player.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct InspectLoyalty {
+    /// Client id
+    pub id: usize,
+    /// Name of the inspected seat
+    pub target_name: String,
+}
 
-        Ok(())
-    }
+/// Fill an open seat with a bot, only the room owner can do it.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct AddBot {
+    /// Client id
+    pub id: usize,
+}
 
-    fn remove_user_from_all_rooms(&mut self, id: usize) {
-        let mut removed_rooms: Vec<String> = Vec::new();
-        let mut empty_rooms: Vec<String> = Vec::new();
-        // remove session from all rooms
-        for (
-            name,
-            Room {
-                ref mut sessions,
-                ref mut seats,
-                ..
-            },
-        ) in &mut self.rooms
-        {
-            if sessions.remove(&id) {
-                removed_rooms.push(name.to_owned());
+/// Move seats beyond the room size into a new room, only the room owner can
+/// do it. A lobby waiting for the host or for `/ready` keeps seating whoever
+/// joins, so it can grow past its size.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Overflow {
+    /// Client id
+    pub id: usize,
+}
 
-                seats.retain(|&(session_id, _)| session_id != id);
+/// Turn `RoomOptions::reveal_on_end` on or off, only the room owner can do
+/// it.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SetRevealOnEnd {
+    /// Client id
+    pub id: usize,
+    pub enabled: bool,
+}
 
-                // more cautious, in case of new created rooms
-                if sessions.is_empty() {
-                    empty_rooms.push(name.to_owned());
-                }
-            }
-        }
-        // clean empty rooms
-        for room in empty_rooms {
-            self.rooms.remove(&room);
-        }
-        // send message to other users
-        for room in removed_rooms {
-            self.broadcast_message(&room, "Someone disconnected", None);
-        }
-    }
+/// Pause or resume the game in play, only the room owner can do it.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SetPaused {
+    /// Client id
+    pub id: usize,
+    pub paused: bool,
+}
 
-    //
-    //    /// Send message to all users in the room
-    //    fn send_message_to_all(&self, room: &str, message: &str) {
-    //        if let Some(sessions) = self.rooms.get(room) {
-    //            for id in sessions {
-    //                if let Some(addr) = self.sessions.get(id) {
-    //                    let _ = addr.do_send(Message(message.to_owned()));
-    //                }
-    //            }
-    //        }
-    //    }
+/// Scrap the game in play without a winner, only the room owner can do
+/// it. The room is back to waiting, with the same seats.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct AbortGame {
+    /// Client id
+    pub id: usize,
 }
 
-/// Make actor from `ChatServer`
-impl Actor for ChatServer {
-    /// We are going to use simple Context, we just need ability to communicate
-    /// with other actors.
-    type Context = Context<Self>;
+/// Say something to the room.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ChatText {
+    /// Client id
+    pub id: usize,
+    pub text: String,
 }
 
-/// Handler for Connect message.
-///
-/// Register new session and assign unique id to this session
-impl Handler<Connect> for ChatServer {
-    type Result = usize;
+/// Mute or unmute the chat of a seated player, only the room owner can do
+/// it.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Mute {
+    /// Client id
+    pub id: usize,
+    pub target_name: String,
+    pub muted: bool,
+}
 
-    fn handle(&mut self, msg: Connect, _: &mut Context<Self>) -> Self::Result {
-        debug!("Someone joined");
+/// Send the user the role again, after the game started.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct WhoAmI {
+    /// Client id
+    pub id: usize,
+}
 
-        // register session with random id
-        let id = self.rng.gen::<usize>();
-        self.sessions.insert(id, msg.addr);
+/// Show the room the role of the user, by the user's own choice. Only one's
+/// own role can be revealed, after the game started.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct RevealSelf {
+    /// Client id
+    pub id: usize,
+}
 
-        // send id back
-        id
-    }
+/// Tell the user which roles the current room deals, not who gets them.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct RoomDeck {
+    /// Client id
+    pub id: usize,
 }
 
-/// Handler for Disconnect message.
-impl Handler<Disconnect> for ChatServer {
-    type Result = ();
+/// Show the room owner a sample deal of the room, before the game starts.
+/// The sample is drawn apart from the real deal and names no one.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct PreviewDeal {
+    /// Client id
+    pub id: usize,
+}
 
-    fn handle(&mut self, msg: Disconnect, _: &mut Context<Self>) {
-        debug!("Someone disconnected");
+/// Permute the seats before the game starts, only the room owner can do
+/// it. Drawn from the room seed if any, roles are dealt apart.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ShuffleSeats {
+    /// Client id
+    pub id: usize,
+}
 
-        // remove address
-        if self.sessions.remove(&msg.id).is_some() {
-            self.remove_user_from_all_rooms(msg.id)
-        }
-    }
+/// Tell the user what each role of the current room's deck does.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct RoomRules {
+    /// Client id
+    pub id: usize,
 }
 
-/// Handler for `ListRooms` message.
-impl Handler<ListRooms> for ChatServer {
-    type Result = MessageResult<ListRooms>;
+/// Tell the user the public state of the game in play.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct GameStatus {
+    /// Client id
+    pub id: usize,
+}
 
-    fn handle(&mut self, _: ListRooms, _: &mut Context<Self>) -> Self::Result {
+/// Start the game before the room is full, with at least `min_start`
+/// seats, only the room owner can do it. The room shrinks to the seats.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Start {
+    /// Client id
+    pub id: usize,
+}
+
+/// Confirm the seat is ready to play, under `RoomOptions::require_ready`
+/// a full room deals once every seat is.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Ready {
+    /// Client id
+    pub id: usize,
+}
+
+/// Tell the user how many seats are left in the current room.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SeatsNeeded {
+    /// Client id
+    pub id: usize,
+}
+
+/// Watch a room without taking a seat
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Watch {
+    /// Client id
+    pub id: usize,
+    /// Room name
+    pub name: String,
+}
+
+/// Replay the public events of the game finished in a room
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Review {
+    /// Client id
+    pub id: usize,
+    /// Room name
+    pub name: String,
+}
+
+/// Record of the named player over recorded games, keyed by the display
+/// name, so players sharing a name share a record
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Stats {
+    /// Client id
+    pub id: usize,
+    /// Player name
+    pub name: String,
+}
+
+/// Seated players of the user's room, in seat order, for a client to
+/// repaint its member list
+#[derive(Message)]
+#[rtype(result = "Option<Vec<RosterEntry>>")]
+pub struct Roster {
+    /// Client id
+    pub id: usize,
+}
+
+/// A seat of `Roster`
+#[derive(Clone, PartialEq, Debug, Serialize)]
+pub struct RosterEntry {
+    pub seat: usize,
+    pub name: String,
+    /// Sent `Ready`, bots always are
+    pub ready: bool,
+    pub owner: bool,
+}
+
+/// Name of the room the user is in, to share it again
+#[derive(Message)]
+#[rtype(result = "Option<String>")]
+pub struct RoomCode {
+    /// Client id
+    pub id: usize,
+}
+
+/// How long the room the user is in has existed
+#[derive(Message)]
+#[rtype(result = "Option<Duration>")]
+pub struct RoomElapsed {
+    /// Client id
+    pub id: usize,
+}
+
+/// Check the admin token, returns whether it's right
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct Authenticate {
+    pub token: String,
+}
+
+/// Remove a room and tell everyone in it, for moderators. Returns whether
+/// the room existed.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct CloseRoom {
+    /// Room name
+    pub name: String,
+}
+
+/// Leave the current room, the seat is abandoned if the game started.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Leave {
+    /// Client id
+    pub id: usize,
+}
+
+/// Settings of the chat server
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    /// Gap between role reveals of consecutive seats, all seats are told at
+    /// once if zero
+    pub reveal_gap: Duration,
+    /// How long events of a finished game are kept for `/review`
+    pub review_ttl: Duration,
+    /// Blocked words and max length of player names
+    pub names: NamePolicy,
+    /// Token for admin commands, nobody is admin if absent
+    pub admin_token: Option<String>,
+    /// How long an empty room lingers for a rejoin before it's removed,
+    /// removed at once if zero
+    pub empty_room_grace: Duration,
+    /// How long bots wait before they act
+    pub bot_delay: Duration,
+    /// How long an abandoned seat is held for its player to rejoin by name
+    pub seat_hold_ttl: Duration,
+    /// Open connections allowed from one IP, unlimited if absent
+    pub max_connections_per_ip: Option<usize>,
+    /// Sessions connected at once, unlimited if absent
+    pub max_sessions: Option<usize>,
+    /// Rooms one session may own at once, unlimited if absent
+    pub max_rooms_per_owner: Option<usize>,
+    /// How room names are drawn
+    pub room_names: RoomNames,
+    /// Texts of the role hints, the built-in ones of the room locale if
+    /// absent
+    pub templates: Templates,
+}
+
+/// Texts the dealer tells each seat, `{role}` is replaced by the role name
+#[derive(Clone, Debug, Default)]
+pub struct Templates {
+    /// e.g. `你的身份是【{role}】，`
+    pub role: Option<String>,
+    /// Told instead of the hint to roles which see nothing
+    pub no_hint: Option<String>,
+}
+
+impl Templates {
+    fn role(&self, role: Role, locale: Locale) -> String {
+        let name = role.localized_name(locale);
+        match (&self.role, locale) {
+            (Some(template), _) => template.replace("{role}", name),
+            (None, Locale::Zh) => format!("你的身份是【{}】，", name),
+            (None, Locale::En) => format!("Your role is [{}],", name),
+        }
+    }
+
+    fn no_hint(&self, locale: Locale) -> String {
+        match (&self.no_hint, locale) {
+            (Some(template), _) => template.clone(),
+            (None, Locale::Zh) => "你没有提示".to_owned(),
+            (None, Locale::En) => "you have no hint".to_owned(),
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> ServerConfig {
+        ServerConfig {
+            reveal_gap: Duration::default(),
+            review_ttl: Duration::from_secs(600),
+            names: NamePolicy::default(),
+            admin_token: None,
+            empty_room_grace: Duration::default(),
+            bot_delay: Duration::from_secs(1),
+            seat_hold_ttl: Duration::from_secs(300),
+            max_connections_per_ip: None,
+            max_sessions: None,
+            max_rooms_per_owner: Some(1),
+            room_names: RoomNames::default(),
+            templates: Templates::default(),
+        }
+    }
+}
+
+/// Names drawn for a new room before giving up
+const ROOM_NAME_ATTEMPTS: usize = 10;
+
+/// How often empty rooms are checked for removal
+const REAP_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often abandoned seats are checked for an expired hold
+const STAND_IN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Structured end of a game, the counterpart of the broadcasts for JSON
+/// peers
+#[derive(Clone, Debug, Serialize)]
+pub struct GameSummary {
+    /// Always "summary", to tell it from other server messages
+    pub kind: &'static str,
+    pub room: String,
+    /// Absent if the game ended without a winner
+    pub winner: Option<Alliance>,
+    /// Whether each finished mission passed
+    pub missions: Vec<bool>,
+    /// Voted proposals, in order
+    pub proposals: Vec<Proposal>,
+    /// Role of each seat, only if the room reveals them on end
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roles: Option<Vec<SeatRole>>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SeatRole {
+    pub seat: usize,
+    pub name: String,
+    pub role: Role,
+}
+
+impl GameSummary {
+    /// Summary of the game in `room`, if any
+    fn of(name: &str, room: &Room) -> Option<GameSummary> {
+        let game = room.game.as_ref()?;
+        let winner = match game.phase() {
+            Phase::Over(winner) => Some(winner),
+            _ => None,
+        };
+        let roles = if room.options.reveal_on_end {
+            let roles = room
+                .seats
+                .iter()
+                .zip(&game.assignment().players)
+                .enumerate()
+                .map(|(seat, ((_, name), &(_, role)))| SeatRole {
+                    seat,
+                    name: name.clone(),
+                    role,
+                })
+                .collect();
+            Some(roles)
+        } else {
+            None
+        };
+
+        Some(GameSummary {
+            kind: "summary",
+            room: name.to_owned(),
+            winner,
+            missions: game.quests().to_vec(),
+            proposals: game.proposals().to_vec(),
+            roles,
+        })
+    }
+}
+
+/// Points in the life of a room, told to the observer
+#[derive(Clone, PartialEq, Debug)]
+pub enum ServerEvent {
+    RoomCreated { room: String },
+    PlayerJoined { room: String, name: String },
+    GameStarted { room: String },
+    GameEnded { room: String, winner: Alliance },
+}
+
+/// Reacts to room lifecycle events, for apps embedding the server
+pub trait Observer {
+    fn notify(&mut self, event: &ServerEvent);
+}
+
+impl<F: FnMut(&ServerEvent)> Observer for F {
+    fn notify(&mut self, event: &ServerEvent) {
+        self(event)
+    }
+}
+
+/// `ChatServer` manages chat rooms and responsible for coordinating chat
+/// session. implementation is super primitive
+pub struct ChatServer {
+    sessions: BTreeMap<usize, Recipient<Message>>,
+    rooms: BTreeMap<String, Room>,
+    rng: Box<dyn RngCore>,
+    /// Draws names of new rooms
+    room_namer: Box<dyn RoomNamer>,
+    config: ServerConfig,
+    /// Public events of finished games by room name, with the finish time
+    finished: BTreeMap<String, (Instant, Vec<String>)>,
+    /// Where outcomes of finished games are recorded, if anywhere
+    outcome_sink: Option<Box<dyn OutcomeSink>>,
+    /// Told about room lifecycle events, if any
+    observer: Option<Box<dyn Observer>>,
+    /// Where room lifecycle events are published for subscribers outside
+    /// the actor system, if anywhere
+    event_channel: Option<broadcast::Sender<ServerEvent>>,
+    /// Open connections by client IP, admitted ones included
+    connections_per_ip: BTreeMap<String, usize>,
+    /// Client IP of each session, to release it on disconnect
+    session_ips: BTreeMap<usize, String>,
+}
+
+pub struct Room {
+    sessions: BTreeSet<usize>,
+    /// Room size
+    size: u8,
+    /// Client id and name pair list
+    seats: Vec<(usize, String)>,
+    /// Client id of the room owner, the creator until handed over
+    owner: usize,
+    options: RoomOptions,
+    /// Game in play, it starts once roles are dealt
+    game: Option<Game>,
+    /// Seats whose player left the started game, with the time they left
+    abandoned: BTreeMap<usize, Instant>,
+    /// Client id of the creator and key of its Create message, ownership
+    /// may move on but a retry comes from the creator
+    create_key: Option<(usize, String)>,
+    /// Client ids watching without a seat, they get broadcasts too
+    spectators: BTreeSet<usize>,
+    /// Public events of the game, for review once it's over
+    events: Vec<String>,
+    /// When the last session left, the room is removed once the grace
+    /// period passes
+    emptied_at: Option<Instant>,
+    /// Seated ids played by the server, they have no session
+    bots: BTreeSet<usize>,
+    created_at: Instant,
+    /// Names of seats whose chat is dropped
+    muted: BTreeSet<String>,
+    /// Client ids which sent `Ready`, under `require_ready`
+    ready: BTreeSet<usize>,
+}
+
+impl Room {
+    fn new(owner: usize, owner_name: String, size: u8, options: RoomOptions) -> Room {
+        let mut sessions = BTreeSet::new();
+        sessions.insert(owner);
+
+        Room {
+            sessions,
+            size,
+            seats: vec![(owner, owner_name)],
+            owner,
+            options,
+            game: None,
+            abandoned: BTreeMap::new(),
+            create_key: None,
+            spectators: BTreeSet::new(),
+            events: Vec::new(),
+            emptied_at: None,
+            bots: BTreeSet::new(),
+            created_at: Instant::now(),
+            muted: BTreeSet::new(),
+            ready: BTreeSet::new(),
+        }
+    }
+
+    pub fn spectator_count(&self) -> usize {
+        self.spectators.len()
+    }
+
+    fn is_started(&self) -> bool {
+        self.game.is_some()
+    }
+
+    /// Whether the seat of `id` may own the room, a bot or a player gone
+    /// from the room can't run it
+    fn can_own(&self, id: usize) -> bool {
+        self.sessions.contains(&id) && !self.bots.contains(&id) && !self.abandoned.contains_key(&id)
+    }
+
+    fn is_full(&self) -> bool {
+        self.sessions.len() == self.size as usize
+    }
+
+    /// Back to a lobby for the next game, the abandoned seats are freed
+    fn reset_to_lobby(&mut self) {
+        let abandoned = std::mem::take(&mut self.abandoned);
+        for seat_no in abandoned.keys() {
+            // a bot may stand in for the seat
+            let id = self.seats[*seat_no].0;
+            self.sessions.remove(&id);
+            self.bots.remove(&id);
+        }
+        self.seats = std::mem::take(&mut self.seats)
+            .into_iter()
+            .enumerate()
+            .filter(|(seat_no, _)| !abandoned.contains_key(seat_no))
+            .map(|(_, seat)| seat)
+            .collect();
+        self.game = None;
+        self.events.clear();
+        self.ready.clear();
+    }
+
+    /// Whether every seat is ready, bots always are
+    fn all_ready(&self) -> bool {
+        self.seats
+            .iter()
+            .all(|(id, _)| self.ready.contains(id) || self.bots.contains(id))
+    }
+
+    /// Fewest seats the game may start with
+    fn min_start(&self) -> usize {
+        self.options.min_start.unwrap_or(self.size) as usize
+    }
+}
+
+impl Default for ChatServer {
+    fn default() -> ChatServer {
+        ChatServer::new(ServerConfig::default())
+    }
+}
+
+impl ChatServer {
+    pub fn new(config: ServerConfig) -> ChatServer {
+        // default room
+        let rooms = BTreeMap::new();
+
+        ChatServer {
+            sessions: BTreeMap::new(),
+            rooms,
+            rng: Box::new(rand::thread_rng()),
+            room_namer: config.room_names.namer(),
+            config,
+            finished: BTreeMap::new(),
+            outcome_sink: None,
+            observer: None,
+            event_channel: None,
+            connections_per_ip: BTreeMap::new(),
+            session_ips: BTreeMap::new(),
+        }
+    }
+
+    /// Draw room names, session ids and deals from `rng` instead of the
+    /// thread local one
+    pub fn with_rng<R: RngCore + 'static>(mut self, rng: R) -> ChatServer {
+        self.rng = Box::new(rng);
+        self
+    }
+
+    /// Draw room names from `namer` instead of the configured one
+    pub fn with_room_namer<N: RoomNamer + 'static>(mut self, namer: N) -> ChatServer {
+        self.room_namer = Box::new(namer);
+        self
+    }
+
+    /// Record outcomes of finished games to `sink`
+    pub fn with_outcome_sink(mut self, sink: Box<dyn OutcomeSink>) -> ChatServer {
+        self.outcome_sink = Some(sink);
+        self
+    }
+
+    /// Tell `observer` when rooms are created or joined and games start or
+    /// end
+    pub fn with_observer(mut self, observer: Box<dyn Observer>) -> ChatServer {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Publish room lifecycle events to `sender`, subscribers only read
+    /// them
+    pub fn with_event_channel(mut self, sender: broadcast::Sender<ServerEvent>) -> ChatServer {
+        self.event_channel = Some(sender);
+        self
+    }
+
+    /// Tell the observer and the event channel
+    fn publish_event(&mut self, event: ServerEvent) {
+        if let Some(observer) = self.observer.as_mut() {
+            observer.notify(&event);
+        }
+        if let Some(sender) = &self.event_channel {
+            // fails only when nobody subscribes
+            let _ = sender.send(event);
+        }
+    }
+
+    /// Uncount a connection admitted from `ip`
+    fn release_ip(&mut self, ip: &str) {
+        if let Some(count) = self.connections_per_ip.get_mut(ip) {
+            *count -= 1;
+            if *count == 0 {
+                self.connections_per_ip.remove(ip);
+            }
+        }
+    }
+
+    /// Send message to all users in the room
+    fn broadcast_message(&self, room: &str, message: &str, skip_id: Option<usize>) {
+        if let Some(Room {
+            sessions,
+            spectators,
+            ..
+        }) = self.rooms.get(room)
+        {
+            for &id in sessions.iter().chain(spectators) {
+                if skip_id == Some(id) {
+                    continue;
+                }
+                if let Some(addr) = self.sessions.get(&id) {
+                    let _ = addr.do_send(Message::new(MessageKind::Broadcast, message.to_owned()));
+                }
+            }
+        }
+    }
+
+    /// Send the game summary to everyone in the room, sessions drop it
+    /// unless they speak JSON
+    fn send_summary(&self, room: &str, summary: &GameSummary) {
+        let text = match serde_json::to_string(summary) {
+            Ok(text) => text,
+            Err(err) => {
+                warn!("{} bad game summary: {}", LogContext::room(room), err);
+                return;
+            }
+        };
+        if let Some(Room {
+            sessions,
+            spectators,
+            ..
+        }) = self.rooms.get(room)
+        {
+            for id in sessions.iter().chain(spectators) {
+                self.send_to_user(*id, MessageKind::Summary, text.clone());
+            }
+        }
+    }
+
+    /// Send message to a specified user in the room
+    fn send_message_to_user(&self, id: usize, message: String) {
+        self.send_to_user(id, MessageKind::System, message);
+    }
+
+    /// Tell the user the request failed
+    fn send_error_to_user(&self, id: usize, code: ErrorCode, message: String) {
+        if let Some(addr) = self.sessions.get(&id) {
+            let _ = addr.do_send(Message::error(code, message));
+        }
+    }
+
+    fn send_to_user(&self, id: usize, kind: MessageKind, message: String) {
+        if let Some(addr) = self.sessions.get(&id) {
+            let _ = addr.do_send(Message::new(kind, message));
+        }
+    }
+
+    /// Locale of the room, the default one once it's gone
+    fn locale_of(&self, room: &str) -> Locale {
+        self.rooms
+            .get(room)
+            .map(|room| room.options.locale)
+            .unwrap_or_default()
+    }
+
+    /// Rooms owned by the user, counted from the rooms themselves so that a
+    /// handed over or removed room no longer counts
+    fn rooms_owned_by(&self, id: usize) -> usize {
+        self.rooms.values().filter(|room| room.owner == id).count()
+    }
+
+    /// A random id of no session nor bot, for a new one of either
+    fn unused_id(&mut self) -> usize {
+        loop {
+            let id = self.rng.gen::<usize>();
+            let taken = id == REFUSED_ID
+                || self.sessions.contains_key(&id)
+                || self.rooms.values().any(|room| room.bots.contains(&id));
+            if !taken {
+                return id;
+            }
+        }
+    }
+
+    /// Name of the room the user is in
+    fn room_of_user(&self, id: usize) -> Option<String> {
+        self.rooms
+            .iter()
+            .find(|(_, room)| room.sessions.contains(&id))
+            .map(|(name, _)| name.to_owned())
+    }
+
+    /// A random name not taken by any room, drawn again on collision up to
+    /// `ROOM_NAME_ATTEMPTS` times
+    fn random_room_name(&mut self) -> Option<String> {
+        for _ in 0..ROOM_NAME_ATTEMPTS {
+            let name = self.room_namer.generate(&mut self.rng);
+            if !self.rooms.contains_key(&name) {
+                return Some(name);
+            }
+        }
+        None
+    }
+
+    /// Peel seats beyond the size of the owner's room off into a new room
+    /// with the same settings, the first moved seat owns it.
+    fn overflow(&mut self, id: usize) -> Result<(), (ErrorCode, String)> {
+        let name = self
+            .room_of_user(id)
+            .ok_or_else(|| (ErrorCode::NotInRoom, "!!! you are not in a room".to_owned()))?;
+        match self.rooms.get(&name) {
+            Some(room) if room.owner != id => {
+                return Err((
+                    ErrorCode::NotOwner,
+                    "!!! only the room owner can split the room".to_owned(),
+                ))
+            }
+            Some(room) if room.is_started() => {
+                return Err((
+                    ErrorCode::GameStarted,
+                    "!!! game already started".to_owned(),
+                ))
+            }
+            Some(room) if room.seats.len() <= room.size as usize => {
+                return Err((
+                    ErrorCode::NotOverCapacity,
+                    "!!! room is not over capacity".to_owned(),
+                ))
+            }
+            Some(_) => {}
+            None => {
+                return Err((
+                    ErrorCode::RoomNotFound,
+                    "!!! room not exist, may be deleted just now".to_owned(),
+                ))
+            }
+        }
+        let new_name = self
+            .random_room_name()
+            .ok_or_else(|| (ErrorCode::CreateFailed, "!!! create room failed".to_owned()))?;
+
+        let room = self.rooms.get_mut(&name).unwrap();
+        let moved = room.seats.split_off(room.size as usize);
+        let mut moved_bots = BTreeSet::new();
+        for (session_id, _) in &moved {
+            room.sessions.remove(session_id);
+            if room.bots.remove(session_id) {
+                moved_bots.insert(*session_id);
+            }
+        }
+        let mut new_room = Room::new(
+            moved[0].0,
+            moved[0].1.clone(),
+            room.size,
+            room.options.clone(),
+        );
+        for (session_id, session_name) in &moved[1..] {
+            new_room.sessions.insert(*session_id);
+            new_room.seats.push((*session_id, session_name.clone()));
+        }
+        new_room.bots = moved_bots;
+        self.rooms.insert(new_name.clone(), new_room);
+        self.publish_event(ServerEvent::RoomCreated {
+            room: new_name.clone(),
+        });
+
+        self.broadcast_message(
+            &name,
+            &format!("{} players moved to room {}", moved.len(), new_name),
+            None,
+        );
+        self.broadcast_message(&new_name, &format!("moved to room {}", new_name), None);
+        Ok(())
+    }
+
+    /// Name of the room the user is watching
+    fn room_watched_by(&self, id: usize) -> Option<String> {
+        self.rooms
+            .iter()
+            .find(|(_, room)| room.spectators.contains(&id))
+            .map(|(name, _)| name.to_owned())
+    }
+
+    /// Tell the room how many are watching
+    fn broadcast_spectator_count(&self, room: &str) {
+        if let Some(count) = self.rooms.get(room).map(Room::spectator_count) {
+            self.broadcast_message(room, &format!("watching: {}", count), None);
+        }
+    }
+
+    /// The last seat is taken, deal or wait for the owner
+    fn room_filled(&mut self, room: &str, ctx: &mut Context<Self>) {
+        match self.rooms.get(room) {
+            Some(Room { options, .. }) if options.manual_start => {
+                self.broadcast_message(room, "room is full, waiting for host to start", None);
+            }
+            Some(game_room) if game_room.options.require_ready && !game_room.all_ready() => {
+                self.broadcast_message(room, "room is full, waiting for everyone to /ready", None);
+            }
+            Some(_) => self.start_game(room, ctx),
+            None => (),
+        }
+    }
+
+    /// Deal roles in a full room, the room is kept for the game
+    fn start_game(&mut self, room: &str, ctx: &mut Context<Self>) {
+        let full = match self.locale_of(room) {
+            Locale::Zh => "人已经凑齐",
+            Locale::En => "everyone is here",
+        };
+        self.broadcast_message(room, full, None);
+        self.shuffle_seats(room);
+        let rules = match self.rooms.get(room) {
+            Some(room) => room.options.rules.clone(),
+            None => return,
+        };
+        match self
+            .assign_and_notify(room, ctx)
+            .and_then(|assignment| Game::with_rules(assignment, rules))
+        {
+            Ok(game) => {
+                if let Some(room) = self.rooms.get_mut(room) {
+                    // started short of the size
+                    room.size = room.seats.len() as u8;
+                    room.game = Some(game);
+                }
+                self.announce_leader(room);
+                self.schedule_bots(room, ctx);
+                info!("{} game started", LogContext::room(room));
+                self.publish_event(ServerEvent::GameStarted {
+                    room: room.to_owned(),
+                });
+            }
+            Err(err) => {
+                warn!("{} failed to deal: {}", LogContext::room(room), err);
+                let message = match self.locale_of(room) {
+                    Locale::Zh => format!("分配失败：{}", err),
+                    Locale::En => format!("failed to deal: {}", err),
+                };
+                self.broadcast_message(room, &message, None);
+                self.rooms.remove(room);
+            }
+        }
+    }
+
+    /// Broadcast a public event of the game and keep it for review
+    fn broadcast_event(&mut self, room: &str, message: String) {
+        if let Some(room) = self.rooms.get_mut(room) {
+            room.events.push(message.clone());
+        }
+        self.broadcast_message(room, &message, None);
+    }
+
+    /// Keep events of the game over for review, with alliances of all seats,
+    /// and make the room a lobby again
+    fn finish_game(&mut self, room: &str) {
+        if let Some(Room {
+            seats,
+            options,
+            game: Some(game),
+            ..
+        }) = self.rooms.get(room)
+        {
+            if options.reveal_on_end {
+                let locale = options.locale;
+                let roles: Vec<String> = seats
+                    .iter()
+                    .zip(&game.assignment().players)
+                    .map(|((_, name), (_, role))| {
+                        let role_name = role.localized_name(locale);
+                        let alliance = role.alliance().localized_name(locale);
+                        match locale {
+                            Locale::Zh => format!("{} {}（{}）", name, role_name, alliance),
+                            Locale::En => format!("{} {} ({})", name, role_name, alliance),
+                        }
+                    })
+                    .collect();
+                let roles = roles.join(locale.list_separator());
+                let message = match locale {
+                    Locale::Zh => format!("身份：{}", roles),
+                    Locale::En => format!("roles: {}", roles),
+                };
+                self.broadcast_event(room, message);
+            }
+        }
+
+        let (seats, options, game, events) = match self.rooms.get_mut(room) {
+            Some(Room {
+                seats,
+                options,
+                game: Some(game),
+                events,
+                ..
+            }) => (seats, options, game, events),
+            _ => return,
+        };
+        let locale = options.locale;
+        let alliances: Vec<String> = seats
+            .iter()
+            .zip(&game.assignment().players)
+            .map(|((_, name), (_, role))| {
+                format!("{} {}", name, role.alliance().localized_name(locale))
+            })
+            .collect();
+        let alliances = alliances.join(locale.list_separator());
+        let mut events = std::mem::take(events);
+        events.push(match locale {
+            Locale::Zh => format!("阵营：{}", alliances),
+            Locale::En => format!("alliances: {}", alliances),
+        });
+
+        if let (Some(sink), Phase::Over(winner)) = (self.outcome_sink.as_mut(), game.phase()) {
+            let players = &game.assignment().players;
+            let outcome = GameOutcome {
+                players: players.len(),
+                names: players.iter().map(|(name, _)| name.clone()).collect(),
+                roles: players.iter().map(|&(_, role)| role).collect(),
+                winner,
+                rejected_votes: game.rejected_votes(),
+            };
+            if let Err(err) = sink.record(&outcome) {
+                warn!(
+                    "{} failed to record the outcome: {}",
+                    LogContext::room(room),
+                    err
+                );
+            }
+        }
+        let winner = match game.phase() {
+            Phase::Over(winner) => Some(winner),
+            _ => None,
+        };
+        if let Some(winner) = winner {
+            self.publish_event(ServerEvent::GameEnded {
+                room: room.to_owned(),
+                winner,
+            });
+        }
+        if let Some(summary) = self
+            .rooms
+            .get(room)
+            .and_then(|game_room| GameSummary::of(room, game_room))
+        {
+            self.send_summary(room, &summary);
+        }
+
+        let now = Instant::now();
+        let ttl = self.config.review_ttl;
+        self.finished
+            .retain(|_, (finished_at, _)| now.duration_since(*finished_at) < ttl);
+        self.finished.insert(room.to_owned(), (now, events));
+        info!("{} game over", LogContext::room(room));
+
+        // the next game is dealt in the same room
+        if let Some(room) = self.rooms.get_mut(room) {
+            room.reset_to_lobby();
+        }
+    }
+
+    /// Tell the room who leads the next proposal
+    fn announce_leader(&mut self, room: &str) {
+        if let Some(Room {
+            seats,
+            options,
+            game: Some(game),
+            ..
+        }) = self.rooms.get(room)
+        {
+            let leader = &seats[game.leader()].1;
+            let mission =
+                game::localized_mission(game.mission_index(), game.team_size(), options.locale);
+            let message = match options.locale {
+                Locale::Zh => format!("{} 是队长，{}，请用 /propose 提名队员", leader, mission),
+                Locale::En => format!("{} leads, {}, /propose the team", leader, mission),
+            };
+            self.broadcast_event(room, message);
+        }
+    }
+
+    /// Tell the room who inspects a player with the lady of the lake
+    fn announce_lady(&mut self, room: &str) {
+        if let Some(Room {
+            seats,
+            options,
+            game: Some(game),
+            ..
+        }) = self.rooms.get(room)
+        {
+            if let Some(holder) = game.lady_holder() {
+                let holder = &seats[holder].1;
+                let message = match options.locale {
+                    Locale::Zh => format!(
+                        "{} 持有湖中仙女，请用 /lake <名字> 查验一名玩家的阵营",
+                        holder
+                    ),
+                    Locale::En => format!(
+                        "{} holds the lady of the lake, /lake <name> to inspect a player",
+                        holder
+                    ),
+                };
+                self.broadcast_event(room, message);
+            }
+        }
+    }
+
+    /// Ask the approved team to play mission cards
+    fn announce_team(&mut self, room: &str) {
+        if let Some(Room {
+            seats,
+            options,
+            game: Some(game),
+            ..
+        }) = self.rooms.get(room)
+        {
+            let names: Vec<&str> = game
+                .team()
+                .iter()
+                .map(|&seat| seats[seat].1.as_str())
+                .collect();
+            let names = names.join(options.locale.list_separator());
+            let message = match options.locale {
+                Locale::Zh => format!("请 {} 用 /mission success 或 /mission fail 执行任务", names),
+                Locale::En => format!(
+                    "{}, /mission success or /mission fail to play the mission",
+                    names
+                ),
+            };
+            self.broadcast_event(room, message);
+        }
+    }
+
+    /// Permute seats of the room, if it's asked to
+    fn shuffle_seats(&mut self, room: &str) {
+        if self
+            .rooms
+            .get(room)
+            .is_some_and(|room| room.options.shuffle_seats)
+        {
+            self.permute_seats(room);
+        }
+    }
+
+    /// Permute seats of the room, drawn from its seed if any
+    fn permute_seats(&mut self, room: &str) {
+        if let Some(Room { seats, options, .. }) = self.rooms.get_mut(room) {
+            match options.seed {
+                Some(seed) => seats.shuffle(&mut StdRng::seed_from_u64(seed)),
+                None => seats.shuffle(&mut self.rng),
+            }
+        }
+    }
+
+    /// Deal roles and tell every seat, a failed deal never reveals a role.
+    fn assign_and_notify(
+        &mut self,
+        room: &str,
+        ctx: &mut Context<Self>,
+    ) -> Result<Assignment, Error> {
+        let assignment = self.deal(room)?;
+        self.notify_hints(room, &assignment, ctx)?;
+        Ok(assignment)
+    }
+
+    /// Tell every seat of the room its hint, nothing is sent unless the
+    /// hints of all seats render
+    fn notify_hints(
+        &self,
+        room: &str,
+        assignment: &Assignment,
+        ctx: &mut Context<Self>,
+    ) -> Result<(), Error> {
+        let hints = self.hints(room, assignment)?;
+
+        for (seat_no, (id, messages)) in hints.into_iter().enumerate() {
+            if self.config.reveal_gap.is_zero() {
+                for message in messages {
+                    self.send_to_user(id, MessageKind::Private, message);
+                }
+            } else {
+                // reveal seat by seat
+                let delay = self.config.reveal_gap * seat_no as u32;
+                ctx.run_later(delay, move |act, _| {
+                    for message in messages {
+                        act.send_to_user(id, MessageKind::Private, message);
+                    }
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Deal roles to the seats of the room
+    fn deal(&mut self, room: &str) -> Result<Assignment, Error> {
+        let Room { seats, options, .. } = self
+            .rooms
+            .get(room)
+            .ok_or_else(|| format_err!("room {} not exist", room))?;
+        let names = seats.iter().map(|(_, name)| name.clone());
+        let role_set = RoleSet::standard_with(seats.len(), &options.deck)?;
+        let rules = &options.rules;
+        let assignment = match options.seed {
+            Some(seed) => {
+                Assignment::with_rng(names, &role_set, rules, &mut StdRng::seed_from_u64(seed))?
+            }
+            None => Assignment::with_rng(names, &role_set, rules, &mut self.rng)?,
+        };
+        Ok(assignment)
+    }
+
+    /// Render the hints of all seats, in seat order
+    fn hints(&self, room: &str, assignment: &Assignment) -> Result<Vec<Hint>, Error> {
+        let Room { seats, options, .. } = self
+            .rooms
+            .get(room)
+            .ok_or_else(|| format_err!("room {} not exist", room))?;
+        seats
+            .iter()
+            .enumerate()
+            .map(|(seat_no, &(id, _))| {
+                let messages =
+                    role_messages(assignment, seat_no, options.locale, &self.config.templates);
+                if messages.is_empty() {
+                    Err(format_err!("seat {} is not dealt", seat_no + 1))
+                } else {
+                    Ok((id, messages))
+                }
+            })
+            .collect()
+    }
+
+    /// Tell the user in `seat_no` the role and what the role sees
+    fn notify_role(&self, id: usize, assignment: &Assignment, seat_no: usize, locale: Locale) {
+        for message in role_messages(assignment, seat_no, locale, &self.config.templates) {
+            self.send_to_user(id, MessageKind::Private, message);
+        }
+    }
+
+    /// Let the bots of the room act on the current phase after `bot_delay`
+    fn schedule_bots(&self, room: &str, ctx: &mut Context<Self>) {
+        match self.rooms.get(room) {
+            Some(Room { bots, .. }) if !bots.is_empty() => {}
+            _ => return,
+        }
+        let room = room.to_owned();
+        ctx.run_later(self.config.bot_delay, move |act, ctx| {
+            act.bots_act(&room, ctx)
+        });
+    }
+
+    /// Bots approve every team and pass missions, unless they are spies.
+    /// The bot leader proposes the seats from itself on.
+    fn bots_act(&self, room: &str, ctx: &mut Context<Self>) {
+        let (seats, bots, game) = match self.rooms.get(room) {
+            Some(Room {
+                seats,
+                bots,
+                game: Some(game),
+                ..
+            }) if !game.is_paused() => (seats, bots, game),
+            _ => return,
+        };
+        let addr = ctx.address();
+        match game.phase() {
+            Phase::Propose => {
+                let leader = game.leader();
+                if bots.contains(&seats[leader].0) {
+                    let names = (0..game.team_size())
+                        .map(|i| seats[(leader + i) % seats.len()].1.clone())
+                        .collect();
+                    addr.do_send(ProposeTeam {
+                        id: seats[leader].0,
+                        names,
+                    });
+                }
+            }
+            Phase::Vote => {
+                for &(id, _) in seats.iter().filter(|(id, _)| bots.contains(id)) {
+                    addr.do_send(CastVote { id, approve: true });
+                }
+            }
+            Phase::Mission => {
+                for &seat in game.team() {
+                    let id = seats[seat].0;
+                    if bots.contains(&id) {
+                        let fail = game.assignment().players[seat].1.alliance() == Alliance::Spy;
+                        addr.do_send(SubmitMission { id, fail });
+                    }
+                }
+            }
+            Phase::Inspect => {
+                let holder = match game.lady_holder() {
+                    Some(seat) if bots.contains(&seats[seat].0) => seat,
+                    _ => return,
+                };
+                let target = (0..seats.len()).find(|seat| !game.lady_holders().contains(seat));
+                if let Some(target) = target {
+                    addr.do_send(InspectLoyalty {
+                        id: seats[holder].0,
+                        target_name: seats[target].1.clone(),
+                    });
+                }
+            }
+            Phase::Assassinate => {
+                // the assassin only knows the spies it sees, it names the
+                // first seat that isn't one of them
+                let assassin = match game.assignment().assassin_seat() {
+                    Some(seat) if bots.contains(&seats[seat].0) => seat,
+                    _ => return,
+                };
+                let spies = match game.assignment().see_from_role(Role::Assassin) {
+                    SeeingBy::Spy(spies) => spies,
+                    _ => Vec::new(),
+                };
+                let target = (0..seats.len())
+                    .find(|seat| *seat != assassin && spies.iter().all(|(spy, _)| spy != seat));
+                if let Some(target) = target {
+                    addr.do_send(Assassinate {
+                        id: seats[assassin].0,
+                        target_name: seats[target].1.clone(),
+                    });
+                }
+            }
+            Phase::Over(_) => {}
+        }
+    }
+
+    /// Remove empty rooms whose grace period has passed
+    fn reap_empty_rooms(&mut self) {
+        let grace = self.config.empty_room_grace;
+        self.rooms.retain(|_, room| match room.emptied_at {
+            Some(emptied_at) => emptied_at.elapsed() < grace,
+            None => true,
+        });
+    }
+
+    /// A bot takes over each seat abandoned longer than `seat_hold_ttl`, so
+    /// the game isn't stuck waiting for it. The seat stays abandoned, it
+    /// can't be taken back and is freed after the game.
+    fn stand_in_expired_seats(&mut self, ctx: &mut Context<Self>) {
+        let ttl = self.config.seat_hold_ttl;
+        let expired: Vec<(String, usize)> = self
+            .rooms
+            .iter()
+            .filter(|(_, room)| room.is_started())
+            .flat_map(|(name, room)| {
+                room.abandoned
+                    .iter()
+                    .filter(move |(seat_no, left_at)| {
+                        left_at.elapsed() >= ttl && !room.bots.contains(&room.seats[**seat_no].0)
+                    })
+                    .map(move |(&seat_no, _)| (name.to_owned(), seat_no))
+            })
+            .collect();
+        let mut stood_in: Vec<(String, usize, String)> = Vec::new();
+        for (name, seat_no) in expired {
+            let bot_id = self.unused_id();
+            if let Some(room) = self.rooms.get_mut(&name) {
+                room.seats[seat_no].0 = bot_id;
+                room.bots.insert(bot_id);
+                room.sessions.insert(bot_id);
+                stood_in.push((name, seat_no, room.seats[seat_no].1.clone()));
+            }
+        }
+        for (room, seat_no, seat_name) in stood_in {
+            info!(
+                "{} a bot stands in for seat {}",
+                LogContext::room(&room),
+                seat_no + 1
+            );
+            self.broadcast_message(
+                &room,
+                &format!(
+                    "seat {} ({}) is played by a bot now",
+                    seat_no + 1,
+                    seat_name
+                ),
+                None,
+            );
+            self.schedule_bots(&room, ctx);
+        }
+    }
+
+    fn remove_user_from_all_rooms(&mut self, id: usize) {
+        self.remove_user_for(id, DisconnectReason::Left)
+    }
+
+    /// Remove the user from all rooms, the rooms are told `reason`
+    fn remove_user_for(&mut self, id: usize, reason: DisconnectReason) {
+        // room name, the seat name and whether the seat is abandoned, that
+        // is the game started
+        let mut removed_rooms: Vec<(String, Option<String>, Option<usize>)> = Vec::new();
+        let mut empty_rooms: Vec<String> = Vec::new();
+        let mut watched_rooms: Vec<String> = Vec::new();
+        // room name and the seat name of the new owner
+        let mut handed_over: Vec<(String, String)> = Vec::new();
+        // remove session from all rooms
+        for (name, room) in &mut self.rooms {
+            if room.spectators.remove(&id) {
+                watched_rooms.push(name.to_owned());
+            }
+            if room.sessions.remove(&id) {
+                let seat_no = room
+                    .seats
+                    .iter()
+                    .position(|&(session_id, _)| session_id == id);
+                let seat_name = seat_no.map(|seat_no| room.seats[seat_no].1.clone());
+                let abandoned = if room.is_started() {
+                    // seats are fixed once roles are dealt, keep the seat to
+                    // leave the game consistent
+                    if let Some(seat_no) = seat_no {
+                        room.abandoned.insert(seat_no, Instant::now());
+                    }
+                    seat_no
+                } else {
+                    room.seats.retain(|&(session_id, _)| session_id != id);
+                    None
+                };
+                removed_rooms.push((name.to_owned(), seat_name, abandoned));
+
+                // the next seated player owns the room, for the room to go
+                // on without the owner
+                if room.owner == id {
+                    let next = room
+                        .seats
+                        .iter()
+                        .find(|&&(session_id, _)| room.can_own(session_id));
+                    if let Some((next_id, next_name)) = next {
+                        room.owner = *next_id;
+                        handed_over.push((name.to_owned(), next_name.clone()));
+                    }
+                }
+
+                // more cautious, in case of new created rooms, bots don't
+                // keep a room alive
+                if room.sessions.is_subset(&room.bots) {
+                    empty_rooms.push(name.to_owned());
+                }
+            }
+        }
+        // clean empty rooms, or keep them for a while if there is a grace
+        // period
+        for room in empty_rooms {
+            if self.config.empty_room_grace.is_zero() {
+                self.rooms.remove(&room);
+            } else if let Some(room) = self.rooms.get_mut(&room) {
+                room.emptied_at = Some(Instant::now());
+            }
+        }
+        // send message to other users
+        for (room, seat_name, abandoned) in removed_rooms {
+            let message = match (reason, seat_name, abandoned) {
+                (DisconnectReason::Left, Some(name), Some(seat_no)) => {
+                    format!("{} left the game, seat {} is abandoned", name, seat_no + 1)
+                }
+                (DisconnectReason::Left, _, _) => "Someone disconnected".to_owned(),
+                (DisconnectReason::Timeout, Some(name), Some(seat_no)) => {
+                    format!("{} timed out, seat {} is abandoned", name, seat_no + 1)
+                }
+                (DisconnectReason::Timeout, Some(name), None) => format!("{} timed out", name),
+                (DisconnectReason::Timeout, None, _) => "Someone timed out".to_owned(),
+            };
+            self.broadcast_message(&room, &message, None);
+        }
+        for (room, owner_name) in handed_over {
+            self.broadcast_message(
+                &room,
+                &format!("{} is the room owner now", owner_name),
+                None,
+            );
+        }
+        for room in watched_rooms {
+            self.broadcast_spectator_count(&room);
+        }
+    }
+
+    //
+    //    /// Send message to all users in the room
+    //    fn send_message_to_all(&self, room: &str, message: &str) {
+    //        if let Some(sessions) = self.rooms.get(room) {
+    //            for id in sessions {
+    //                if let Some(addr) = self.sessions.get(id) {
+    //                    let _ = addr.do_send(Message(message.to_owned()));
+    //                }
+    //            }
+    //        }
+    //    }
+}
+
+/// Client id and the role messages it is told
+type Hint = (usize, Vec<String>);
+
+/// `name`, or with the first free suffix from 2 on if it is already seated
+fn unique_name(seats: &[(usize, String)], name: String) -> String {
+    let taken = |candidate: &str| seats.iter().any(|(_, seated)| seated == candidate);
+    if !taken(&name) {
+        return name;
+    }
+    (2..)
+        .map(|n| format!("{}({})", name, n))
+        .find(|candidate| !taken(candidate))
+        .unwrap_or(name)
+}
+
+/// Who approved and who rejected the team, e.g. `赞成：a、b；反对：c`
+fn vote_breakdown(assignment: &Assignment, votes: &[bool], locale: Locale) -> String {
+    let none = match locale {
+        Locale::Zh => "无",
+        Locale::En => "none",
+    };
+    let names = |approve: bool| {
+        let names: Vec<&str> = votes
+            .iter()
+            .enumerate()
+            .filter(|&(_, &vote)| vote == approve)
+            .filter_map(|(seat, _)| assignment.get_player(seat))
+            .map(|(name, _)| name)
+            .collect();
+        if names.is_empty() {
+            none.to_owned()
+        } else {
+            names.join(locale.list_separator())
+        }
+    };
+    match locale {
+        Locale::Zh => format!("赞成：{}；反对：{}", names(true), names(false)),
+        Locale::En => format!("approved: {}; rejected: {}", names(true), names(false)),
+    }
+}
+
+/// Role of `seat_no` and what the role sees
+fn role_messages(
+    assignment: &Assignment,
+    seat_no: usize,
+    locale: Locale,
+    templates: &Templates,
+) -> Vec<String> {
+    let mut messages = Vec::new();
+    if let Some((_, role)) = assignment.get_player(seat_no) {
+        messages.push(templates.role(role, locale));
+        let assignment_text = assignment
+            .see_from_role(role)
+            .localized_text_from_player(seat_no, locale);
+        if assignment_text.is_empty() {
+            messages.push(templates.no_hint(locale));
+        } else {
+            messages.push(assignment_text);
+        }
+    }
+    messages
+}
+
+/// Make actor from `ChatServer`
+impl Actor for ChatServer {
+    /// We are going to use simple Context, we just need ability to communicate
+    /// with other actors.
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Context<Self>) {
+        if !self.config.empty_room_grace.is_zero() {
+            ctx.run_interval(REAP_INTERVAL, |act, _| act.reap_empty_rooms());
+        }
+        ctx.run_interval(STAND_IN_INTERVAL, |act, ctx| {
+            act.stand_in_expired_seats(ctx)
+        });
+    }
+}
+
+/// Handler for Connect message.
+///
+/// Register new session and assign unique id to this session
+impl Handler<Connect> for ChatServer {
+    type Result = usize;
+
+    fn handle(&mut self, msg: Connect, _: &mut Context<Self>) -> Self::Result {
+        if let Some(max) = self
+            .config
+            .max_sessions
+            .filter(|&max| self.sessions.len() >= max)
+        {
+            warn!("session refused, {} sessions connected already", max);
+            if let Some(ip) = msg.ip {
+                self.release_ip(&ip);
+            }
+            return REFUSED_ID;
+        }
+
+        // register session with random id
+        let id = self.unused_id();
+        self.sessions.insert(id, msg.addr);
+        if let Some(ip) = msg.ip {
+            self.session_ips.insert(id, ip);
+        }
+        debug!("{} connected", LogContext::session(id));
+
+        // send id back
+        id
+    }
+}
+
+/// Handler for Disconnect message.
+impl Handler<Disconnect> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Disconnect, _: &mut Context<Self>) {
+        debug!("{} disconnected", LogContext::session(msg.id));
+
+        if let Some(ip) = self.session_ips.remove(&msg.id) {
+            self.release_ip(&ip);
+        }
+
+        // remove address
+        if self.sessions.remove(&msg.id).is_some() {
+            self.remove_user_for(msg.id, msg.reason)
+        }
+    }
+}
+
+/// Admit a connection unless its IP reached the limit, the session then
+/// connects with the IP to release it on disconnect
+impl Handler<Admit> for ChatServer {
+    type Result = bool;
+
+    fn handle(&mut self, msg: Admit, _: &mut Context<Self>) -> Self::Result {
+        let count = self
+            .connections_per_ip
+            .get(&msg.ip)
+            .cloned()
+            .unwrap_or_default();
+        if self
+            .config
+            .max_connections_per_ip
+            .is_some_and(|max| count >= max)
+        {
+            return false;
+        }
+        self.connections_per_ip.insert(msg.ip, count + 1);
+
+        true
+    }
+}
+
+/// Handler for `ListRooms` message.
+impl Handler<ListRooms> for ChatServer {
+    type Result = MessageResult<ListRooms>;
+
+    fn handle(&mut self, _: ListRooms, _: &mut Context<Self>) -> Self::Result {
         let mut rooms = Vec::new();
 
-        for key in self.rooms.keys() {
-            rooms.push(key.to_owned())
+        for (key, room) in &self.rooms {
+            match room.spectator_count() {
+                0 => rooms.push(key.to_owned()),
+                count => rooms.push(format!("{} (watching: {})", key, count)),
+            }
+        }
+
+        MessageResult(rooms)
+    }
+}
+
+/// Handler for `Limits` message.
+impl Handler<Limits> for ChatServer {
+    type Result = MessageResult<Limits>;
+
+    fn handle(&mut self, _: Limits, _: &mut Context<Self>) -> Self::Result {
+        MessageResult((game::LOWER_ROOM_SIZE, game::UPPER_ROOM_SIZE))
+    }
+}
+
+/// Handler for `Load` message.
+impl Handler<Load> for ChatServer {
+    type Result = MessageResult<Load>;
+
+    fn handle(&mut self, _: Load, _: &mut Context<Self>) -> Self::Result {
+        MessageResult((self.sessions.len(), self.rooms.len()))
+    }
+}
+
+/// Handler for `ListOpenRooms` message.
+impl Handler<ListOpenRooms> for ChatServer {
+    type Result = MessageResult<ListOpenRooms>;
+
+    fn handle(&mut self, _: ListOpenRooms, _: &mut Context<Self>) -> Self::Result {
+        let rooms = self
+            .rooms
+            .iter()
+            .filter(|(_, room)| !room.is_started() && !room.is_full())
+            .map(|(name, _)| name.to_owned())
+            .collect();
+
+        MessageResult(rooms)
+    }
+}
+
+/// Handler for `ListGames` message.
+impl Handler<ListGames> for ChatServer {
+    type Result = MessageResult<ListGames>;
+
+    fn handle(&mut self, _: ListGames, _: &mut Context<Self>) -> Self::Result {
+        let games = self
+            .rooms
+            .iter()
+            .filter_map(|(name, room)| match room.game {
+                Some(ref game) if !matches!(game.phase(), Phase::Over(_)) => {
+                    Some(format!("{}: {}", name, game.scoreboard()))
+                }
+                _ => None,
+            })
+            .collect();
+
+        MessageResult(games)
+    }
+}
+
+/// Join room, send disconnect message to old room
+/// send join message to new room
+impl Handler<Join> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Join, ctx: &mut Context<Self>) {
+        let Join {
+            id,
+            session_name,
+            name,
+        } = msg;
+        let session_name = match self.config.names.validate(&session_name) {
+            Ok(session_name) => session_name,
+            Err(err) => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::InvalidName,
+                    format!("!!! invalid name: {}", err),
+                );
+                return;
+            }
+        };
+
+        match self.rooms.get(&name) {
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::RoomNotFound,
+                    "!!! room not exist".to_owned(),
+                );
+                return;
+            }
+            Some(room) if room.sessions.contains(&id) => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::AlreadyInRoom,
+                    "!!! you are already in this room".to_owned(),
+                );
+                return;
+            }
+            Some(room) if room.is_started() => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::GameStarted,
+                    "!!! game already started".to_owned(),
+                );
+                return;
+            }
+            // commands name the seats, two of a name couldn't be told apart
+            Some(room)
+                if !room.options.suffix_duplicate_names
+                    && room.seats.iter().any(|(_, seated)| *seated == session_name) =>
+            {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::NameTaken,
+                    format!("!!! {} is seated in this room already", session_name),
+                );
+                return;
+            }
+            Some(_) => {}
+        }
+
+        self.remove_user_from_all_rooms(id);
+
+        let requested_name = session_name.clone();
+        let (session_name, is_full) = match self.rooms.get_mut(&name) {
+            Some(room) => {
+                if room.emptied_at.take().is_some() {
+                    // the owner is gone, whoever brings the room back owns it
+                    room.owner = id;
+                }
+                room.sessions.insert(id);
+                let session_name = if room.options.suffix_duplicate_names {
+                    unique_name(&room.seats, session_name)
+                } else {
+                    session_name
+                };
+                room.seats.push((id, session_name.clone()));
+
+                (session_name, room.is_full())
+            }
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::RoomNotFound,
+                    "!!! room not exist, may be deleted just now".to_owned(),
+                );
+                return;
+            }
+        };
+
+        info!("{} joined", LogContext::session(id).in_room(&name));
+        self.broadcast_message(&name, &format!("{} connected", &session_name), Some(id));
+        let reply = if session_name == requested_name {
+            "joined".to_owned()
+        } else {
+            format!("joined as {}", session_name)
+        };
+        self.send_message_to_user(id, reply);
+        self.publish_event(ServerEvent::PlayerJoined {
+            room: name.clone(),
+            name: session_name,
+        });
+        if is_full {
+            self.room_filled(&name, ctx);
+        }
+    }
+}
+
+/// Handler for `TokenStatus` message.
+impl Handler<TokenStatus> for ChatServer {
+    type Result = MessageResult<TokenStatus>;
+
+    fn handle(&mut self, msg: TokenStatus, _: &mut Context<Self>) -> Self::Result {
+        let ttl = self.config.seat_hold_ttl;
+        let left_at = self.rooms.get(&msg.name).and_then(|room| {
+            room.seats
+                .iter()
+                .position(|(_, seat_name)| *seat_name == msg.session_name)
+                .and_then(|seat_no| room.abandoned.get(&seat_no))
+        });
+
+        MessageResult(match left_at {
+            Some(left_at) if left_at.elapsed() < ttl => TokenState::Valid,
+            Some(_) => TokenState::Expired,
+            None => TokenState::Unknown,
+        })
+    }
+}
+
+/// Reclaim a held seat, with its role
+impl Handler<Rejoin> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Rejoin, _: &mut Context<Self>) {
+        let Rejoin {
+            id,
+            session_name,
+            name,
+        } = msg;
+
+        let ttl = self.config.seat_hold_ttl;
+        let seat_no = match self.rooms.get(&name) {
+            Some(room) => room
+                .seats
+                .iter()
+                .position(|(_, seat_name)| *seat_name == session_name)
+                .filter(|seat_no| {
+                    room.abandoned
+                        .get(seat_no)
+                        .is_some_and(|left_at| left_at.elapsed() < ttl)
+                }),
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::RoomNotFound,
+                    "!!! room not exist".to_owned(),
+                );
+                return;
+            }
+        };
+        let seat_no = match seat_no {
+            Some(seat_no) => seat_no,
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::NoSeatHeld,
+                    format!("!!! no seat held for {}", session_name),
+                );
+                return;
+            }
+        };
+
+        self.remove_user_from_all_rooms(id);
+
+        let room = match self.rooms.get_mut(&name) {
+            Some(room) => room,
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::RoomNotFound,
+                    "!!! room not exist, may be deleted just now".to_owned(),
+                );
+                return;
+            }
+        };
+        let old_id = room.seats[seat_no].0;
+        room.seats[seat_no].0 = id;
+        room.abandoned.remove(&seat_no);
+        room.sessions.insert(id);
+        if room.owner == old_id {
+            room.owner = id;
+        }
+
+        info!("{} rejoined", LogContext::session(id).in_room(&name));
+        self.broadcast_message(&name, &format!("{} is back", session_name), Some(id));
+        self.send_message_to_user(id, "rejoined".to_owned());
+        let room = &self.rooms[&name];
+        if let Some(game) = &room.game {
+            self.notify_role(id, game.assignment(), seat_no, room.options.locale);
+        }
+    }
+}
+
+/// Create, send disconnect message to old room
+/// send join message to new room
+impl Handler<Create> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Create, _: &mut Context<Self>) {
+        let Create {
+            id,
+            session_name,
+            size,
+            options,
+            key,
+        } = msg;
+
+        // retried Create, the room is there already
+        if let Some(key) = &key {
+            let created = self.rooms.iter().find(|(_, room)| match &room.create_key {
+                Some((creator, create_key)) => *creator == id && create_key == key,
+                None => false,
+            });
+            if let Some((name, _)) = created {
+                self.send_message_to_user(id, format!("room {} created.", name));
+                return;
+            }
+        }
+
+        let session_name = match self.config.names.validate(&session_name) {
+            Ok(session_name) => session_name,
+            Err(err) => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::InvalidName,
+                    format!("!!! invalid name: {}", err),
+                );
+                return;
+            }
+        };
+        if let Some(max) = self.config.max_rooms_per_owner {
+            if self.rooms_owned_by(id) >= max {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::RoomLimit,
+                    format!("!!! room limit reached, you may own {} rooms at once", max),
+                );
+                return;
+            }
+        }
+        let deck = RoleSet::standard_with(size as usize, &options.deck)
+            .and_then(|deck| deck.is_playable(size as usize).map_err(Error::from));
+        if let Err(err) = deck {
+            self.send_error_to_user(id, ErrorCode::of_error(&err), format!("!!! {}", err));
+            return;
+        }
+        if let Some(min_start) = options.min_start {
+            if !game::legal_player_counts().contains(&(min_start as usize)) || min_start > size {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::InvalidSize,
+                    format!(
+                        "!!! min start {} should be in range {}-{}",
+                        min_start,
+                        game::LOWER_ROOM_SIZE,
+                        size
+                    ),
+                );
+                return;
+            }
+        }
+        let name = match self.random_room_name() {
+            Some(name) => name,
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::CreateFailed,
+                    "!!! create room failed".to_owned(),
+                );
+                return;
+            }
+        };
+
+        self.remove_user_from_all_rooms(id);
+
+        self.send_message_to_user(id, format!("room {} created.", &name));
+        self.send_message_to_user(id, "请把房间号告诉你的小伙伴们".to_owned());
+        let mut room = Room::new(id, session_name, size, options);
+        room.create_key = key.map(|key| (id, key));
+        info!("{} created", LogContext::session(id).in_room(&name));
+        self.rooms.insert(name.clone(), room);
+        self.publish_event(ServerEvent::RoomCreated { room: name });
+    }
+}
+
+/// Resize the room of the owner, the room may become full and start
+impl Handler<Resize> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Resize, ctx: &mut Context<Self>) {
+        let Resize { id, size } = msg;
+
+        let name = match self.room_of_user(id) {
+            Some(name) => name,
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::NotInRoom,
+                    "!!! you are not in a room".to_owned(),
+                );
+                return;
+            }
+        };
+
+        let resized = match self.rooms.get_mut(&name) {
+            Some(room) if room.owner != id => Err((
+                ErrorCode::NotOwner,
+                "!!! only the room owner can resize the room".to_owned(),
+            )),
+            Some(room) if room.is_started() => Err((
+                ErrorCode::GameStarted,
+                "!!! game already started".to_owned(),
+            )),
+            Some(_) if !game::legal_player_counts().contains(&(size as usize)) => Err((
+                ErrorCode::InvalidSize,
+                format!(
+                    "!!! room size {} is not supported. it should be in range {}-{}",
+                    size,
+                    game::LOWER_ROOM_SIZE,
+                    game::UPPER_ROOM_SIZE,
+                ),
+            )),
+            Some(room) if (size as usize) < room.seats.len() => Err((
+                ErrorCode::InvalidSize,
+                format!(
+                    "!!! room size {} is less than {} seated players",
+                    size,
+                    room.seats.len(),
+                ),
+            )),
+            Some(room) if room.options.min_start.is_some_and(|min| size < min) => Err((
+                ErrorCode::InvalidSize,
+                format!(
+                    "!!! room size {} is less than min start {}",
+                    size,
+                    room.min_start(),
+                ),
+            )),
+            Some(room) => {
+                room.size = size;
+                Ok(room.is_full())
+            }
+            None => Err((
+                ErrorCode::RoomNotFound,
+                "!!! room not exist, may be deleted just now".to_owned(),
+            )),
+        };
+
+        match resized {
+            Ok(is_full) => {
+                self.broadcast_message(&name, &format!("room size changed to {}", size), None);
+                if is_full {
+                    self.room_filled(&name, ctx);
+                }
+            }
+            Err((code, err)) => self.send_error_to_user(id, code, err),
+        }
+    }
+}
+
+/// Make the named seat the room owner
+impl Handler<TransferHost> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: TransferHost, _: &mut Context<Self>) {
+        let TransferHost { id, target_name } = msg;
+
+        let name = match self.room_of_user(id) {
+            Some(name) => name,
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::NotInRoom,
+                    "!!! you are not in a room".to_owned(),
+                );
+                return;
+            }
+        };
+
+        let transferred = match self.rooms.get_mut(&name) {
+            Some(room) if room.owner != id => Err((
+                ErrorCode::NotOwner,
+                "!!! only the room owner can transfer the room".to_owned(),
+            )),
+            Some(room) => {
+                match room
+                    .seats
+                    .iter()
+                    .find(|(_, seat_name)| *seat_name == target_name)
+                {
+                    Some(&(target_id, _)) if room.can_own(target_id) => {
+                        room.owner = target_id;
+                        Ok(())
+                    }
+                    Some(_) => Err((
+                        ErrorCode::CannotOwn,
+                        format!("!!! {} can't own the room", target_name),
+                    )),
+                    None => Err((
+                        ErrorCode::UnknownPlayer,
+                        format!("!!! {} is not seated in this room", target_name),
+                    )),
+                }
+            }
+            None => Err((
+                ErrorCode::RoomNotFound,
+                "!!! room not exist, may be deleted just now".to_owned(),
+            )),
+        };
+
+        match transferred {
+            Ok(()) => self.broadcast_message(
+                &name,
+                &format!("{} is the room owner now", target_name),
+                None,
+            ),
+            Err((code, err)) => self.send_error_to_user(id, code, err),
+        }
+    }
+}
+
+/// Handler for Leave message.
+impl Handler<Leave> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Leave, _: &mut Context<Self>) {
+        if self.room_of_user(msg.id).is_none() && self.room_watched_by(msg.id).is_none() {
+            self.send_error_to_user(
+                msg.id,
+                ErrorCode::NotInRoom,
+                "!!! you are not in a room".to_owned(),
+            );
+            return;
+        }
+
+        self.remove_user_from_all_rooms(msg.id);
+        self.send_message_to_user(msg.id, "left".to_owned());
+    }
+}
+
+/// Watch the room, the seat taken elsewhere is left
+impl Handler<Watch> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Watch, _: &mut Context<Self>) {
+        let Watch { id, name } = msg;
+
+        match self.rooms.get(&name) {
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::RoomNotFound,
+                    "!!! room not exist".to_owned(),
+                );
+                return;
+            }
+            Some(room) if room.sessions.contains(&id) || room.spectators.contains(&id) => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::AlreadyInRoom,
+                    "!!! you are already in this room".to_owned(),
+                );
+                return;
+            }
+            Some(_) => {}
+        }
+
+        self.remove_user_from_all_rooms(id);
+
+        match self.rooms.get_mut(&name) {
+            Some(room) => {
+                room.spectators.insert(id);
+            }
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::RoomNotFound,
+                    "!!! room not exist, may be deleted just now".to_owned(),
+                );
+                return;
+            }
+        }
+
+        self.send_message_to_user(id, format!("watching room {}", name));
+        self.broadcast_spectator_count(&name);
+    }
+}
+
+/// Handler for Overflow message.
+impl Handler<Overflow> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Overflow, _: &mut Context<Self>) {
+        if let Err((code, err)) = self.overflow(msg.id) {
+            self.send_error_to_user(msg.id, code, err);
+        }
+    }
+}
+
+/// Handler for SetRevealOnEnd message.
+impl Handler<SetRevealOnEnd> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetRevealOnEnd, _: &mut Context<Self>) {
+        let SetRevealOnEnd { id, enabled } = msg;
+
+        let name = match self.room_of_user(id) {
+            Some(name) => name,
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::NotInRoom,
+                    "!!! you are not in a room".to_owned(),
+                );
+                return;
+            }
+        };
+
+        let set = match self.rooms.get_mut(&name) {
+            Some(room) if room.owner != id => Err((
+                ErrorCode::NotOwner,
+                "!!! only the room owner can change it".to_owned(),
+            )),
+            Some(room) => {
+                room.options.reveal_on_end = enabled;
+                Ok(())
+            }
+            None => Err((
+                ErrorCode::RoomNotFound,
+                "!!! room not exist, may be deleted just now".to_owned(),
+            )),
+        };
+
+        match set {
+            Ok(()) => {
+                let state = if enabled { "on" } else { "off" };
+                self.broadcast_message(&name, &format!("reveal on end: {}", state), None);
+            }
+            Err((code, err)) => self.send_error_to_user(id, code, err),
+        }
+    }
+}
+
+/// Seat a bot, the game starts once the room is full
+impl Handler<AddBot> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: AddBot, ctx: &mut Context<Self>) {
+        let AddBot { id } = msg;
+
+        let name = match self.room_of_user(id) {
+            Some(name) => name,
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::NotInRoom,
+                    "!!! you are not in a room".to_owned(),
+                );
+                return;
+            }
+        };
+
+        let bot_id = self.unused_id();
+        let added = match self.rooms.get_mut(&name) {
+            Some(room) if room.owner != id => Err((
+                ErrorCode::NotOwner,
+                "!!! only the room owner can add bots".to_owned(),
+            )),
+            Some(room) if room.is_started() => Err((
+                ErrorCode::GameStarted,
+                "!!! game already started".to_owned(),
+            )),
+            Some(room) if room.is_full() => {
+                Err((ErrorCode::RoomFull, "!!! room is full".to_owned()))
+            }
+            Some(room) => {
+                let bot_name = (1..)
+                    .map(|n| format!("bot{}", n))
+                    .find(|bot_name| room.seats.iter().all(|(_, name)| name != bot_name))
+                    .unwrap_or_default();
+                room.bots.insert(bot_id);
+                room.sessions.insert(bot_id);
+                room.seats.push((bot_id, bot_name.clone()));
+                Ok((bot_name, room.is_full()))
+            }
+            None => Err((
+                ErrorCode::RoomNotFound,
+                "!!! room not exist, may be deleted just now".to_owned(),
+            )),
+        };
+
+        match added {
+            Ok((bot_name, is_full)) => {
+                self.broadcast_message(&name, &format!("{} connected", bot_name), None);
+                self.publish_event(ServerEvent::PlayerJoined {
+                    room: name.clone(),
+                    name: bot_name,
+                });
+                if is_full {
+                    self.room_filled(&name, ctx);
+                }
+            }
+            Err((code, err)) => self.send_error_to_user(id, code, err),
+        }
+    }
+}
+
+/// Freeze or unfreeze the game, game actions are rejected while paused
+impl Handler<SetPaused> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetPaused, ctx: &mut Context<Self>) {
+        let SetPaused { id, paused } = msg;
+
+        let name = match self.room_of_user(id) {
+            Some(name) => name,
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::NotInRoom,
+                    "!!! you are not in a room".to_owned(),
+                );
+                return;
+            }
+        };
+
+        let set = match self.rooms.get_mut(&name) {
+            Some(room) if room.owner != id => Err((
+                ErrorCode::NotOwner,
+                "!!! only the room owner can change it".to_owned(),
+            )),
+            Some(Room {
+                game: Some(game), ..
+            }) => game
+                .set_paused(paused)
+                .map_err(|err| (ErrorCode::of_error(&err), format!("!!! {}", err))),
+            Some(_) => Err((ErrorCode::GameNotStarted, "!!! game not started".to_owned())),
+            None => Err((
+                ErrorCode::RoomNotFound,
+                "!!! room not exist, may be deleted just now".to_owned(),
+            )),
+        };
+
+        match set {
+            Ok(()) => {
+                let state = if paused { "paused" } else { "resumed" };
+                self.broadcast_message(&name, &format!("game {}", state), None);
+                if !paused {
+                    self.schedule_bots(&name, ctx);
+                }
+            }
+            Err((code, err)) => self.send_error_to_user(id, code, err),
+        }
+    }
+}
+
+/// Drop the game and the seats abandoned in it, the others stay seated
+impl Handler<AbortGame> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: AbortGame, _: &mut Context<Self>) {
+        let AbortGame { id } = msg;
+
+        let name = match self.room_of_user(id) {
+            Some(name) => name,
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::NotInRoom,
+                    "!!! you are not in a room".to_owned(),
+                );
+                return;
+            }
+        };
+
+        let aborted = match self.rooms.get_mut(&name) {
+            Some(room) if room.owner != id => Err((
+                ErrorCode::NotOwner,
+                "!!! only the room owner can abort the game".to_owned(),
+            )),
+            Some(Room { game: None, .. }) => {
+                Err((ErrorCode::GameNotStarted, "!!! game not started".to_owned()))
+            }
+            Some(room) => {
+                room.reset_to_lobby();
+                Ok(())
+            }
+            None => Err((
+                ErrorCode::RoomNotFound,
+                "!!! room not exist, may be deleted just now".to_owned(),
+            )),
+        };
+
+        match aborted {
+            Ok(()) => {
+                info!("{} game aborted", LogContext::room(&name));
+                self.broadcast_message(&name, "game aborted by the room owner", None);
+            }
+            Err((code, err)) => self.send_error_to_user(id, code, err),
+        }
+    }
+}
+
+/// Chat goes to the whole room, unless the speaker is muted
+impl Handler<ChatText> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: ChatText, _: &mut Context<Self>) {
+        let ChatText { id, text } = msg;
+
+        let name = match self.room_of_user(id) {
+            Some(name) => name,
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::NotInRoom,
+                    "!!! you are not in a room".to_owned(),
+                );
+                return;
+            }
+        };
+        let text = match validate_chat(&text) {
+            Ok(text) => text,
+            Err(err) => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::InvalidCommand,
+                    format!("!!! invalid text: {}", err),
+                );
+                return;
+            }
+        };
+
+        let speaker = match self.rooms.get(&name) {
+            Some(Room { seats, muted, .. }) => {
+                match seats.iter().find(|&&(seat_id, _)| seat_id == id) {
+                    Some((_, speaker)) if muted.contains(speaker) => Err((
+                        ErrorCode::Muted,
+                        "!!! you are muted by the room owner".to_owned(),
+                    )),
+                    Some((_, speaker)) => Ok(speaker.clone()),
+                    None => Err((ErrorCode::NotSeated, "!!! you are not seated".to_owned())),
+                }
+            }
+            None => Err((
+                ErrorCode::RoomNotFound,
+                "!!! room not exist, may be deleted just now".to_owned(),
+            )),
+        };
+
+        match speaker {
+            Ok(speaker) => self.broadcast_message(&name, &format!("{}: {}", speaker, text), None),
+            Err((code, err)) => self.send_error_to_user(id, code, err),
+        }
+    }
+}
+
+/// Add the seat to the muted set of the room, or remove it
+impl Handler<Mute> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Mute, _: &mut Context<Self>) {
+        let Mute {
+            id,
+            target_name,
+            muted,
+        } = msg;
+
+        let name = match self.room_of_user(id) {
+            Some(name) => name,
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::NotInRoom,
+                    "!!! you are not in a room".to_owned(),
+                );
+                return;
+            }
+        };
+
+        let target = match self.rooms.get_mut(&name) {
+            Some(room) if room.owner != id => Err((
+                ErrorCode::NotOwner,
+                "!!! only the room owner can change it".to_owned(),
+            )),
+            Some(room) => match room.seats.iter().find(|(_, name)| *name == target_name) {
+                Some(&(target, _)) => {
+                    if muted {
+                        room.muted.insert(target_name.clone());
+                    } else {
+                        room.muted.remove(&target_name);
+                    }
+                    Ok(target)
+                }
+                None => Err((
+                    ErrorCode::UnknownPlayer,
+                    format!("!!! {} is not seated in this room", target_name),
+                )),
+            },
+            None => Err((
+                ErrorCode::RoomNotFound,
+                "!!! room not exist, may be deleted just now".to_owned(),
+            )),
+        };
+
+        match target {
+            Ok(target) => {
+                let state = if muted { "muted" } else { "unmuted" };
+                self.send_message_to_user(id, format!("{} {}", target_name, state));
+                self.send_message_to_user(target, format!("you are {} by the room owner", state));
+            }
+            Err((code, err)) => self.send_error_to_user(id, code, err),
+        }
+    }
+}
+
+/// Handler for Stats message.
+impl Handler<Stats> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Stats, _: &mut Context<Self>) {
+        let Stats { id, name } = msg;
+
+        let stats = match self.outcome_sink.as_ref() {
+            Some(sink) => sink.stats(&name),
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::StatsUnavailable,
+                    "!!! stats are not recorded".to_owned(),
+                );
+                return;
+            }
+        };
+        self.send_message_to_user(
+            id,
+            format!(
+                "games: {}, wins: {}, {}: {}/{}, {}: {}/{}",
+                stats.played,
+                stats.wins,
+                game::Alliance::Resistance,
+                stats.resistance.1,
+                stats.resistance.0,
+                game::Alliance::Spy,
+                stats.spy.1,
+                stats.spy.0,
+            ),
+        );
+    }
+}
+
+/// Reply the name of the room the user is in
+impl Handler<RoomCode> for ChatServer {
+    type Result = Option<String>;
+
+    fn handle(&mut self, msg: RoomCode, _: &mut Context<Self>) -> Self::Result {
+        self.room_of_user(msg.id)
+    }
+}
+
+impl Handler<Roster> for ChatServer {
+    type Result = Option<Vec<RosterEntry>>;
+
+    fn handle(&mut self, msg: Roster, _: &mut Context<Self>) -> Self::Result {
+        let room = self
+            .room_of_user(msg.id)
+            .and_then(|name| self.rooms.get(&name))?;
+        let roster = room
+            .seats
+            .iter()
+            .enumerate()
+            .map(|(seat, (id, name))| RosterEntry {
+                seat,
+                name: name.clone(),
+                ready: room.ready.contains(id) || room.bots.contains(id),
+                owner: room.owner == *id,
+            })
+            .collect();
+        Some(roster)
+    }
+}
+
+impl Handler<RoomElapsed> for ChatServer {
+    type Result = Option<Duration>;
+
+    fn handle(&mut self, msg: RoomElapsed, _: &mut Context<Self>) -> Self::Result {
+        self.room_of_user(msg.id)
+            .and_then(|name| self.rooms.get(&name))
+            .map(|room| room.created_at.elapsed())
+    }
+}
+
+/// Handler for Authenticate message.
+impl Handler<Authenticate> for ChatServer {
+    type Result = bool;
+
+    fn handle(&mut self, msg: Authenticate, _: &mut Context<Self>) -> Self::Result {
+        self.config.admin_token.as_ref() == Some(&msg.token)
+    }
+}
+
+/// Handler for CloseRoom message.
+impl Handler<CloseRoom> for ChatServer {
+    type Result = bool;
+
+    fn handle(&mut self, msg: CloseRoom, _: &mut Context<Self>) -> Self::Result {
+        let CloseRoom { name } = msg;
+
+        if !self.rooms.contains_key(&name) {
+            return false;
+        }
+        info!("{} closed by admin", LogContext::room(&name));
+        self.broadcast_message(&name, &format!("room {} is closed by admin", name), None);
+        self.rooms.remove(&name);
+        true
+    }
+}
+
+/// Send the role and hint again to the user
+impl Handler<WhoAmI> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: WhoAmI, _: &mut Context<Self>) {
+        let WhoAmI { id } = msg;
+
+        let room = match self.room_of_user(id).and_then(|name| self.rooms.get(&name)) {
+            Some(room) => room,
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::NotInRoom,
+                    "!!! you are not in a room".to_owned(),
+                );
+                return;
+            }
+        };
+        let assignment = match room.game {
+            Some(ref game) => game.assignment(),
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::GameNotStarted,
+                    "!!! game not started".to_owned(),
+                );
+                return;
+            }
+        };
+        if let Some(seat_no) = room.seats.iter().position(|&(seat_id, _)| seat_id == id) {
+            self.notify_role(id, assignment, seat_no, room.options.locale);
+        }
+    }
+}
+
+/// Broadcast the role of the user, kept as an event of the game
+impl Handler<RevealSelf> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: RevealSelf, _: &mut Context<Self>) {
+        let RevealSelf { id } = msg;
+
+        let name = match self.room_of_user(id) {
+            Some(name) => name,
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::NotInRoom,
+                    "!!! you are not in a room".to_owned(),
+                );
+                return;
+            }
+        };
+
+        let revealed = match self.rooms.get(&name) {
+            Some(Room { game: None, .. }) => {
+                Err((ErrorCode::GameNotStarted, "!!! game not started".to_owned()))
+            }
+            Some(Room {
+                game: Some(game),
+                seats,
+                options,
+                ..
+            }) => seats
+                .iter()
+                .position(|&(seat_id, _)| seat_id == id)
+                .and_then(|seat_no| game.assignment().get_player(seat_no))
+                .map(|(player, role)| {
+                    let role = role.localized_name(options.locale);
+                    match options.locale {
+                        Locale::Zh => format!("{} 亮明身份：【{}】", player, role),
+                        Locale::En => format!("{} reveals being [{}]", player, role),
+                    }
+                })
+                .ok_or_else(|| (ErrorCode::NotSeated, "!!! you are not seated".to_owned())),
+            None => Err((
+                ErrorCode::RoomNotFound,
+                "!!! room not exist, may be deleted just now".to_owned(),
+            )),
+        };
+
+        match revealed {
+            Ok(message) => {
+                info!(
+                    "{} session {} revealed the role",
+                    LogContext::room(&name),
+                    id
+                );
+                self.broadcast_event(&name, message);
+            }
+            Err((code, err)) => self.send_error_to_user(id, code, err),
+        }
+    }
+}
+
+/// Reply the deck of the room, it's public setup
+impl Handler<RoomDeck> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: RoomDeck, _: &mut Context<Self>) {
+        let RoomDeck { id } = msg;
+
+        let room = match self.room_of_user(id).and_then(|name| self.rooms.get(&name)) {
+            Some(room) => room,
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::NotInRoom,
+                    "!!! you are not in a room".to_owned(),
+                );
+                return;
+            }
+        };
+        match RoleSet::standard_with(room.size as usize, &room.options.deck) {
+            Ok(deck) => self.send_message_to_user(
+                id,
+                format!("deck: {}", deck.composition(room.options.locale)),
+            ),
+            Err(err) => {
+                self.send_error_to_user(id, ErrorCode::of_error(&err), format!("!!! {}", err))
+            }
+        }
+    }
+}
+
+/// Permute the seats of a waiting room and tell everyone the new order
+impl Handler<ShuffleSeats> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: ShuffleSeats, _: &mut Context<Self>) {
+        let ShuffleSeats { id } = msg;
+
+        let name = match self.room_of_user(id) {
+            Some(name) => name,
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::NotInRoom,
+                    "!!! you are not in a room".to_owned(),
+                );
+                return;
+            }
+        };
+        match self.rooms.get(&name) {
+            Some(room) if room.owner != id => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::NotOwner,
+                    "!!! only the room owner can shuffle seats".to_owned(),
+                );
+                return;
+            }
+            Some(room) if room.is_started() => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::GameStarted,
+                    "!!! game already started".to_owned(),
+                );
+                return;
+            }
+            Some(_) => (),
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::RoomNotFound,
+                    "!!! room not exist, may be deleted just now".to_owned(),
+                );
+                return;
+            }
+        }
+
+        self.permute_seats(&name);
+        let order: Vec<&str> = self.rooms[&name]
+            .seats
+            .iter()
+            .map(|(_, name)| name.as_str())
+            .collect();
+        let order = order.join(", ");
+        self.broadcast_message(&name, &format!("seats shuffled: {}", order), None);
+    }
+}
+
+/// Reply a sample deal for the room size, drawn from its own rng so that
+/// neither the room nor the real deal is touched
+impl Handler<PreviewDeal> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: PreviewDeal, _: &mut Context<Self>) {
+        let PreviewDeal { id } = msg;
+
+        let room = match self.room_of_user(id).and_then(|name| self.rooms.get(&name)) {
+            Some(room) => room,
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::NotInRoom,
+                    "!!! you are not in a room".to_owned(),
+                );
+                return;
+            }
+        };
+        if room.owner != id {
+            self.send_error_to_user(
+                id,
+                ErrorCode::NotOwner,
+                "!!! only the room owner can preview".to_owned(),
+            );
+            return;
+        }
+        if room.is_started() {
+            self.send_error_to_user(
+                id,
+                ErrorCode::GameStarted,
+                "!!! game already started".to_owned(),
+            );
+            return;
+        }
+
+        let locale = room.options.locale;
+        let preview =
+            RoleSet::standard_with(room.size as usize, &room.options.deck).and_then(|deck| {
+                let roles = deck.deal(
+                    room.size as usize,
+                    &room.options.rules,
+                    &mut rand::thread_rng(),
+                )?;
+                let sample: Vec<String> = roles
+                    .iter()
+                    .enumerate()
+                    .map(|(seat_no, role)| {
+                        format!("#{} {}", seat_no + 1, role.localized_name(locale))
+                    })
+                    .collect();
+                Ok(format!(
+                    "preview: {}\nsample: {}",
+                    deck.composition(locale),
+                    sample.join(", ")
+                ))
+            });
+        match preview {
+            Ok(preview) => self.send_message_to_user(id, preview),
+            Err(err) => {
+                self.send_error_to_user(id, ErrorCode::of_error(&err), format!("!!! {}", err))
+            }
+        }
+    }
+}
+
+/// Reply the description of every role in the deck, in the room language
+impl Handler<RoomRules> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: RoomRules, _: &mut Context<Self>) {
+        let RoomRules { id } = msg;
+
+        let room = match self.room_of_user(id).and_then(|name| self.rooms.get(&name)) {
+            Some(room) => room,
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::NotInRoom,
+                    "!!! you are not in a room".to_owned(),
+                );
+                return;
+            }
+        };
+        let locale = room.options.locale;
+        match RoleSet::standard_with(room.size as usize, &room.options.deck) {
+            Ok(deck) => {
+                let rules: Vec<String> = game::all_roles()
+                    .iter()
+                    .filter(|role| deck.roles().contains(role))
+                    .map(|role| {
+                        let name = role.localized_name(locale);
+                        let description = role.localized_description(locale);
+                        match locale {
+                            Locale::Zh => format!("{}：{}", name, description),
+                            Locale::En => format!("{}: {}", name, description),
+                        }
+                    })
+                    .collect();
+                self.send_message_to_user(id, rules.join("\n"));
+            }
+            Err(err) => {
+                self.send_error_to_user(id, ErrorCode::of_error(&err), format!("!!! {}", err))
+            }
+        }
+    }
+}
+
+/// Reply the scoreboard of the game, it tells nothing about roles
+impl Handler<GameStatus> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: GameStatus, _: &mut Context<Self>) {
+        let GameStatus { id } = msg;
+
+        match self.room_of_user(id).and_then(|name| self.rooms.get(&name)) {
+            Some(Room {
+                game: Some(game), ..
+            }) => self.send_message_to_user(id, game.scoreboard()),
+            Some(_) => self.send_error_to_user(
+                id,
+                ErrorCode::GameNotStarted,
+                "!!! game not started".to_owned(),
+            ),
+            None => self.send_error_to_user(
+                id,
+                ErrorCode::NotInRoom,
+                "!!! you are not in a room".to_owned(),
+            ),
+        }
+    }
+}
+
+/// Reply the number of seats left before the room is full
+impl Handler<SeatsNeeded> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: SeatsNeeded, _: &mut Context<Self>) {
+        let SeatsNeeded { id } = msg;
+
+        let room = match self.room_of_user(id).and_then(|name| self.rooms.get(&name)) {
+            Some(room) => room,
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::NotInRoom,
+                    "!!! you are not in a room".to_owned(),
+                );
+                return;
+            }
+        };
+        if room.is_started() {
+            self.send_error_to_user(
+                id,
+                ErrorCode::GameStarted,
+                "!!! game already started".to_owned(),
+            );
+            return;
+        }
+        let (size, seated) = (room.size as usize, room.seats.len());
+        let message = if seated > size {
+            format!("over capacity by {} players, /overflow them", seated - size)
+        } else {
+            format!("{} more players needed", size.saturating_sub(seated))
+        };
+        self.send_message_to_user(id, message);
+    }
+}
+
+/// Mark the seat ready, and deal if it was the last one in a full room
+impl Handler<Ready> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Ready, ctx: &mut Context<Self>) {
+        let Ready { id } = msg;
+
+        let name = match self.room_of_user(id) {
+            Some(name) => name,
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::NotInRoom,
+                    "!!! you are not in a room".to_owned(),
+                );
+                return;
+            }
+        };
+        let ready = match self.rooms.get_mut(&name) {
+            Some(room) if room.is_started() => Err((
+                ErrorCode::GameStarted,
+                "!!! game already started".to_owned(),
+            )),
+            Some(room) => match room.seats.iter().find(|&&(seat_id, _)| seat_id == id) {
+                Some((_, seat_name)) => {
+                    let seat_name = seat_name.clone();
+                    room.ready.insert(id);
+                    let count = room
+                        .seats
+                        .iter()
+                        .filter(|(seat_id, _)| room.ready.contains(seat_id))
+                        .count();
+                    let start = room.is_full() && room.all_ready();
+                    Ok((
+                        format!("{} is ready ({}/{})", seat_name, count, room.size),
+                        start,
+                    ))
+                }
+                None => Err((ErrorCode::NotSeated, "!!! you are not seated".to_owned())),
+            },
+            None => Err((
+                ErrorCode::RoomNotFound,
+                "!!! room not exist, may be deleted just now".to_owned(),
+            )),
+        };
+
+        match ready {
+            Ok((message, start)) => {
+                self.broadcast_message(&name, &message, None);
+                let options = &self.rooms[&name].options;
+                if start && options.require_ready && !options.manual_start {
+                    self.start_game(&name, ctx);
+                }
+            }
+            Err((code, err)) => self.send_error_to_user(id, code, err),
+        }
+    }
+}
+
+/// Start the game with the seats taken so far
+impl Handler<Start> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Start, ctx: &mut Context<Self>) {
+        let Start { id } = msg;
+
+        let name = match self.room_of_user(id) {
+            Some(name) => name,
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::NotInRoom,
+                    "!!! you are not in a room".to_owned(),
+                );
+                return;
+            }
+        };
+        let started = match self.rooms.get_mut(&name) {
+            Some(room) if room.owner != id => Err((
+                ErrorCode::NotOwner,
+                "!!! only the room owner can start the game".to_owned(),
+            )),
+            Some(room) if room.is_started() => Err((
+                ErrorCode::GameStarted,
+                "!!! game already started".to_owned(),
+            )),
+            Some(room) if room.seats.len() < room.min_start() => Err((
+                ErrorCode::NotEnoughPlayers,
+                format!(
+                    "!!! {} more players needed to start",
+                    room.min_start() - room.seats.len()
+                ),
+            )),
+            Some(room) if room.seats.len() > room.size as usize => Err((
+                ErrorCode::OverCapacity,
+                format!(
+                    "!!! {} players over the room size, /overflow them first",
+                    room.seats.len() - room.size as usize
+                ),
+            )),
+            Some(_) => Ok(()),
+            None => Err((
+                ErrorCode::RoomNotFound,
+                "!!! room not exist, may be deleted just now".to_owned(),
+            )),
+        };
+
+        match started {
+            Ok(()) => self.start_game(&name, ctx),
+            Err((code, err)) => self.send_error_to_user(id, code, err),
+        }
+    }
+}
+
+/// Put the team of the leader to the vote
+impl Handler<ProposeTeam> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: ProposeTeam, ctx: &mut Context<Self>) {
+        let ProposeTeam { id, names } = msg;
+
+        let name = match self.room_of_user(id) {
+            Some(name) => name,
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::NotInRoom,
+                    "!!! you are not in a room".to_owned(),
+                );
+                return;
+            }
+        };
+
+        let proposed = match self.rooms.get_mut(&name) {
+            Some(Room {
+                seats,
+                game: Some(game),
+                ..
+            }) => {
+                let seat = seats.iter().position(|&(seat_id, _)| seat_id == id);
+                let team: Result<Vec<usize>, (ErrorCode, String)> = names
+                    .iter()
+                    .map(|target| {
+                        seats
+                            .iter()
+                            .position(|(_, seat_name)| seat_name == target)
+                            .ok_or_else(|| {
+                                (
+                                    ErrorCode::UnknownPlayer,
+                                    format!("!!! {} is not seated in this room", target),
+                                )
+                            })
+                    })
+                    .collect();
+                team.and_then(|team| match seat {
+                    Some(seat) => game
+                        .propose_team(seat, team)
+                        .map_err(|err| (ErrorCode::of_error(&err), format!("!!! {}", err))),
+                    None => Err((ErrorCode::NotSeated, "!!! you are not seated".to_owned())),
+                })
+            }
+            Some(_) => Err((ErrorCode::GameNotStarted, "!!! game not started".to_owned())),
+            None => Err((
+                ErrorCode::RoomNotFound,
+                "!!! room not exist, may be deleted just now".to_owned(),
+            )),
+        };
+
+        match proposed {
+            Ok(()) => {
+                let locale = self.locale_of(&name);
+                let names = names.join(locale.list_separator());
+                let message = match locale {
+                    Locale::Zh => format!(
+                        "队长提名了 {}，请用 /vote approve 或 /vote reject 投票",
+                        names
+                    ),
+                    Locale::En => format!(
+                        "the leader proposes {}, /vote approve or /vote reject",
+                        names
+                    ),
+                };
+                self.broadcast_event(&name, message);
+                self.schedule_bots(&name, ctx);
+            }
+            Err((code, err)) => self.send_error_to_user(id, code, err),
+        }
+    }
+}
+
+/// Collect votes, the result is told once everyone voted
+impl Handler<CastVote> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: CastVote, ctx: &mut Context<Self>) {
+        let CastVote { id, approve } = msg;
+
+        let name = match self.room_of_user(id) {
+            Some(name) => name,
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::NotInRoom,
+                    "!!! you are not in a room".to_owned(),
+                );
+                return;
+            }
+        };
+
+        let voted = match self.rooms.get_mut(&name) {
+            Some(Room {
+                seats,
+                game: Some(game),
+                options,
+                ..
+            }) => match seats.iter().position(|&(seat_id, _)| seat_id == id) {
+                Some(seat) => game
+                    .cast_vote(seat, approve)
+                    .map(|tally| {
+                        tally.map(|tally| {
+                            let breakdown = Some(&tally.votes)
+                                .filter(|_| !options.secret_votes)
+                                .map(|votes| {
+                                    vote_breakdown(game.assignment(), votes, options.locale)
+                                });
+                            (tally, breakdown, game.reject_streak(), game.phase())
+                        })
+                    })
+                    .map_err(|err| (ErrorCode::of_error(&err), format!("!!! {}", err))),
+                None => Err((ErrorCode::NotSeated, "!!! you are not seated".to_owned())),
+            },
+            Some(_) => Err((ErrorCode::GameNotStarted, "!!! game not started".to_owned())),
+            None => Err((
+                ErrorCode::RoomNotFound,
+                "!!! room not exist, may be deleted just now".to_owned(),
+            )),
+        };
+
+        match voted {
+            Ok(Some((tally, breakdown, streak, phase))) => {
+                let locale = self.locale_of(&name);
+                let message = match (locale, tally.approved()) {
+                    (Locale::Zh, approved) => format!(
+                        "{}：{} 票赞成，{} 票反对，已连续否决 {} 次",
+                        if approved {
+                            "投票通过"
+                        } else {
+                            "投票否决"
+                        },
+                        tally.approvals,
+                        tally.rejections,
+                        streak
+                    ),
+                    (Locale::En, approved) => format!(
+                        "team {}: {} approved, {} rejected, {} rejected in a row",
+                        if approved { "approved" } else { "rejected" },
+                        tally.approvals,
+                        tally.rejections,
+                        streak
+                    ),
+                };
+                self.broadcast_event(&name, message);
+                if let Some(breakdown) = breakdown {
+                    self.broadcast_event(&name, breakdown);
+                }
+                match phase {
+                    Phase::Over(_) => {
+                        let message = match locale {
+                            Locale::Zh => format!("连续 {} 次否决，坏人获胜", game::MAX_REJECTS),
+                            Locale::En => {
+                                format!("{} rejected in a row, evil wins", game::MAX_REJECTS)
+                            }
+                        };
+                        self.broadcast_event(&name, message);
+                        self.finish_game(&name);
+                    }
+                    Phase::Propose => self.announce_leader(&name),
+                    Phase::Mission => self.announce_team(&name),
+                    _ => (),
+                }
+                self.schedule_bots(&name, ctx);
+            }
+            Ok(None) => self.send_message_to_user(id, "voted".to_owned()),
+            Err((code, err)) => self.send_error_to_user(id, code, err),
+        }
+    }
+}
+
+/// Collect mission cards of the team, only the counts are told
+impl Handler<SubmitMission> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: SubmitMission, ctx: &mut Context<Self>) {
+        let SubmitMission { id, fail } = msg;
+
+        let name = match self.room_of_user(id) {
+            Some(name) => name,
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::NotInRoom,
+                    "!!! you are not in a room".to_owned(),
+                );
+                return;
+            }
+        };
+
+        let submitted = match self.rooms.get_mut(&name) {
+            Some(Room {
+                seats,
+                game: Some(game),
+                ..
+            }) => match seats.iter().position(|&(seat_id, _)| seat_id == id) {
+                Some(seat) => game
+                    .submit_mission(seat, fail)
+                    .map(|result| result.map(|result| (result, game.phase())))
+                    .map_err(|err| (ErrorCode::of_error(&err), format!("!!! {}", err))),
+                None => Err((ErrorCode::NotSeated, "!!! you are not seated".to_owned())),
+            },
+            Some(_) => Err((ErrorCode::GameNotStarted, "!!! game not started".to_owned())),
+            None => Err((
+                ErrorCode::RoomNotFound,
+                "!!! room not exist, may be deleted just now".to_owned(),
+            )),
+        };
+
+        match submitted {
+            Ok(Some((result, phase))) => {
+                let locale = self.locale_of(&name);
+                let message = match locale {
+                    Locale::Zh => format!(
+                        "{}：{} 张成功，{} 张失败",
+                        if result.passed {
+                            "任务成功"
+                        } else {
+                            "任务失败"
+                        },
+                        result.successes,
+                        result.fails
+                    ),
+                    Locale::En => format!(
+                        "mission {}: {} successes, {} fails",
+                        if result.passed { "passed" } else { "failed" },
+                        result.successes,
+                        result.fails
+                    ),
+                };
+                self.broadcast_event(&name, message);
+                match phase {
+                    Phase::Over(winner) => {
+                        let winner = winner.localized_name(locale);
+                        let message = match locale {
+                            Locale::Zh => format!(
+                                "{}赢下了 {} 个任务，{}获胜",
+                                winner,
+                                game::MISSIONS_TO_WIN,
+                                winner
+                            ),
+                            Locale::En => format!(
+                                "{} won {} missions, {} wins",
+                                winner,
+                                game::MISSIONS_TO_WIN,
+                                winner
+                            ),
+                        };
+                        self.broadcast_event(&name, message);
+                        self.finish_game(&name);
+                    }
+                    Phase::Assassinate => {
+                        let message = match locale {
+                            Locale::Zh => format!(
+                                "好人赢下了 {} 个任务，请刺客用 /assassinate <名字> 刺杀梅林",
+                                game::MISSIONS_TO_WIN
+                            ),
+                            Locale::En => format!(
+                                "the resistance won {} missions, the assassin may /assassinate <name> merlin",
+                                game::MISSIONS_TO_WIN
+                            ),
+                        };
+                        self.broadcast_event(&name, message)
+                    }
+                    Phase::Inspect => self.announce_lady(&name),
+                    Phase::Propose => self.announce_leader(&name),
+                    _ => (),
+                }
+                self.schedule_bots(&name, ctx);
+            }
+            Ok(None) => self.send_message_to_user(id, "submitted".to_owned()),
+            Err((code, err)) => self.send_error_to_user(id, code, err),
+        }
+    }
+}
+
+/// Only the seat holding the assassin may guess, the game ends either way
+impl Handler<Assassinate> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Assassinate, _: &mut Context<Self>) {
+        let Assassinate { id, target_name } = msg;
+
+        let name = match self.room_of_user(id) {
+            Some(name) => name,
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::NotInRoom,
+                    "!!! you are not in a room".to_owned(),
+                );
+                return;
+            }
+        };
+
+        let assassinated = match self.rooms.get_mut(&name) {
+            Some(Room {
+                seats,
+                game: Some(game),
+                ..
+            }) => {
+                let seat = seats.iter().position(|&(seat_id, _)| seat_id == id);
+                let target = seats
+                    .iter()
+                    .position(|(_, seat_name)| *seat_name == target_name)
+                    .ok_or_else(|| {
+                        (
+                            ErrorCode::UnknownPlayer,
+                            format!("!!! {} is not seated in this room", target_name),
+                        )
+                    });
+                target.and_then(|target| match seat {
+                    Some(seat) => game
+                        .assassinate(seat, target)
+                        .map_err(|err| (ErrorCode::of_error(&err), format!("!!! {}", err))),
+                    None => Err((ErrorCode::NotSeated, "!!! you are not seated".to_owned())),
+                })
+            }
+            Some(_) => Err((ErrorCode::GameNotStarted, "!!! game not started".to_owned())),
+            None => Err((
+                ErrorCode::RoomNotFound,
+                "!!! room not exist, may be deleted just now".to_owned(),
+            )),
+        };
+
+        match assassinated {
+            Ok(winner) => {
+                let hit = winner == Alliance::Spy;
+                let locale = self.locale_of(&name);
+                let winner = winner.localized_name(locale);
+                let message = match locale {
+                    Locale::Zh => format!(
+                        "刺客刺杀了 {}，{}{}梅林，{}获胜",
+                        target_name,
+                        target_name,
+                        if hit { "是" } else { "不是" },
+                        winner
+                    ),
+                    Locale::En => format!(
+                        "the assassin names {}, who {} merlin, {} wins",
+                        target_name,
+                        if hit { "is" } else { "is not" },
+                        winner
+                    ),
+                };
+                self.broadcast_event(&name, message);
+                self.finish_game(&name);
+            }
+            Err((code, err)) => self.send_error_to_user(id, code, err),
+        }
+    }
+}
+
+/// Only the lady of the lake holder may inspect, the alliance is told to the
+/// holder alone and the lady passes on to the inspected player
+impl Handler<InspectLoyalty> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: InspectLoyalty, ctx: &mut Context<Self>) {
+        let InspectLoyalty { id, target_name } = msg;
+
+        let name = match self.room_of_user(id) {
+            Some(name) => name,
+            None => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::NotInRoom,
+                    "!!! you are not in a room".to_owned(),
+                );
+                return;
+            }
+        };
+
+        let inspected = match self.rooms.get_mut(&name) {
+            Some(Room {
+                seats,
+                game: Some(game),
+                options,
+                ..
+            }) => {
+                let seat = seats.iter().position(|&(seat_id, _)| seat_id == id);
+                let target = seats
+                    .iter()
+                    .position(|(_, seat_name)| *seat_name == target_name)
+                    .ok_or_else(|| {
+                        (
+                            ErrorCode::UnknownPlayer,
+                            format!("!!! {} is not seated in this room", target_name),
+                        )
+                    });
+                target.and_then(|target| match seat {
+                    Some(seat) => game
+                        .inspect_loyalty(seat, target)
+                        .map(|alliance| (seats[seat].1.clone(), alliance, options.locale))
+                        .map_err(|err| (ErrorCode::of_error(&err), format!("!!! {}", err))),
+                    None => Err((ErrorCode::NotSeated, "!!! you are not seated".to_owned())),
+                })
+            }
+            Some(_) => Err((ErrorCode::GameNotStarted, "!!! game not started".to_owned())),
+            None => Err((
+                ErrorCode::RoomNotFound,
+                "!!! room not exist, may be deleted just now".to_owned(),
+            )),
+        };
+
+        match inspected {
+            Ok((holder, alliance, locale)) => {
+                self.send_to_user(
+                    id,
+                    MessageKind::Private,
+                    format!("{} 是{}", target_name, alliance.localized_name(locale)),
+                );
+                let message = match locale {
+                    Locale::Zh => format!(
+                        "{} 查验了 {}，湖中仙女交给了 {}",
+                        holder, target_name, target_name
+                    ),
+                    Locale::En => format!(
+                        "{} inspects {}, the lady of the lake goes to {}",
+                        holder, target_name, target_name
+                    ),
+                };
+                self.broadcast_event(&name, message);
+                self.announce_leader(&name);
+                self.schedule_bots(&name, ctx);
+            }
+            Err((code, err)) => self.send_error_to_user(id, code, err),
+        }
+    }
+}
+
+/// Replay a finished game, events expire after `review_ttl`
+impl Handler<Review> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Review, _: &mut Context<Self>) {
+        let Review { id, name } = msg;
+
+        let events = match self.finished.get(&name) {
+            Some((finished_at, events)) if finished_at.elapsed() < self.config.review_ttl => events,
+            _ => {
+                self.send_error_to_user(
+                    id,
+                    ErrorCode::NoFinishedGame,
+                    format!("!!! no finished game in room {}", name),
+                );
+                return;
+            }
+        };
+        for event in events {
+            self.send_message_to_user(id, event.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use rand::rngs::mock::StepRng;
+
+    use crate::game::Role::{Assassin, Loyal, Merlin, Mordred, Morgana};
+    use crate::name::Blocklist;
+
+    /// Stands in for a websocket session, collecting what the server sends
+    #[derive(Default)]
+    struct Sink(Vec<Message>);
+
+    impl Actor for Sink {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<Message> for Sink {
+        type Result = ();
+
+        fn handle(&mut self, msg: Message, _: &mut Context<Self>) {
+            self.0.push(msg);
+        }
+    }
+
+    /// Take all messages collected so far, with kind and time
+    struct DrainMessages;
+
+    impl actix::Message for DrainMessages {
+        type Result = Vec<Message>;
+    }
+
+    impl Handler<DrainMessages> for Sink {
+        type Result = MessageResult<DrainMessages>;
+
+        fn handle(&mut self, _: DrainMessages, _: &mut Context<Self>) -> Self::Result {
+            MessageResult(std::mem::take(&mut self.0))
+        }
+    }
+
+    /// Take texts of all messages collected so far, but summaries
+    struct Drain;
+
+    impl actix::Message for Drain {
+        type Result = Vec<String>;
+    }
+
+    impl Handler<Drain> for Sink {
+        type Result = MessageResult<Drain>;
+
+        fn handle(&mut self, _: Drain, _: &mut Context<Self>) -> Self::Result {
+            MessageResult(
+                std::mem::take(&mut self.0)
+                    .into_iter()
+                    .filter(|msg| msg.kind != MessageKind::Summary)
+                    .map(|msg| msg.text)
+                    .collect(),
+            )
+        }
+    }
+
+    async fn connect(server: &Addr<ChatServer>) -> (usize, Addr<Sink>) {
+        let sink = Sink::default().start();
+        let id = server
+            .send(Connect {
+                addr: sink.clone().recipient(),
+                ip: None,
+            })
+            .await
+            .unwrap();
+
+        (id, sink)
+    }
+
+    async fn drain(sink: &Addr<Sink>) -> Vec<String> {
+        sink.send(Drain).await.unwrap()
+    }
+
+    /// Create a room and return its name
+    async fn create_room(
+        server: &Addr<ChatServer>,
+        (id, sink): &(usize, Addr<Sink>),
+        size: u8,
+    ) -> String {
+        let msg = Create {
+            id: *id,
+            session_name: "owner".to_owned(),
+            size,
+            options: RoomOptions::default(),
+            key: None,
+        };
+        create_room_with(server, sink, msg).await
+    }
+
+    async fn create_room_with(server: &Addr<ChatServer>, sink: &Addr<Sink>, msg: Create) -> String {
+        server.send(msg).await.unwrap();
+
+        let messages = drain(sink).await;
+        messages[0]
+            .trim_start_matches("room ")
+            .trim_end_matches(" created.")
+            .to_owned()
+    }
+
+    async fn join_room(
+        server: &Addr<ChatServer>,
+        (id, _): &(usize, Addr<Sink>),
+        session_name: &str,
+        name: &str,
+    ) {
+        server
+            .send(Join {
+                id: *id,
+                session_name: session_name.to_owned(),
+                name: name.to_owned(),
+            })
+            .await
+            .unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn test_resize() {
+        let server = ChatServer::default().start();
+        let owner = connect(&server).await;
+        let guest = connect(&server).await;
+        let name = create_room(&server, &owner, 5).await;
+        join_room(&server, &guest, "guest", &name).await;
+
+        server
+            .send(Resize {
+                id: guest.0,
+                size: 6,
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            drain(&guest.1).await.last().unwrap(),
+            "!!! only the room owner can resize the room"
+        );
+
+        server
+            .send(Resize {
+                id: owner.0,
+                size: 6,
+            })
+            .await
+            .unwrap();
+        assert_eq!(drain(&guest.1).await, vec!["room size changed to 6"]);
+        assert_eq!(server.send(ListRooms).await.unwrap(), vec![name]);
+    }
+
+    #[actix_rt::test]
+    async fn test_rooms_per_owner() {
+        let server = ChatServer::default().start();
+        let owner = connect(&server).await;
+        let name = create_room(&server, &owner, 5).await;
+        let msg = Create {
+            id: owner.0,
+            session_name: "owner".to_owned(),
+            size: 5,
+            options: RoomOptions::default(),
+            key: None,
+        };
+        server.send(msg).await.unwrap();
+        assert_eq!(
+            drain(&owner.1).await,
+            vec!["!!! room limit reached, you may own 1 rooms at once"]
+        );
+        assert_eq!(
+            server.send(RoomCode { id: owner.0 }).await.unwrap(),
+            Some(name)
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_emptied_room_counts_for_owner() {
+        let server = ChatServer::new(ServerConfig {
+            empty_room_grace: Duration::from_secs(60),
+            ..ServerConfig::default()
+        })
+        .start();
+        let owner = connect(&server).await;
+        create_room(&server, &owner, 5).await;
+        // the emptied room is kept for a rejoin, still owned
+        server.send(Leave { id: owner.0 }).await.unwrap();
+        drain(&owner.1).await;
+
+        let msg = Create {
+            id: owner.0,
+            session_name: "owner".to_owned(),
+            size: 5,
+            options: RoomOptions::default(),
+            key: None,
+        };
+        server.send(msg).await.unwrap();
+        assert_eq!(
+            drain(&owner.1).await,
+            vec!["!!! room limit reached, you may own 1 rooms at once"]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_owner_leaves_then_creates() {
+        let server = ChatServer::default().start();
+        let owner = connect(&server).await;
+        let name = create_room(&server, &owner, 5).await;
+        let guest = connect(&server).await;
+        join_room(&server, &guest, "guest", &name).await;
+        drain(&guest.1).await;
+
+        server.send(Leave { id: owner.0 }).await.unwrap();
+        assert_eq!(
+            drain(&guest.1).await,
+            vec!["Someone disconnected", "guest is the room owner now"]
+        );
+        drain(&owner.1).await;
+
+        // the room stays with the guest, and doesn't count for the owner
+        let second = create_room(&server, &owner, 5).await;
+        assert_eq!(
+            server.send(RoomCode { id: owner.0 }).await.unwrap(),
+            Some(second)
+        );
+        let roster = server.send(Roster { id: guest.0 }).await.unwrap().unwrap();
+        assert!(roster[0].owner);
+    }
+
+    #[actix_rt::test]
+    async fn test_abort_game() {
+        let server = ChatServer::default().start();
+        let seats = started_room(&server, 5).await;
+        let ((owner_id, owner_sink), _) = &seats[0];
+        let ((guest_id, guest_sink), _) = &seats[1];
+        server.send(Leave { id: seats[4].0 .0 }).await.unwrap();
+        drain(owner_sink).await;
+        drain(guest_sink).await;
+
+        server.send(AbortGame { id: *guest_id }).await.unwrap();
+        assert_eq!(
+            drain(guest_sink).await,
+            vec!["!!! only the room owner can abort the game"]
+        );
+
+        server.send(AbortGame { id: *owner_id }).await.unwrap();
+        assert_eq!(
+            drain(owner_sink).await,
+            vec!["game aborted by the room owner"]
+        );
+        assert_eq!(
+            drain(guest_sink).await,
+            vec!["game aborted by the room owner"]
+        );
+        server.send(GameStatus { id: *owner_id }).await.unwrap();
+        assert_eq!(drain(owner_sink).await, vec!["!!! game not started"]);
+        // the seat left during the game is free again
+        server.send(SeatsNeeded { id: *owner_id }).await.unwrap();
+        assert_eq!(drain(owner_sink).await, vec!["1 more players needed"]);
+
+        server.send(AbortGame { id: *owner_id }).await.unwrap();
+        assert_eq!(drain(owner_sink).await, vec!["!!! game not started"]);
+    }
+
+    #[actix_rt::test]
+    async fn test_require_ready() {
+        let server = ChatServer::default().start();
+        let owner = connect(&server).await;
+        let msg = Create {
+            id: owner.0,
+            session_name: "owner".to_owned(),
+            size: 5,
+            options: RoomOptions {
+                require_ready: true,
+                ..RoomOptions::default()
+            },
+            key: None,
+        };
+        let name = create_room_with(&server, &owner.1, msg).await;
+        server.send(Ready { id: owner.0 }).await.unwrap();
+        assert_eq!(drain(&owner.1).await, vec!["owner is ready (1/5)"]);
+        let mut guests = Vec::new();
+        for i in 1..5 {
+            let guest = connect(&server).await;
+            join_room(&server, &guest, &format!("guest{}", i), &name).await;
+            guests.push(guest);
+        }
+        for guest in &guests[..3] {
+            server.send(Ready { id: guest.0 }).await.unwrap();
+        }
+        let told = drain(&owner.1).await;
+        assert!(told.contains(&"room is full, waiting for everyone to /ready".to_owned()));
+        assert_eq!(told.last().unwrap(), "guest3 is ready (4/5)");
+        server.send(GameStatus { id: owner.0 }).await.unwrap();
+        assert_eq!(drain(&owner.1).await, vec!["!!! game not started"]);
+
+        server.send(Ready { id: guests[3].0 }).await.unwrap();
+        let told = drain(&owner.1).await;
+        assert_eq!(told[..2], ["guest4 is ready (5/5)", "人已经凑齐"]);
+        server.send(Ready { id: guests[3].0 }).await.unwrap();
+        assert_eq!(
+            drain(&guests[3].1).await.last().unwrap(),
+            "!!! game already started"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_roster() {
+        let server = ChatServer::default().start();
+        let owner = connect(&server).await;
+        assert_eq!(server.send(Roster { id: owner.0 }).await.unwrap(), None);
+
+        let name = create_room(&server, &owner, 5).await;
+        let guest = connect(&server).await;
+        join_room(&server, &guest, "guest", &name).await;
+        server.send(Ready { id: guest.0 }).await.unwrap();
+
+        let roster = server.send(Roster { id: guest.0 }).await.unwrap().unwrap();
+        assert_eq!(
+            roster,
+            vec![
+                RosterEntry {
+                    seat: 0,
+                    name: "owner".to_owned(),
+                    ready: false,
+                    owner: true,
+                },
+                RosterEntry {
+                    seat: 1,
+                    name: "guest".to_owned(),
+                    ready: true,
+                    owner: false,
+                },
+            ]
+        );
+        assert_eq!(
+            serde_json::to_value(&roster[1]).unwrap(),
+            serde_json::json!({"seat": 1, "name": "guest", "ready": true, "owner": false})
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_manual_start() {
+        let server = ChatServer::default().start();
+        let owner = connect(&server).await;
+        let msg = Create {
+            id: owner.0,
+            session_name: "owner".to_owned(),
+            size: 5,
+            options: RoomOptions {
+                manual_start: true,
+                ..RoomOptions::default()
+            },
+            key: None,
+        };
+        let name = create_room_with(&server, &owner.1, msg).await;
+        for i in 1..5 {
+            let guest = connect(&server).await;
+            join_room(&server, &guest, &format!("guest{}", i), &name).await;
+        }
+        assert_eq!(
+            drain(&owner.1).await.last().unwrap(),
+            "room is full, waiting for host to start"
+        );
+        server.send(GameStatus { id: owner.0 }).await.unwrap();
+        assert_eq!(drain(&owner.1).await, vec!["!!! game not started"]);
+
+        server.send(Start { id: owner.0 }).await.unwrap();
+        let told = drain(&owner.1).await;
+        assert_eq!(told[0], "人已经凑齐");
+        assert!(told.iter().any(|line| line.starts_with("你的身份是")));
+    }
+
+    #[actix_rt::test]
+    async fn test_start_over_capacity() {
+        let server = ChatServer::default().start();
+        let owner = connect(&server).await;
+        let msg = Create {
+            id: owner.0,
+            session_name: "owner".to_owned(),
+            size: 5,
+            options: RoomOptions {
+                manual_start: true,
+                ..RoomOptions::default()
+            },
+            key: None,
+        };
+        let name = create_room_with(&server, &owner.1, msg).await;
+        for i in 1..7 {
+            let guest = connect(&server).await;
+            join_room(&server, &guest, &format!("guest{}", i), &name).await;
+        }
+        drain(&owner.1).await;
+
+        server.send(Start { id: owner.0 }).await.unwrap();
+        assert_eq!(
+            drain(&owner.1).await,
+            vec!["!!! 2 players over the room size, /overflow them first"]
+        );
+        server.send(GameStatus { id: owner.0 }).await.unwrap();
+        assert_eq!(drain(&owner.1).await, vec!["!!! game not started"]);
+
+        server.send(Overflow { id: owner.0 }).await.unwrap();
+        drain(&owner.1).await;
+        server.send(Start { id: owner.0 }).await.unwrap();
+        let told = drain(&owner.1).await;
+        assert_eq!(told[0], "人已经凑齐");
+        assert!(told.iter().any(|line| line.starts_with("你的身份是")));
+    }
+
+    #[actix_rt::test]
+    async fn test_start_at_min_start() {
+        let server = ChatServer::default().start();
+        let owner = connect(&server).await;
+        let msg = Create {
+            id: owner.0,
+            session_name: "owner".to_owned(),
+            size: 8,
+            options: RoomOptions {
+                min_start: Some(6),
+                ..RoomOptions::default()
+            },
+            key: None,
+        };
+        let name = create_room_with(&server, &owner.1, msg).await;
+        let mut guests = Vec::new();
+        for i in 1..4 {
+            let guest = connect(&server).await;
+            join_room(&server, &guest, &format!("guest{}", i), &name).await;
+            guests.push(guest);
+        }
+        drain(&owner.1).await;
+
+        server.send(Start { id: owner.0 }).await.unwrap();
+        assert_eq!(
+            drain(&owner.1).await,
+            vec!["!!! 2 more players needed to start"]
+        );
+        server
+            .send(Resize {
+                id: owner.0,
+                size: 5,
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            drain(&owner.1).await,
+            vec!["!!! room size 5 is less than min start 6"]
+        );
+
+        for i in 4..6 {
+            let guest = connect(&server).await;
+            join_room(&server, &guest, &format!("guest{}", i), &name).await;
+            guests.push(guest);
+        }
+        drain(&owner.1).await;
+        let guest = &guests[0];
+        drain(&guest.1).await;
+        server.send(Start { id: guest.0 }).await.unwrap();
+        assert_eq!(
+            drain(&guest.1).await,
+            vec!["!!! only the room owner can start the game"]
+        );
+        server.send(Start { id: owner.0 }).await.unwrap();
+        assert_eq!(drain(&owner.1).await[0], "人已经凑齐");
+        server.send(Start { id: owner.0 }).await.unwrap();
+        assert_eq!(drain(&owner.1).await, vec!["!!! game already started"]);
+    }
+
+    #[actix_rt::test]
+    async fn test_resize_below_seated() {
+        let server = ChatServer::default().start();
+        let owner = connect(&server).await;
+        let name = create_room(&server, &owner, 7).await;
+        for i in 0..5 {
+            let guest = connect(&server).await;
+            join_room(&server, &guest, &format!("guest{}", i), &name).await;
+        }
+
+        server
+            .send(Resize {
+                id: owner.0,
+                size: 5,
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            drain(&owner.1).await.last().unwrap(),
+            "!!! room size 5 is less than 6 seated players"
+        );
+
+        server
+            .send(Resize {
+                id: owner.0,
+                size: 11,
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            drain(&owner.1).await.last().unwrap(),
+            "!!! room size 11 is not supported. it should be in range 5-10"
+        );
+    }
+
+    /// Fill a room of `size` and return what each seat was told
+    async fn deal_seeded_room(server: &Addr<ChatServer>, size: u8, seed: u64) -> Vec<Vec<String>> {
+        let owner = connect(server).await;
+        let msg = Create {
+            id: owner.0,
+            session_name: "owner".to_owned(),
+            size,
+            options: RoomOptions {
+                seed: Some(seed),
+                ..RoomOptions::default()
+            },
+            key: None,
+        };
+        let name = create_room_with(server, &owner.1, msg).await;
+        let mut seats = vec![owner];
+        for i in 1..size {
+            let guest = connect(server).await;
+            join_room(server, &guest, &format!("guest{}", i), &name).await;
+            seats.push(guest);
+        }
+
+        let mut told = Vec::new();
+        for (_, sink) in &seats {
+            told.push(drain(sink).await);
+        }
+        told
+    }
+
+    #[actix_rt::test]
+    async fn test_seeded_deal() {
+        let server = ChatServer::default().start();
+        let first = deal_seeded_room(&server, 7, 42).await;
+        let second = deal_seeded_room(&server, 7, 42).await;
+
+        assert!(first[0].iter().any(|m| m.starts_with("你的身份是")));
+        assert_eq!(first, second);
+    }
+
+    #[actix_rt::test]
+    async fn test_whoami() {
+        let server = ChatServer::default().start();
+        let owner = connect(&server).await;
+        let name = create_room(&server, &owner, 5).await;
+
+        server.send(WhoAmI { id: owner.0 }).await.unwrap();
+        assert_eq!(drain(&owner.1).await, vec!["!!! game not started"]);
+
+        for i in 1..5 {
+            let guest = connect(&server).await;
+            join_room(&server, &guest, &format!("guest{}", i), &name).await;
+        }
+        let told = drain(&owner.1).await;
+        let start = told
+            .iter()
+            .position(|m| m.starts_with("你的身份是"))
+            .unwrap();
+        let hint = told[start..start + 2].to_vec();
+
+        server.send(WhoAmI { id: owner.0 }).await.unwrap();
+        assert_eq!(drain(&owner.1).await, hint);
+    }
+
+    #[actix_rt::test]
+    async fn test_reveal_self() {
+        let server = ChatServer::default().start();
+        let owner = connect(&server).await;
+        create_room(&server, &owner, 5).await;
+        server.send(RevealSelf { id: owner.0 }).await.unwrap();
+        assert_eq!(drain(&owner.1).await, vec!["!!! game not started"]);
+
+        let seats = started_room(&server, 5).await;
+        let ((guest_id, guest_sink), guest_name) = &seats[1];
+        server.send(WhoAmI { id: *guest_id }).await.unwrap();
+        let told = drain(guest_sink).await;
+        let role = told[0]
+            .trim_start_matches("你的身份是【")
+            .trim_end_matches("】，");
+
+        server.send(RevealSelf { id: *guest_id }).await.unwrap();
+        let revealed = format!("{} 亮明身份：【{}】", guest_name, role);
+        for ((_, sink), _) in &seats {
+            assert_eq!(drain(sink).await, vec![revealed.clone()]);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_room_locale() {
+        let server = ChatServer::default().start();
+        for &(locale, greeting) in &[(Locale::En, "Your role is ["), (Locale::Zh, "你的身份是【")]
+        {
+            let owner = connect(&server).await;
+            let msg = Create {
+                id: owner.0,
+                session_name: "owner".to_owned(),
+                size: 5,
+                options: RoomOptions {
+                    locale,
+                    ..RoomOptions::default()
+                },
+                key: None,
+            };
+            let name = create_room_with(&server, &owner.1, msg).await;
+            for i in 1..5 {
+                let guest = connect(&server).await;
+                join_room(&server, &guest, &format!("guest{}", i), &name).await;
+            }
+            let told = drain(&owner.1).await;
+            assert!(told.iter().any(|m| m.starts_with(greeting)), "{:?}", told);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_room_deck() {
+        let server = ChatServer::default().start();
+        let seats = started_room(&server, 5).await;
+        let owner = &seats[0].0;
+        server.send(RoomDeck { id: owner.0 }).await.unwrap();
+        assert_eq!(
+            drain(&owner.1).await,
+            vec!["deck: 梅林、派西维尔、忠臣、刺客、莫甘娜"]
+        );
+
+        let stranger = connect(&server).await;
+        server.send(RoomDeck { id: stranger.0 }).await.unwrap();
+        assert_eq!(drain(&stranger.1).await, vec!["!!! you are not in a room"]);
+    }
+
+    #[actix_rt::test]
+    async fn test_preview_deal() {
+        let server = ChatServer::default().start();
+        let owner = connect(&server).await;
+        let name = create_room(&server, &owner, 5).await;
+        let guest = connect(&server).await;
+        join_room(&server, &guest, "guest", &name).await;
+        drain(&owner.1).await;
+        drain(&guest.1).await;
+
+        server.send(PreviewDeal { id: guest.0 }).await.unwrap();
+        assert_eq!(
+            drain(&guest.1).await,
+            vec!["!!! only the room owner can preview"]
+        );
+
+        server.send(PreviewDeal { id: owner.0 }).await.unwrap();
+        let told = drain(&owner.1).await;
+        assert_eq!(told.len(), 1);
+        let lines: Vec<&str> = told[0].lines().collect();
+        assert_eq!(lines[0], "preview: 梅林、派西维尔、忠臣、刺客、莫甘娜");
+        assert!(lines[1].starts_with("sample: #1 "));
+        assert!(!lines[1].contains("owner") && !lines[1].contains("guest"));
+        assert!(drain(&guest.1).await.is_empty());
+
+        // the room is still waiting for the same players
+        server.send(SeatsNeeded { id: owner.0 }).await.unwrap();
+        assert_eq!(drain(&owner.1).await, vec!["3 more players needed"]);
+        server
+            .send(ProposeTeam {
+                id: owner.0,
+                names: vec!["owner".to_owned(), "guest".to_owned()],
+            })
+            .await
+            .unwrap();
+        assert_eq!(drain(&owner.1).await, vec!["!!! game not started"]);
+    }
+
+    #[actix_rt::test]
+    async fn test_shuffle_seats_command() {
+        let server = ChatServer::default().start();
+        let owner = connect(&server).await;
+        let msg = Create {
+            id: owner.0,
+            session_name: "owner".to_owned(),
+            size: 7,
+            options: RoomOptions {
+                seed: Some(42),
+                ..RoomOptions::default()
+            },
+            key: None,
+        };
+        let name = create_room_with(&server, &owner.1, msg).await;
+        let mut guests = Vec::new();
+        for i in 1..5 {
+            let guest = connect(&server).await;
+            join_room(&server, &guest, &format!("guest{}", i), &name).await;
+            guests.push(guest);
+        }
+        drain(&owner.1).await;
+        drain(&guests[0].1).await;
+
+        server.send(ShuffleSeats { id: guests[0].0 }).await.unwrap();
+        assert_eq!(
+            drain(&guests[0].1).await,
+            vec!["!!! only the room owner can shuffle seats"]
+        );
+
+        let mut expected = vec!["owner", "guest1", "guest2", "guest3", "guest4"];
+        expected.shuffle(&mut StdRng::seed_from_u64(42));
+        server.send(ShuffleSeats { id: owner.0 }).await.unwrap();
+        let told = drain(&owner.1).await;
+        assert_eq!(
+            told,
+            vec![format!("seats shuffled: {}", expected.join(", "))]
+        );
+        assert_eq!(drain(&guests[0].1).await, told);
+        assert_ne!(
+            expected,
+            vec!["owner", "guest1", "guest2", "guest3", "guest4"]
+        );
+
+        let seats = started_room(&server, 5).await;
+        server
+            .send(ShuffleSeats { id: seats[0].0 .0 })
+            .await
+            .unwrap();
+        assert_eq!(
+            drain(&seats[0].0 .1).await.last().unwrap(),
+            "!!! game already started"
+        );
+    }
+
+    #[test]
+    fn test_game_summary() {
+        let names = (0..5).map(|i| format!("p{}", i)).collect();
+        let assignment =
+            Assignment::from_exact(names, vec![Loyal, Loyal, Loyal, Morgana, Mordred]).unwrap();
+        let mut game = Game::new(assignment).unwrap();
+        for _ in 0..3 {
+            let (leader, size) = (game.leader(), game.team_size());
+            game.propose_team(leader, (0..size).collect()).unwrap();
+            for seat in 0..5 {
+                game.cast_vote(seat, true).unwrap();
+            }
+            for seat in 0..size {
+                game.submit_mission(seat, false).unwrap();
+            }
+        }
+        let mut room = seated_room(
+            5,
+            RoomOptions {
+                reveal_on_end: false,
+                ..RoomOptions::default()
+            },
+        );
+        room.game = Some(game);
+
+        let summary = serde_json::to_value(GameSummary::of("42", &room).unwrap()).unwrap();
+        assert_eq!(summary["kind"], "summary");
+        assert_eq!(summary["room"], "42");
+        assert_eq!(summary["winner"], "resistance");
+        assert_eq!(summary["missions"], serde_json::json!([true, true, true]));
+        assert_eq!(summary["proposals"].as_array().unwrap().len(), 3);
+        assert_eq!(
+            summary["proposals"][1]["team"],
+            serde_json::json!([0, 1, 2])
+        );
+        assert!(summary.get("roles").is_none());
+
+        room.options.reveal_on_end = true;
+        let summary = serde_json::to_value(GameSummary::of("42", &room).unwrap()).unwrap();
+        assert_eq!(
+            summary["roles"][3],
+            serde_json::json!({"seat": 3, "name": "p3", "role": "morgana"})
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_room_rules() {
+        let server = ChatServer::default().start();
+        let owner = connect(&server).await;
+        server.send(RoomRules { id: owner.0 }).await.unwrap();
+        assert_eq!(drain(&owner.1).await, vec!["!!! you are not in a room"]);
+
+        let msg = Create {
+            id: owner.0,
+            session_name: "owner".to_owned(),
+            size: 7,
+            options: RoomOptions {
+                locale: Locale::En,
+                ..RoomOptions::default()
+            },
+            key: None,
+        };
+        create_room_with(&server, &owner.1, msg).await;
+        server.send(RoomRules { id: owner.0 }).await.unwrap();
+        let told = drain(&owner.1).await;
+        assert_eq!(told.len(), 1);
+        let deck = RoleSet::standard(7).unwrap();
+        for role in deck.roles() {
+            assert!(told[0].contains(role.localized_description(Locale::En)));
+        }
+        // each role once, even with two loyal servants
+        assert_eq!(told[0].lines().count(), 6);
+        assert!(!told[0].contains(Role::Mordred.localized_description(Locale::En)));
+    }
+
+    #[actix_rt::test]
+    async fn test_seats_needed() {
+        let server = ChatServer::default().start();
+        let owner = connect(&server).await;
+        server.send(SeatsNeeded { id: owner.0 }).await.unwrap();
+        assert_eq!(drain(&owner.1).await, vec!["!!! you are not in a room"]);
+
+        let name = create_room(&server, &owner, 5).await;
+        let guest = connect(&server).await;
+        join_room(&server, &guest, "guest", &name).await;
+        drain(&owner.1).await;
+
+        server.send(SeatsNeeded { id: owner.0 }).await.unwrap();
+        assert_eq!(drain(&owner.1).await, vec!["3 more players needed"]);
+    }
+
+    #[actix_rt::test]
+    async fn test_seats_needed_over_capacity() {
+        let server = ChatServer::default().start();
+        let owner = connect(&server).await;
+        let msg = Create {
+            id: owner.0,
+            session_name: "owner".to_owned(),
+            size: 5,
+            options: RoomOptions {
+                manual_start: true,
+                ..RoomOptions::default()
+            },
+            key: None,
+        };
+        let name = create_room_with(&server, &owner.1, msg).await;
+        for i in 1..7 {
+            let guest = connect(&server).await;
+            join_room(&server, &guest, &format!("guest{}", i), &name).await;
+        }
+        drain(&owner.1).await;
+
+        server.send(SeatsNeeded { id: owner.0 }).await.unwrap();
+        assert_eq!(
+            drain(&owner.1).await,
+            vec!["over capacity by 2 players, /overflow them"]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_failed_deal_reveals_nothing() {
+        let mut server = ChatServer::default();
+        let mut sinks = Vec::new();
+        // a room of an unsupported size, which can't be dealt once full
+        let mut room = Room::new(0, "p0".to_owned(), 4, RoomOptions::default());
+        for id in 0..3 {
+            let sink = Sink::default().start();
+            server.sessions.insert(id, sink.clone().recipient());
+            if id > 0 {
+                room.sessions.insert(id);
+                room.seats.push((id, format!("p{}", id)));
+            }
+            sinks.push(sink);
+        }
+        server.rooms.insert("small".to_owned(), room);
+        let server = server.start();
+
+        let last = connect(&server).await;
+        join_room(&server, &last, "p3", "small").await;
+        sinks.push(last.1);
+
+        for sink in &sinks {
+            let messages = sink.send(DrainMessages).await.unwrap();
+            assert!(messages.iter().all(|m| m.kind != MessageKind::Private));
+            assert!(messages.iter().any(|m| m.text.starts_with("分配失败")));
+        }
+        let rooms = server.send(ListRooms).await.unwrap();
+        assert!(!rooms.iter().any(|room| room.starts_with("small")));
+    }
+
+    #[actix_rt::test]
+    async fn test_failed_hint_reveals_nothing() {
+        let sinks: Vec<_> = (0..6).map(|_| Sink::default().start()).collect();
+        let recipients: Vec<_> = sinks.iter().map(|sink| sink.clone().recipient()).collect();
+        ChatServer::create(move |ctx| {
+            let mut server = ChatServer::default();
+            for (id, recipient) in recipients.into_iter().enumerate() {
+                server.sessions.insert(id, recipient);
+            }
+            server
+                .rooms
+                .insert("0".to_owned(), seated_room(5, RoomOptions::default()));
+            let assignment = server.deal("0").unwrap();
+
+            // a seat taken after the deal, the hints of the five dealt
+            // seats render before it fails
+            let room = server.rooms.get_mut("0").unwrap();
+            room.sessions.insert(5);
+            room.seats.push((5, "p5".to_owned()));
+            let err = server.notify_hints("0", &assignment, ctx).unwrap_err();
+            assert_eq!(err.to_string(), "seat 6 is not dealt");
+            server
+        });
+
+        for sink in &sinks {
+            assert!(drain(sink).await.is_empty());
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_room_code() {
+        let server = ChatServer::default().start();
+        let owner = connect(&server).await;
+        let guest = connect(&server).await;
+        assert_eq!(server.send(RoomCode { id: guest.0 }).await.unwrap(), None);
+
+        let name = create_room(&server, &owner, 5).await;
+        join_room(&server, &guest, "guest", &name).await;
+        assert_eq!(
+            server.send(RoomCode { id: guest.0 }).await.unwrap(),
+            Some(name)
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_empty_room_grace() {
+        let server = ChatServer::new(ServerConfig {
+            empty_room_grace: Duration::from_secs(60),
+            ..ServerConfig::default()
+        })
+        .start();
+        let owner = connect(&server).await;
+        let name = create_room(&server, &owner, 5).await;
+        server
+            .send(Disconnect {
+                id: owner.0,
+                reason: DisconnectReason::Left,
+            })
+            .await
+            .unwrap();
+        assert_eq!(server.send(ListRooms).await.unwrap(), vec![name.clone()]);
+
+        let host = connect(&server).await;
+        join_room(&server, &host, "host", &name).await;
+        assert_eq!(drain(&host.1).await, vec!["joined"]);
+        server
+            .send(SetRevealOnEnd {
+                id: host.0,
+                enabled: true,
+            })
+            .await
+            .unwrap();
+        assert_eq!(drain(&host.1).await, vec!["reveal on end: on"]);
+    }
+
+    #[test]
+    fn test_reap_empty_rooms() {
+        let mut server = ChatServer::new(ServerConfig {
+            empty_room_grace: Duration::from_millis(1),
+            ..ServerConfig::default()
+        });
+        server.rooms.insert(
+            "gone".to_owned(),
+            Room::new(0, "p0".to_owned(), 5, RoomOptions::default()),
+        );
+        server.remove_user_from_all_rooms(0);
+        assert!(server.rooms["gone"].emptied_at.is_some());
+
+        std::thread::sleep(Duration::from_millis(2));
+        server.reap_empty_rooms();
+        assert!(server.rooms.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn test_room_elapsed() {
+        let server = ChatServer::default().start();
+        let owner = connect(&server).await;
+        assert_eq!(
+            server.send(RoomElapsed { id: owner.0 }).await.unwrap(),
+            None
+        );
+
+        create_room(&server, &owner, 5).await;
+        let first = server.send(RoomElapsed { id: owner.0 }).await.unwrap();
+        actix_rt::time::delay_for(Duration::from_millis(10)).await;
+        let second = server.send(RoomElapsed { id: owner.0 }).await.unwrap();
+        assert!(first.unwrap() > Duration::default());
+        assert!(second.unwrap() >= first.unwrap() + Duration::from_millis(10));
+    }
+
+    #[actix_rt::test]
+    async fn test_list_open_rooms() {
+        let server = ChatServer::default().start();
+        let owner = connect(&server).await;
+        let open = create_room(&server, &owner, 5).await;
+
+        let owner = connect(&server).await;
+        let full = create_room(&server, &owner, 5).await;
+        for i in 1..5 {
+            let guest = connect(&server).await;
+            join_room(&server, &guest, &format!("guest{}", i), &full).await;
+        }
+
+        assert_eq!(server.send(ListRooms).await.unwrap().len(), 2);
+        assert_eq!(server.send(ListOpenRooms).await.unwrap(), vec![open]);
+    }
+
+    #[actix_rt::test]
+    async fn test_list_games() {
+        let server = ChatServer::default().start();
+        let owner = connect(&server).await;
+        create_room(&server, &owner, 5).await;
+        assert!(server.send(ListGames).await.unwrap().is_empty());
+
+        let seats = started_room(&server, 5).await;
+        let name = server
+            .send(RoomCode { id: (seats[0].0).0 })
+            .await
+            .unwrap()
+            .unwrap();
+        let games = server.send(ListGames).await.unwrap();
+        assert_eq!(games.len(), 1);
+        assert!(games[0].starts_with(&format!("{}: · · · · · leader: ", name)));
+        assert!(games[0].ends_with("mission: 1, rejects: 0"));
+    }
+
+    #[actix_rt::test]
+    async fn test_join_own_room() {
+        let server = ChatServer::default().start();
+        let owner = connect(&server).await;
+        let guest = connect(&server).await;
+        let name = create_room(&server, &owner, 5).await;
+        join_room(&server, &guest, "guest", &name).await;
+        drain(&owner.1).await;
+        drain(&guest.1).await;
+
+        join_room(&server, &guest, "guest", &name).await;
+        assert!(drain(&owner.1).await.is_empty());
+        assert_eq!(
+            drain(&guest.1).await,
+            vec!["!!! you are already in this room"]
+        );
+    }
+
+    /// A full room of `size`, with client ids from 0 and names from "p0"
+    fn seated_room(size: u8, options: RoomOptions) -> Room {
+        let mut room = Room::new(0, "p0".to_owned(), size, options);
+        for i in 1..size as usize {
+            room.sessions.insert(i);
+            room.seats.push((i, format!("p{}", i)));
+        }
+        room
+    }
+
+    #[test]
+    fn test_injected_rng() {
+        let mut server = ChatServer::default().with_rng(StepRng::new(0, 0));
+        assert_eq!(server.random_room_name(), Some("0".to_owned()));
+        server
+            .rooms
+            .insert("0".to_owned(), seated_room(5, RoomOptions::default()));
+        assert_eq!(server.random_room_name(), None);
+
+        assert_eq!(server.deal("0").unwrap().players[0].1, Assassin);
+    }
+
+    #[actix_rt::test]
+    async fn test_unused_id() {
+        let mut server = ChatServer::default().with_rng(StepRng::new(5, 1));
+        let sink = Sink::default().start();
+        server.sessions.insert(5, sink.recipient());
+        let mut room = seated_room(5, RoomOptions::default());
+        room.bots.insert(6);
+        server.rooms.insert("0".to_owned(), room);
+
+        // 5 is a session, 6 a bot
+        assert_eq!(server.unused_id(), 7);
+    }
+
+    #[test]
+    fn test_templates() {
+        let mut server = ChatServer::new(ServerConfig {
+            templates: Templates {
+                role: Some("=== {role} ===".to_owned()),
+                no_hint: Some("(nothing to see)".to_owned()),
+            },
+            ..ServerConfig::default()
+        })
+        .with_rng(StepRng::new(0, 0));
+        server
+            .rooms
+            .insert("0".to_owned(), seated_room(5, RoomOptions::default()));
+
+        let assignment = server.deal("0").unwrap();
+        let hints = server.hints("0", &assignment).unwrap();
+        assert_eq!(hints[0].1[0], "=== 刺客 ===");
+        let loyal = assignment
+            .players
+            .iter()
+            .position(|&(_, role)| role == Loyal)
+            .unwrap();
+        assert_eq!(hints[loyal].1, vec!["=== 忠臣 ===", "(nothing to see)"]);
+    }
+
+    #[test]
+    fn test_word_pair_room_names() {
+        let mut server = ChatServer::new(ServerConfig {
+            room_names: RoomNames::WordPair,
+            ..ServerConfig::default()
+        })
+        .with_rng(StdRng::seed_from_u64(42));
+        for _ in 0..200 {
+            let name = server.random_room_name().unwrap();
+            assert!(name.contains('-'));
+            assert!(!server.rooms.contains_key(&name));
+            server
+                .rooms
+                .insert(name, seated_room(5, RoomOptions::default()));
+        }
+        assert_eq!(server.rooms.len(), 200);
+    }
+
+    #[test]
+    fn test_shuffle_seats() {
+        let mut server = ChatServer::default();
+        let seated = |options| seated_room(7, options);
+
+        let options = RoomOptions {
+            seed: Some(42),
+            shuffle_seats: false,
+            ..RoomOptions::default()
+        };
+        server
+            .rooms
+            .insert("kept".to_owned(), seated(options.clone()));
+        server.shuffle_seats("kept");
+        let kept = server.rooms["kept"].seats.clone();
+        assert_eq!(kept, seated(options).seats);
+
+        let options = RoomOptions {
+            seed: Some(42),
+            shuffle_seats: true,
+            ..RoomOptions::default()
+        };
+        server.rooms.insert("a".to_owned(), seated(options.clone()));
+        server.rooms.insert("b".to_owned(), seated(options));
+        server.shuffle_seats("a");
+        server.shuffle_seats("b");
+        let shuffled = &server.rooms["a"].seats;
+        assert_ne!(shuffled, &kept);
+        assert_eq!(shuffled, &server.rooms["b"].seats);
+
+        let mut ids: Vec<_> = shuffled.iter().map(|&(id, _)| id).collect();
+        ids.sort_unstable();
+        assert_eq!(ids, (0..7).collect::<Vec<_>>());
+    }
+
+    #[actix_rt::test]
+    async fn test_leave() {
+        let mut server = ChatServer::default();
+        let sinks: Vec<_> = (0..5).map(|_| Sink::default().start()).collect();
+        for (id, sink) in sinks.iter().enumerate() {
+            server.sessions.insert(id, sink.clone().recipient());
+        }
+
+        // before the game starts, the seat is freed
+        let mut room = seated_room(5, RoomOptions::default());
+        room.sessions.remove(&4);
+        room.seats.pop();
+        server.rooms.insert("lobby".to_owned(), room);
+        server.remove_user_from_all_rooms(3);
+        assert_eq!(server.rooms["lobby"].seats.len(), 3);
+        assert!(server.rooms["lobby"].abandoned.is_empty());
+        assert_eq!(drain(&sinks[0]).await, vec!["Someone disconnected"]);
+        server.rooms.clear();
+
+        // once started, the seat is kept but abandoned
+        let mut room = seated_room(5, RoomOptions::default());
+        let names = room.seats.iter().map(|(_, name)| name.clone());
+        room.game = Some(Game::new(Assignment::new(names).unwrap()).unwrap());
+        server.rooms.insert("game".to_owned(), room);
+        server.remove_user_from_all_rooms(3);
+        let room = &server.rooms["game"];
+        assert_eq!(room.seats.len(), 5);
+        assert_eq!(room.sessions.len(), 4);
+        assert!(room.abandoned.contains_key(&3));
+        assert_eq!(
+            drain(&sinks[0]).await,
+            vec!["p3 left the game, seat 4 is abandoned"]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_timeout() {
+        let mut server = ChatServer::default();
+        let sinks: Vec<_> = (0..5).map(|_| Sink::default().start()).collect();
+        for (id, sink) in sinks.iter().enumerate() {
+            server.sessions.insert(id, sink.clone().recipient());
+        }
+
+        let mut room = seated_room(5, RoomOptions::default());
+        room.sessions.remove(&4);
+        room.seats.pop();
+        server.rooms.insert("lobby".to_owned(), room);
+        server.remove_user_for(3, DisconnectReason::Timeout);
+        assert_eq!(drain(&sinks[0]).await, vec!["p3 timed out"]);
+        server.rooms.clear();
+
+        let mut room = seated_room(5, RoomOptions::default());
+        let names = room.seats.iter().map(|(_, name)| name.clone());
+        room.game = Some(Game::new(Assignment::new(names).unwrap()).unwrap());
+        server.rooms.insert("game".to_owned(), room);
+        server.remove_user_for(3, DisconnectReason::Timeout);
+        assert!(server.rooms["game"].abandoned.contains_key(&3));
+        assert_eq!(
+            drain(&sinks[0]).await,
+            vec!["p3 timed out, seat 4 is abandoned"]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_rejoin() {
+        let server = ChatServer::default().start();
+        let seats = started_room(&server, 5).await;
+        let ((guest_id, guest_sink), guest_name) = &seats[1];
+        let name = server
+            .send(RoomCode { id: *guest_id })
+            .await
+            .unwrap()
+            .unwrap();
+        server.send(WhoAmI { id: *guest_id }).await.unwrap();
+        let hint = drain(guest_sink).await;
+        server.send(Leave { id: *guest_id }).await.unwrap();
+
+        let back = connect(&server).await;
+        let rejoin = |session_name: &str| Rejoin {
+            id: back.0,
+            session_name: session_name.to_owned(),
+            name: name.clone(),
+        };
+        server.send(rejoin("guest2")).await.unwrap();
+        assert_eq!(drain(&back.1).await, vec!["!!! no seat held for guest2"]);
+
+        server.send(rejoin(guest_name)).await.unwrap();
+        let told = drain(&back.1).await;
+        assert_eq!(told[0], "rejoined");
+        assert_eq!(told[1..], hint[..]);
+        assert_eq!(
+            drain(&seats[0].0 .1).await.last().unwrap(),
+            "guest1 is back"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_rejoin_expired() {
+        let server = ChatServer::new(ServerConfig {
+            seat_hold_ttl: Duration::default(),
+            ..ServerConfig::default()
+        })
+        .start();
+        let seats = started_room(&server, 5).await;
+        let ((guest_id, _), guest_name) = &seats[1];
+        let name = server
+            .send(RoomCode { id: *guest_id })
+            .await
+            .unwrap()
+            .unwrap();
+        server.send(Leave { id: *guest_id }).await.unwrap();
+
+        let back = connect(&server).await;
+        let msg = Rejoin {
+            id: back.0,
+            session_name: guest_name.clone(),
+            name,
+        };
+        server.send(msg).await.unwrap();
+        assert_eq!(drain(&back.1).await, vec!["!!! no seat held for guest1"]);
+    }
+
+    #[actix_rt::test]
+    async fn test_expired_seat_stand_in() {
+        let server = ChatServer::new(ServerConfig {
+            seat_hold_ttl: Duration::from_millis(50),
+            bot_delay: Duration::default(),
+            ..ServerConfig::default()
+        })
+        .start();
+        let seats = started_room(&server, 5).await;
+        server.send(Leave { id: seats[4].0 .0 }).await.unwrap();
+        server
+            .send(ProposeTeam {
+                id: seats[0].0 .0,
+                names: vec![seats[0].1.clone(), seats[1].1.clone()],
+            })
+            .await
+            .unwrap();
+        for ((id, _), _) in &seats[..4] {
+            server
+                .send(CastVote {
+                    id: *id,
+                    approve: true,
+                })
+                .await
+                .unwrap();
+        }
+        let sink = &seats[0].0 .1;
+        assert!(!drain(sink).await.iter().any(|m| m.starts_with("投票通过")));
+
+        // the hold expires, a bot casts the missing vote
+        actix_rt::time::delay_for(STAND_IN_INTERVAL + Duration::from_millis(100)).await;
+        let told = drain(sink).await;
+        assert!(told.contains(&"seat 5 (guest4) is played by a bot now".to_owned()));
+        assert!(told.contains(&"投票通过：5 票赞成，0 票反对，已连续否决 0 次".to_owned()));
+    }
+
+    #[actix_rt::test]
+    async fn test_token_status() {
+        let server = ChatServer::new(ServerConfig {
+            seat_hold_ttl: Duration::from_millis(50),
+            ..ServerConfig::default()
+        })
+        .start();
+        let seats = started_room(&server, 5).await;
+        let ((guest_id, _), guest_name) = &seats[1];
+        let name = server
+            .send(RoomCode { id: *guest_id })
+            .await
+            .unwrap()
+            .unwrap();
+        let status = |session_name: &str| TokenStatus {
+            session_name: session_name.to_owned(),
+            name: name.clone(),
+        };
+        assert_eq!(
+            server.send(status(guest_name)).await.unwrap(),
+            TokenState::Unknown
+        );
+
+        server.send(Leave { id: *guest_id }).await.unwrap();
+        assert_eq!(
+            server.send(status(guest_name)).await.unwrap(),
+            TokenState::Valid
+        );
+        assert_eq!(
+            server.send(status("guest9")).await.unwrap(),
+            TokenState::Unknown
+        );
+
+        actix_rt::time::delay_for(Duration::from_millis(60)).await;
+        assert_eq!(
+            server.send(status(guest_name)).await.unwrap(),
+            TokenState::Expired
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_staggered_reveal() {
+        let server = ChatServer::new(ServerConfig {
+            reveal_gap: Duration::from_millis(10),
+            ..ServerConfig::default()
+        })
+        .start();
+        let owner = connect(&server).await;
+        let name = create_room(&server, &owner, 5).await;
+        let mut seats = vec![owner];
+        for i in 1..5 {
+            let guest = connect(&server).await;
+            join_room(&server, &guest, &format!("guest{}", i), &name).await;
+            seats.push(guest);
+        }
+
+        // the last seat is told 40ms later
+        let told = drain(&seats[4].1).await;
+        assert!(!told.iter().any(|m| m.starts_with("你的身份是")));
+
+        actix::clock::delay_for(Duration::from_millis(100)).await;
+        for (_, sink) in &seats[..4] {
+            let told = drain(sink).await;
+            assert!(told.iter().any(|m| m.starts_with("你的身份是")));
+        }
+        let told = drain(&seats[4].1).await;
+        assert!(told[0].starts_with("你的身份是"));
+    }
+
+    #[actix_rt::test]
+    async fn test_join_with_forged_name() {
+        let server = ChatServer::default().start();
+        let owner = connect(&server).await;
+        let guest = connect(&server).await;
+        let name = create_room(&server, &owner, 5).await;
+
+        join_room(&server, &guest, "guest\n人已经凑齐", &name).await;
+        assert!(drain(&owner.1).await.is_empty());
+        assert_eq!(
+            drain(&guest.1).await,
+            vec!["!!! invalid name: name contains control characters"]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_limits() {
+        let server = ChatServer::default().start();
+        assert_eq!(
+            server.send(Limits).await.unwrap(),
+            (game::LOWER_ROOM_SIZE, game::UPPER_ROOM_SIZE)
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_load() {
+        let server = ChatServer::default().start();
+        assert_eq!(server.send(Load).await.unwrap(), (0, 0));
+
+        let owner = connect(&server).await;
+        let guest = connect(&server).await;
+        create_room(&server, &owner, 5).await;
+        assert_eq!(server.send(Load).await.unwrap(), (2, 1));
+
+        for (id, _) in &[owner, guest] {
+            server
+                .send(Disconnect {
+                    id: *id,
+                    reason: DisconnectReason::Left,
+                })
+                .await
+                .unwrap();
         }
+        assert_eq!(server.send(Load).await.unwrap(), (0, 0));
+    }
 
-        MessageResult(rooms)
+    #[actix_rt::test]
+    async fn test_max_sessions() {
+        let server = ChatServer::new(ServerConfig {
+            max_sessions: Some(2),
+            ..ServerConfig::default()
+        })
+        .start();
+        let first = connect(&server).await;
+        let second = connect(&server).await;
+        assert_ne!(second.0, REFUSED_ID);
+        assert_eq!(connect(&server).await.0, REFUSED_ID);
+        assert_eq!(server.send(Load).await.unwrap(), (2, 0));
+
+        server
+            .send(Disconnect {
+                id: first.0,
+                reason: DisconnectReason::Left,
+            })
+            .await
+            .unwrap();
+        assert_ne!(connect(&server).await.0, REFUSED_ID);
     }
-}
 
-/// Join room, send disconnect message to old room
-/// send join message to new room
-impl Handler<Join> for ChatServer {
-    type Result = ();
+    #[actix_rt::test]
+    async fn test_create_with_key() {
+        let server = ChatServer::default().start();
+        let (id, sink) = connect(&server).await;
+        let create = || Create {
+            id,
+            session_name: "owner".to_owned(),
+            size: 5,
+            options: RoomOptions::default(),
+            key: Some("retry".to_owned()),
+        };
 
-    fn handle(&mut self, msg: Join, _: &mut Context<Self>) {
-        let Join {
+        let first = create_room_with(&server, &sink, create()).await;
+        let second = create_room_with(&server, &sink, create()).await;
+        assert_eq!(first, second);
+        assert_eq!(server.send(ListRooms).await.unwrap(), vec![first.clone()]);
+
+        // the key stays with the creator once the room is handed over
+        let guest = connect(&server).await;
+        join_room(&server, &guest, "guest", &first).await;
+        server
+            .send(TransferHost {
+                id,
+                target_name: "guest".to_owned(),
+            })
+            .await
+            .unwrap();
+        drain(&sink).await;
+        assert_eq!(create_room_with(&server, &sink, create()).await, first);
+        let other = Create {
+            id: guest.0,
+            ..create()
+        };
+        drain(&guest.1).await;
+        assert_ne!(create_room_with(&server, &guest.1, other).await, first);
+    }
+
+    #[actix_rt::test]
+    async fn test_transfer_host() {
+        let server = ChatServer::default().start();
+        let owner = connect(&server).await;
+        let guest = connect(&server).await;
+        let name = create_room(&server, &owner, 5).await;
+        join_room(&server, &guest, "guest", &name).await;
+        drain(&guest.1).await;
+
+        server
+            .send(TransferHost {
+                id: guest.0,
+                target_name: "guest".to_owned(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            drain(&guest.1).await,
+            vec!["!!! only the room owner can transfer the room"]
+        );
+
+        server
+            .send(TransferHost {
+                id: owner.0,
+                target_name: "nobody".to_owned(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            drain(&owner.1).await.last().unwrap(),
+            "!!! nobody is not seated in this room"
+        );
+
+        server
+            .send(TransferHost {
+                id: owner.0,
+                target_name: "guest".to_owned(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(drain(&guest.1).await, vec!["guest is the room owner now"]);
+
+        // a bot seat would leave the room without an owner
+        server.send(AddBot { id: guest.0 }).await.unwrap();
+        drain(&guest.1).await;
+        server
+            .send(TransferHost {
+                id: guest.0,
+                target_name: "bot1".to_owned(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(drain(&guest.1).await, vec!["!!! bot1 can't own the room"]);
+
+        // the new owner can resize, the old one can't any more
+        server
+            .send(Resize {
+                id: owner.0,
+                size: 6,
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            drain(&owner.1).await.last().unwrap(),
+            "!!! only the room owner can resize the room"
+        );
+        server
+            .send(Resize {
+                id: guest.0,
+                size: 6,
+            })
+            .await
+            .unwrap();
+        assert_eq!(drain(&guest.1).await, vec!["room size changed to 6"]);
+    }
+
+    #[actix_rt::test]
+    async fn test_message_kind() {
+        let server = ChatServer::default().start();
+        let owner = connect(&server).await;
+        let name = create_room(&server, &owner, 5).await;
+        server.send(WhoAmI { id: owner.0 }).await.unwrap();
+        for i in 1..5 {
+            let guest = connect(&server).await;
+            join_room(&server, &guest, &format!("guest{}", i), &name).await;
+        }
+
+        let told = owner.1.send(DrainMessages).await.unwrap();
+        assert_eq!(told[0].kind, MessageKind::Error);
+        let hint = told
+            .iter()
+            .find(|msg| msg.text.starts_with("你的身份是"))
+            .unwrap();
+        assert_eq!(hint.kind, MessageKind::Private);
+        let full = told.iter().find(|msg| msg.text == "人已经凑齐").unwrap();
+        assert_eq!(full.kind, MessageKind::Broadcast);
+    }
+
+    /// Fill a room of `size`, seats are returned in order with their names
+    async fn started_room(
+        server: &Addr<ChatServer>,
+        size: u8,
+    ) -> Vec<((usize, Addr<Sink>), String)> {
+        started_room_with(server, size, RoomOptions::default()).await
+    }
+
+    async fn started_room_with(
+        server: &Addr<ChatServer>,
+        size: u8,
+        options: RoomOptions,
+    ) -> Vec<((usize, Addr<Sink>), String)> {
+        let owner = connect(server).await;
+        let msg = Create {
+            id: owner.0,
+            session_name: "owner".to_owned(),
+            size,
+            options,
+            key: None,
+        };
+        let name = create_room_with(server, &owner.1, msg).await;
+        let mut seats = vec![(owner, "owner".to_owned())];
+        for i in 1..size {
+            let guest = connect(server).await;
+            let guest_name = format!("guest{}", i);
+            join_room(server, &guest, &guest_name, &name).await;
+            seats.push((guest, guest_name));
+        }
+        for ((_, sink), _) in &seats {
+            drain(sink).await;
+        }
+        seats
+    }
+
+    #[actix_rt::test]
+    async fn test_five_rejects() {
+        let server = ChatServer::default().start();
+        let seats = started_room(&server, 5).await;
+        let team = vec![seats[0].1.clone(), seats[1].1.clone()];
+
+        for round in 0..game::MAX_REJECTS {
+            // the leader moves on after each rejection
+            let ((leader, _), _) = &seats[round];
+            server
+                .send(ProposeTeam {
+                    id: *leader,
+                    names: team.clone(),
+                })
+                .await
+                .unwrap();
+            for ((_, sink), _) in &seats {
+                drain(sink).await;
+            }
+            for (i, ((id, sink), _)) in seats.iter().enumerate() {
+                server
+                    .send(CastVote {
+                        id: *id,
+                        approve: false,
+                    })
+                    .await
+                    .unwrap();
+                // missing votes block the result
+                if i < 4 {
+                    assert!(!drain(sink).await.iter().any(|m| m.starts_with("投票否决")));
+                }
+            }
+        }
+
+        let told = drain(&seats[0].0 .1).await;
+        assert!(told.contains(&"投票否决：0 票赞成，5 票反对，已连续否决 5 次".to_owned()));
+        assert_eq!(told.last().unwrap(), "连续 5 次否决，坏人获胜");
+    }
+
+    #[actix_rt::test]
+    async fn test_second_game() {
+        let server = ChatServer::default().start();
+        let seats = started_room(&server, 5).await;
+        let ((owner_id, owner_sink), _) = &seats[0];
+        for round in 0..game::MAX_REJECTS {
+            server
+                .send(ProposeTeam {
+                    id: seats[round].0 .0,
+                    names: vec![seats[0].1.clone(), seats[1].1.clone()],
+                })
+                .await
+                .unwrap();
+            for ((id, _), _) in &seats {
+                server
+                    .send(CastVote {
+                        id: *id,
+                        approve: false,
+                    })
+                    .await
+                    .unwrap();
+            }
+        }
+        for ((_, sink), _) in &seats {
+            drain(sink).await;
+        }
+
+        // the room is a lobby again, with everyone seated
+        server.send(GameStatus { id: *owner_id }).await.unwrap();
+        assert_eq!(drain(owner_sink).await, vec!["!!! game not started"]);
+        server.send(Start { id: *owner_id }).await.unwrap();
+        for ((_, sink), _) in &seats {
+            assert!(drain(sink)
+                .await
+                .iter()
+                .any(|m| m.starts_with("你的身份是")));
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_propose_by_leader_only() {
+        let server = ChatServer::default().start();
+        let seats = started_room(&server, 5).await;
+        let ((guest, sink), _) = &seats[1];
+
+        server
+            .send(ProposeTeam {
+                id: *guest,
+                names: vec!["owner".to_owned(), "guest1".to_owned()],
+            })
+            .await
+            .unwrap();
+        assert_eq!(drain(sink).await, vec!["!!! you are not the leader"]);
+
+        server
+            .send(CastVote {
+                id: *guest,
+                approve: true,
+            })
+            .await
+            .unwrap();
+        assert_eq!(drain(sink).await, vec!["!!! not allowed now"]);
+    }
+
+    /// Propose `names` as the team and approve it by everyone
+    async fn approve_team(
+        server: &Addr<ChatServer>,
+        seats: &[((usize, Addr<Sink>), String)],
+        leader: usize,
+        names: Vec<String>,
+    ) {
+        server
+            .send(ProposeTeam {
+                id: seats[leader].0 .0,
+                names,
+            })
+            .await
+            .unwrap();
+        for ((id, _), _) in seats {
+            server
+                .send(CastVote {
+                    id: *id,
+                    approve: true,
+                })
+                .await
+                .unwrap();
+        }
+        for ((_, sink), _) in seats {
+            drain(sink).await;
+        }
+    }
+
+    /// Seat of each alliance in a seeded 5 players room
+    async fn seeded_seats(
+        server: &Addr<ChatServer>,
+    ) -> (Vec<((usize, Addr<Sink>), String)>, Vec<game::Alliance>) {
+        let options = RoomOptions {
+            seed: Some(42),
+            ..RoomOptions::default()
+        };
+        let seats = started_room_with(server, 5, options).await;
+        let alliances = game::deal_with_seed(5, 42)
+            .unwrap()
+            .into_iter()
+            .map(|role| role.alliance())
+            .collect();
+        (seats, alliances)
+    }
+
+    #[actix_rt::test]
+    async fn test_error_code() {
+        let server = ChatServer::default().start();
+        let (id, sink) = connect(&server).await;
+        let codes = || async {
+            sink.send(DrainMessages)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|msg| msg.code)
+                .collect::<Vec<_>>()
+        };
+
+        let join = Join {
             id,
-            session_name,
+            session_name: "guest".to_owned(),
+            name: "missing".to_owned(),
+        };
+        server.send(join).await.unwrap();
+        assert_eq!(codes().await, vec![Some(ErrorCode::RoomNotFound)]);
+
+        // the name in the text doesn't steer the code
+        let seats = started_room(&server, 5).await;
+        let name = server
+            .send(RoomCode { id: seats[0].0 .0 })
+            .await
+            .unwrap()
+            .unwrap();
+        let rejoin = Rejoin {
+            id,
+            session_name: "room is full".to_owned(),
             name,
-        } = msg;
+        };
+        server.send(rejoin).await.unwrap();
+        assert_eq!(codes().await, vec![Some(ErrorCode::NoSeatHeld)]);
 
-        if !self.rooms.contains_key(&name) {
-            self.send_message_to_user(id, format!("!!! room not exist"));
-            return;
+        server
+            .send(CastVote {
+                id: seats[0].0 .0,
+                approve: true,
+            })
+            .await
+            .unwrap();
+        let told = seats[0].0 .1.send(DrainMessages).await.unwrap();
+        assert_eq!(told[0].code, Some(ErrorCode::WrongPhase));
+
+        assert_eq!(
+            ErrorCode::of_error(&GameError::InvalidSeat(6).into()),
+            ErrorCode::InvalidTeam
+        );
+        assert_eq!(
+            ErrorCode::of_error(&format_err!("something new")),
+            ErrorCode::Other
+        );
+        assert_eq!(
+            serde_json::to_value(ErrorCode::RoomNotFound).unwrap(),
+            "ROOM_NOT_FOUND"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_vote_visibility() {
+        for &secret_votes in &[false, true] {
+            let server = ChatServer::default().start();
+            let options = RoomOptions {
+                secret_votes,
+                ..RoomOptions::default()
+            };
+            let seats = started_room_with(&server, 5, options).await;
+            server
+                .send(ProposeTeam {
+                    id: seats[0].0 .0,
+                    names: vec![seats[0].1.clone(), seats[1].1.clone()],
+                })
+                .await
+                .unwrap();
+            for (seat, ((id, _), _)) in seats.iter().enumerate() {
+                server
+                    .send(CastVote {
+                        id: *id,
+                        approve: seat < 2,
+                    })
+                    .await
+                    .unwrap();
+            }
+
+            let told = drain(&seats[4].0 .1).await;
+            let tally = told.iter().position(|m| m.starts_with("投票否决")).unwrap();
+            assert_eq!(told[tally], "投票否决：2 票赞成，3 票反对，已连续否决 1 次");
+            let breakdown = "赞成：owner、guest1；反对：guest2、guest3、guest4";
+            assert_eq!(told.contains(&breakdown.to_owned()), !secret_votes);
         }
+    }
 
-        self.remove_user_from_all_rooms(id);
+    #[actix_rt::test]
+    async fn test_english_announcements() {
+        let server = ChatServer::default().start();
+        let options = RoomOptions {
+            locale: Locale::En,
+            ..RoomOptions::default()
+        };
+        let seats = started_room_with(&server, 5, options).await;
+        let team = vec![seats[0].1.clone(), seats[1].1.clone()];
+        server
+            .send(ProposeTeam {
+                id: seats[0].0 .0,
+                names: team.clone(),
+            })
+            .await
+            .unwrap();
+        for ((id, _), _) in &seats {
+            server
+                .send(CastVote {
+                    id: *id,
+                    approve: true,
+                })
+                .await
+                .unwrap();
+        }
+        for ((id, _), _) in &seats[..2] {
+            server
+                .send(SubmitMission {
+                    id: *id,
+                    fail: false,
+                })
+                .await
+                .unwrap();
+        }
 
-        let is_full = match self.rooms.get_mut(&name) {
-            Some(room) => {
-                room.sessions.insert(id);
-                // FIXME: check duplicated name
-                room.seats.push((id, session_name.clone()));
+        let told = drain(&seats[4].0 .1).await;
+        let team = team.join(", ");
+        for expected in &[
+            format!(
+                "the leader proposes {}, /vote approve or /vote reject",
+                team
+            ),
+            "team approved: 5 approved, 0 rejected, 0 rejected in a row".to_owned(),
+            format!(
+                "{}, /mission success or /mission fail to play the mission",
+                team
+            ),
+            "mission passed: 2 successes, 0 fails".to_owned(),
+        ] {
+            assert!(told.contains(expected), "{:?}", told);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_vote_breakdown_locale() {
+        let server = ChatServer::default().start();
+        let options = RoomOptions {
+            locale: Locale::En,
+            ..RoomOptions::default()
+        };
+        let seats = started_room_with(&server, 5, options).await;
+        server
+            .send(ProposeTeam {
+                id: seats[0].0 .0,
+                names: vec![seats[0].1.clone(), seats[1].1.clone()],
+            })
+            .await
+            .unwrap();
+        for ((id, _), _) in &seats {
+            server
+                .send(CastVote {
+                    id: *id,
+                    approve: false,
+                })
+                .await
+                .unwrap();
+        }
+
+        let told = drain(&seats[4].0 .1).await;
+        let breakdown = "approved: none; rejected: owner, guest1, guest2, guest3, guest4";
+        assert!(told.contains(&breakdown.to_owned()), "{:?}", told);
+    }
+
+    #[actix_rt::test]
+    async fn test_passed_mission() {
+        let server = ChatServer::default().start();
+        let (seats, _) = seeded_seats(&server).await;
+        let team = vec![seats[0].1.clone(), seats[1].1.clone()];
+        approve_team(&server, &seats, 0, team).await;
+
+        // the third seat isn't on the team
+        let ((outsider, sink), _) = &seats[2];
+        server
+            .send(SubmitMission {
+                id: *outsider,
+                fail: false,
+            })
+            .await
+            .unwrap();
+        assert_eq!(drain(sink).await, vec!["!!! you are not on the team"]);
+
+        for ((id, _), _) in &seats[..2] {
+            server
+                .send(SubmitMission {
+                    id: *id,
+                    fail: false,
+                })
+                .await
+                .unwrap();
+        }
+        let told = drain(sink).await;
+        assert_eq!(told[0], "任务成功：2 张成功，0 张失败");
+    }
+
+    #[actix_rt::test]
+    async fn test_game_status() {
+        let server = ChatServer::default().start();
+        let owner = connect(&server).await;
+        create_room(&server, &owner, 5).await;
+        server.send(GameStatus { id: owner.0 }).await.unwrap();
+        assert_eq!(drain(&owner.1).await, vec!["!!! game not started"]);
+
+        let (seats, _) = seeded_seats(&server).await;
+        let team = vec![seats[0].1.clone(), seats[1].1.clone()];
+        approve_team(&server, &seats, 0, team).await;
+        for ((id, _), _) in &seats[..2] {
+            server
+                .send(SubmitMission {
+                    id: *id,
+                    fail: false,
+                })
+                .await
+                .unwrap();
+        }
+        let ((id, sink), _) = &seats[3];
+        drain(sink).await;
+        server.send(GameStatus { id: *id }).await.unwrap();
+        assert_eq!(
+            drain(sink).await,
+            vec![format!(
+                "✔ · · · · leader: {}, mission: 2, rejects: 0",
+                seats[1].1
+            )]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_sabotaged_mission() {
+        let server = ChatServer::default().start();
+        let (seats, alliances) = seeded_seats(&server).await;
+        let spy = alliances
+            .iter()
+            .position(|&alliance| alliance == game::Alliance::Spy)
+            .unwrap();
+        let good = alliances
+            .iter()
+            .position(|&alliance| alliance == game::Alliance::Resistance)
+            .unwrap();
+        // the first mission of 5 players takes 2, pick a spy and a good one
+        let team = vec![seats[spy].1.clone(), seats[good].1.clone()];
+        approve_team(&server, &seats, 0, team).await;
+
+        let ((good_id, good_sink), _) = &seats[good];
+        server
+            .send(SubmitMission {
+                id: *good_id,
+                fail: true,
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            drain(good_sink).await,
+            vec!["!!! only spies can fail a mission"]
+        );
 
-                room.is_full()
+        server
+            .send(SubmitMission {
+                id: *good_id,
+                fail: false,
+            })
+            .await
+            .unwrap();
+        server
+            .send(SubmitMission {
+                id: seats[spy].0 .0,
+                fail: true,
+            })
+            .await
+            .unwrap();
+        let told = drain(good_sink).await;
+        assert!(told.contains(&"任务失败：1 张成功，1 张失败".to_owned()));
+    }
+
+    #[actix_rt::test]
+    async fn test_assassinate() {
+        let server = ChatServer::default().start();
+        let (seats, _) = seeded_seats(&server).await;
+        let roles = game::deal_with_seed(5, 42).unwrap();
+        let good: Vec<usize> = (0..5)
+            .filter(|&seat| roles[seat].alliance() == Alliance::Resistance)
+            .collect();
+        // 5 players missions take 2, 3 then 2, the leader moves on each time
+        for (leader, size) in [2, 3, 2].iter().enumerate() {
+            let team = good[..*size].iter().map(|&seat| seats[seat].1.clone());
+            approve_team(&server, &seats, leader, team.collect()).await;
+            for &seat in &good[..*size] {
+                server
+                    .send(SubmitMission {
+                        id: seats[seat].0 .0,
+                        fail: false,
+                    })
+                    .await
+                    .unwrap();
             }
-            None => {
-                self.send_message_to_user(
-                    id,
-                    format!("!!! room not exist, may be deleted just now"),
-                );
-                return;
+        }
+        let merlin = &seats[roles.iter().position(|&role| role == Merlin).unwrap()].1;
+        let assassin = roles.iter().position(|&role| role == Assassin).unwrap();
+        let ((assassin_id, sink), _) = &seats[assassin];
+        assert_eq!(
+            drain(sink).await.last().unwrap(),
+            "好人赢下了 3 个任务，请刺客用 /assassinate <名字> 刺杀梅林"
+        );
+
+        let other = (assassin + 1) % 5;
+        let ((other_id, other_sink), _) = &seats[other];
+        drain(other_sink).await;
+        server
+            .send(Assassinate {
+                id: *other_id,
+                target_name: merlin.clone(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            drain(other_sink).await,
+            vec!["!!! only the assassin can assassinate"]
+        );
+
+        server
+            .send(Assassinate {
+                id: *assassin_id,
+                target_name: merlin.clone(),
+            })
+            .await
+            .unwrap();
+        assert!(drain(sink).await.contains(&format!(
+            "刺客刺杀了 {}，{}是梅林，坏人获胜",
+            merlin, merlin
+        )));
+    }
+
+    #[actix_rt::test]
+    async fn test_inspect_loyalty() {
+        let server = ChatServer::default().start();
+        let options = RoomOptions {
+            seed: Some(42),
+            rules: GameRules {
+                lady_of_the_lake: true,
+                ..GameRules::default()
+            },
+            ..RoomOptions::default()
+        };
+        let seats = started_room_with(&server, 5, options).await;
+        let roles = game::deal_with_seed(5, 42).unwrap();
+        // the first two missions take 2 then 3, everyone passes
+        for (leader, size) in [2, 3].iter().enumerate() {
+            let team = seats[..*size].iter().map(|(_, name)| name.clone());
+            approve_team(&server, &seats, leader, team.collect()).await;
+            for ((id, _), _) in &seats[..*size] {
+                server
+                    .send(SubmitMission {
+                        id: *id,
+                        fail: false,
+                    })
+                    .await
+                    .unwrap();
             }
+        }
+        // the lady starts on the right of the first leader
+        let ((holder_id, holder_sink), holder_name) = &seats[4];
+        assert_eq!(
+            drain(holder_sink).await.last().unwrap(),
+            &format!(
+                "{} 持有湖中仙女，请用 /lake <名字> 查验一名玩家的阵营",
+                holder_name
+            )
+        );
+
+        let ((other_id, other_sink), target_name) = &seats[0];
+        drain(other_sink).await;
+        server
+            .send(InspectLoyalty {
+                id: *other_id,
+                target_name: holder_name.clone(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            drain(other_sink).await,
+            vec!["!!! you don't hold the lady of the lake"]
+        );
+
+        server
+            .send(InspectLoyalty {
+                id: *holder_id,
+                target_name: target_name.clone(),
+            })
+            .await
+            .unwrap();
+        let told = drain(holder_sink).await;
+        assert_eq!(
+            told[0],
+            format!("{} 是{}", target_name, roles[0].alliance())
+        );
+        let transfer = format!(
+            "{} 查验了 {}，湖中仙女交给了 {}",
+            holder_name, target_name, target_name
+        );
+        assert_eq!(told[1], transfer);
+        // the others only learn who holds the lady now
+        assert_eq!(drain(other_sink).await[0], transfer);
+    }
+
+    #[actix_rt::test]
+    async fn test_spectator_count() {
+        let server = ChatServer::default().start();
+        let owner = connect(&server).await;
+        let name = create_room(&server, &owner, 5).await;
+        let spectator = connect(&server).await;
+
+        server
+            .send(Watch {
+                id: spectator.0,
+                name: name.clone(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(drain(&owner.1).await, vec!["watching: 1"]);
+        assert_eq!(
+            drain(&spectator.1).await,
+            vec![format!("watching room {}", name), "watching: 1".to_owned()]
+        );
+        assert_eq!(
+            server.send(ListRooms).await.unwrap(),
+            vec![format!("{} (watching: 1)", name)]
+        );
+
+        server.send(Leave { id: spectator.0 }).await.unwrap();
+        assert_eq!(drain(&owner.1).await, vec!["watching: 0"]);
+        assert_eq!(server.send(ListRooms).await.unwrap(), vec![name]);
+    }
+
+    #[actix_rt::test]
+    async fn test_review() {
+        let server = ChatServer::default().start();
+        let viewer = connect(&server).await;
+        let owner = connect(&server).await;
+        let name = create_room(&server, &owner, 5).await;
+        server
+            .send(Review {
+                id: viewer.0,
+                name: name.clone(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            drain(&viewer.1).await,
+            vec![format!("!!! no finished game in room {}", name)]
+        );
+
+        let mut seats = vec![(owner, "owner".to_owned())];
+        for i in 1..5 {
+            let guest = connect(&server).await;
+            let guest_name = format!("guest{}", i);
+            join_room(&server, &guest, &guest_name, &name).await;
+            seats.push((guest, guest_name));
+        }
+        reject_five(&server, &seats).await;
+
+        server
+            .send(Review {
+                id: viewer.0,
+                name: name.clone(),
+            })
+            .await
+            .unwrap();
+        let events = drain(&viewer.1).await;
+        assert_eq!(
+            events[0],
+            "owner 是队长，第 1 个任务需要 2 人，请用 /propose 提名队员"
+        );
+        assert_eq!(
+            events[1],
+            "队长提名了 owner、guest1，请用 /vote approve 或 /vote reject 投票"
+        );
+        assert_eq!(events[2], "投票否决：0 票赞成，5 票反对，已连续否决 1 次");
+        assert_eq!(events[events.len() - 2], "连续 5 次否决，坏人获胜");
+        assert!(events.last().unwrap().starts_with("阵营：owner "));
+        assert!(!events.iter().any(|m| m.starts_with("你的身份是")));
+    }
+
+    #[actix_rt::test]
+    async fn test_long_name() {
+        let server = ChatServer::new(ServerConfig {
+            names: NamePolicy {
+                max_len: 5,
+                ..NamePolicy::default()
+            },
+            ..ServerConfig::default()
+        })
+        .start();
+        let rejected = vec!["!!! invalid name: name is longer than 5 characters"];
+        let owner = connect(&server).await;
+        let msg = Create {
+            id: owner.0,
+            session_name: "longer".to_owned(),
+            size: 5,
+            options: RoomOptions::default(),
+            key: None,
         };
+        server.send(msg).await.unwrap();
+        assert_eq!(drain(&owner.1).await, rejected);
 
-        self.broadcast_message(&name, &format!("{} connected", &session_name), Some(id));
-        self.send_message_to_user(id, format!("joined"));
-        if is_full {
-            self.broadcast_message(&name, "人已经凑齐", None);
-            if let Err(err) = self.assign_and_notify(&name) {
-                self.broadcast_message(&name, &format!("分配失败：{}", err), None);
+        let name = create_room(&server, &owner, 5).await;
+        let guest = connect(&server).await;
+        join_room(&server, &guest, "longer", &name).await;
+        assert_eq!(drain(&guest.1).await, rejected);
+    }
+
+    #[actix_rt::test]
+    async fn test_blocked_name() {
+        let server = ChatServer::new(ServerConfig {
+            names: NamePolicy {
+                blocklist: Blocklist::new(vec!["admin".to_owned()]),
+                ..NamePolicy::default()
+            },
+            ..ServerConfig::default()
+        })
+        .start();
+        let owner = connect(&server).await;
+        let name = create_room(&server, &owner, 5).await;
+
+        let guest = connect(&server).await;
+        join_room(&server, &guest, "RealAdmin", &name).await;
+        assert_eq!(
+            drain(&guest.1).await,
+            vec!["!!! invalid name: name is not allowed"]
+        );
+        join_room(&server, &guest, "guest", &name).await;
+        assert_eq!(drain(&guest.1).await, vec!["joined"]);
+    }
+
+    #[actix_rt::test]
+    async fn test_overflow() {
+        // a lobby waiting for the host keeps seating whoever joins
+        let server = ChatServer::default().start();
+        let owner = connect(&server).await;
+        let msg = Create {
+            id: owner.0,
+            session_name: "owner".to_owned(),
+            size: 5,
+            options: RoomOptions {
+                manual_start: true,
+                ..RoomOptions::default()
+            },
+            key: None,
+        };
+        let name = create_room_with(&server, &owner.1, msg).await;
+        let mut guests = Vec::new();
+        for i in 1..7 {
+            let guest = connect(&server).await;
+            join_room(&server, &guest, &format!("guest{}", i), &name).await;
+            guests.push(guest);
+        }
+        drain(&owner.1).await;
+        for guest in &guests {
+            drain(&guest.1).await;
+        }
+
+        server.send(Overflow { id: guests[0].0 }).await.unwrap();
+        assert_eq!(
+            drain(&guests[0].1).await,
+            vec!["!!! only the room owner can split the room"]
+        );
+
+        server.send(Overflow { id: owner.0 }).await.unwrap();
+        let told = drain(&owner.1).await;
+        assert_eq!(told.len(), 1);
+        let new_name = told[0]
+            .trim_start_matches("2 players moved to room ")
+            .to_owned();
+        assert_ne!(new_name, name);
+        for guest in &guests[..4] {
+            assert_eq!(
+                drain(&guest.1).await,
+                vec![format!("2 players moved to room {}", new_name)]
+            );
+        }
+        for guest in &guests[4..] {
+            assert_eq!(
+                drain(&guest.1).await,
+                vec![format!("moved to room {}", new_name)]
+            );
+        }
+
+        // nothing more to split
+        server.send(Overflow { id: owner.0 }).await.unwrap();
+        assert_eq!(drain(&owner.1).await, vec!["!!! room is not over capacity"]);
+        // the first moved seat owns the new room
+        server.send(Overflow { id: guests[4].0 }).await.unwrap();
+        assert_eq!(
+            drain(&guests[4].1).await,
+            vec!["!!! room is not over capacity"]
+        );
+        assert_eq!(
+            server.send(RoomCode { id: guests[5].0 }).await.unwrap(),
+            Some(new_name)
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_broadcast_to_session_zero() {
+        // ids are random, 0 is a real session rather than "skip nobody"
+        let mut server = ChatServer::default();
+        let sinks: Vec<_> = (0..2).map(|_| Sink::default().start()).collect();
+        for (id, sink) in sinks.iter().enumerate() {
+            server.sessions.insert(id, sink.clone().recipient());
+        }
+        server
+            .rooms
+            .insert("lobby".to_owned(), seated_room(2, RoomOptions::default()));
+
+        server.broadcast_message("lobby", "hello", None);
+        server.broadcast_message("lobby", "from p1", Some(1));
+        assert_eq!(drain(&sinks[0]).await, vec!["hello", "from p1"]);
+        assert_eq!(drain(&sinks[1]).await, vec!["hello"]);
+    }
+
+    /// Reject proposals until the spies win
+    async fn reject_five(server: &Addr<ChatServer>, seats: &[((usize, Addr<Sink>), String)]) {
+        for round in 0..game::MAX_REJECTS {
+            server
+                .send(ProposeTeam {
+                    id: seats[round].0 .0,
+                    names: vec![seats[0].1.clone(), seats[1].1.clone()],
+                })
+                .await
+                .unwrap();
+            for ((id, _), _) in seats {
+                server
+                    .send(CastVote {
+                        id: *id,
+                        approve: false,
+                    })
+                    .await
+                    .unwrap();
             }
-            self.rooms.remove(&name);
         }
     }
-}
 
-/// Create, send disconnect message to old room
-/// send join message to new room
-impl Handler<Create> for ChatServer {
-    type Result = ();
+    #[actix_rt::test]
+    async fn test_reveal_on_end() {
+        let server = ChatServer::default().start();
+        let seats = started_room(&server, 5).await;
+        reject_five(&server, &seats).await;
+        let told = drain(&seats[1].0 .1).await;
+        assert_eq!(told.last().unwrap(), "连续 5 次否决，坏人获胜");
 
-    fn handle(&mut self, msg: Create, _: &mut Context<Self>) {
-        let Create {
+        let options = RoomOptions {
+            seed: Some(42),
+            reveal_on_end: true,
+            ..RoomOptions::default()
+        };
+        let seats = started_room_with(&server, 5, options).await;
+        reject_five(&server, &seats).await;
+        let told = drain(&seats[1].0 .1).await;
+        let roles: Vec<String> = seats
+            .iter()
+            .zip(game::deal_with_seed(5, 42).unwrap())
+            .map(|((_, name), role)| format!("{} {}（{}）", name, role, role.alliance()))
+            .collect();
+        assert_eq!(told.last().unwrap(), &format!("身份：{}", roles.join("、")));
+    }
+
+    #[actix_rt::test]
+    async fn test_set_reveal_on_end() {
+        let server = ChatServer::default().start();
+        let owner = connect(&server).await;
+        let guest = connect(&server).await;
+        let name = create_room(&server, &owner, 5).await;
+        join_room(&server, &guest, "guest", &name).await;
+        drain(&guest.1).await;
+
+        server
+            .send(SetRevealOnEnd {
+                id: guest.0,
+                enabled: true,
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            drain(&guest.1).await,
+            vec!["!!! only the room owner can change it"]
+        );
+        server
+            .send(SetRevealOnEnd {
+                id: owner.0,
+                enabled: true,
+            })
+            .await
+            .unwrap();
+        assert_eq!(drain(&guest.1).await, vec!["reveal on end: on"]);
+    }
+
+    #[actix_rt::test]
+    async fn test_pause() {
+        let server = ChatServer::default().start();
+        let seats = started_room(&server, 5).await;
+        let owner = &seats[0].0;
+        let guest = &seats[1].0;
+        server
+            .send(ProposeTeam {
+                id: owner.0,
+                names: vec!["owner".to_owned(), "guest1".to_owned()],
+            })
+            .await
+            .unwrap();
+        drain(&guest.1).await;
+
+        server
+            .send(SetPaused {
+                id: guest.0,
+                paused: true,
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            drain(&guest.1).await,
+            vec!["!!! only the room owner can change it"]
+        );
+
+        server
+            .send(SetPaused {
+                id: owner.0,
+                paused: true,
+            })
+            .await
+            .unwrap();
+        assert_eq!(drain(&guest.1).await, vec!["game paused"]);
+        server
+            .send(CastVote {
+                id: guest.0,
+                approve: true,
+            })
+            .await
+            .unwrap();
+        assert_eq!(drain(&guest.1).await, vec!["!!! game is paused"]);
+
+        server
+            .send(SetPaused {
+                id: owner.0,
+                paused: false,
+            })
+            .await
+            .unwrap();
+        assert_eq!(drain(&guest.1).await, vec!["game resumed"]);
+        server
+            .send(CastVote {
+                id: guest.0,
+                approve: true,
+            })
+            .await
+            .unwrap();
+        assert_eq!(drain(&guest.1).await, vec!["voted"]);
+    }
+
+    #[actix_rt::test]
+    async fn test_suffix_duplicate_names() {
+        let server = ChatServer::default().start();
+        let owner = connect(&server).await;
+        let msg = Create {
+            id: owner.0,
+            session_name: "owner".to_owned(),
+            size: 5,
+            options: RoomOptions {
+                suffix_duplicate_names: true,
+                ..RoomOptions::default()
+            },
+            key: None,
+        };
+        let name = create_room_with(&server, &owner.1, msg).await;
+        let first = connect(&server).await;
+        join_room(&server, &first, "Alice", &name).await;
+        assert_eq!(drain(&first.1).await, vec!["joined"]);
+        let second = connect(&server).await;
+        join_room(&server, &second, "Alice", &name).await;
+        assert_eq!(drain(&second.1).await, vec!["joined as Alice(2)"]);
+        assert_eq!(
+            drain(&owner.1).await,
+            vec!["Alice connected", "Alice(2) connected"]
+        );
+
+        // without the option the second one is refused
+        let plain = create_room(&server, &connect(&server).await, 5).await;
+        join_room(&server, &first, "Alice", &plain).await;
+        drain(&first.1).await;
+        drain(&second.1).await;
+        join_room(&server, &second, "Alice", &plain).await;
+        assert_eq!(
+            drain(&second.1).await,
+            vec!["!!! Alice is seated in this room already"]
+        );
+
+        let seats = vec![(0, "Alice".to_owned()), (1, "Alice(2)".to_owned())];
+        assert_eq!(unique_name(&seats, "Alice".to_owned()), "Alice(3)");
+        assert_eq!(unique_name(&seats, "Bob".to_owned()), "Bob");
+    }
+
+    #[actix_rt::test]
+    async fn test_mute() {
+        let server = ChatServer::default().start();
+        let owner = connect(&server).await;
+        let guest = connect(&server).await;
+        let name = create_room(&server, &owner, 5).await;
+        join_room(&server, &guest, "guest", &name).await;
+        drain(&owner.1).await;
+        drain(&guest.1).await;
+
+        let say = |id, text: &str| ChatText {
             id,
-            session_name,
-            size,
-        } = msg;
-        let name: u32 = self.rng.gen_range(0, 1000);
-        let name = name.to_string();
-        if self.rooms.contains_key(&name) {
-            // TODO: better random number
-            self.send_message_to_user(id, "!!! create room failed".to_owned());
-            return;
+            text: text.to_owned(),
+        };
+        server.send(say(guest.0, "hi")).await.unwrap();
+        assert_eq!(drain(&owner.1).await, vec!["guest: hi"]);
+        assert_eq!(drain(&guest.1).await, vec!["guest: hi"]);
+        // a newline would forge a server line
+        server.send(say(guest.0, "hi\n人已经凑齐")).await.unwrap();
+        assert!(drain(&owner.1).await.is_empty());
+        assert_eq!(
+            drain(&guest.1).await,
+            vec!["!!! invalid text: text contains control characters"]
+        );
+
+        let mute = |id, muted| Mute {
+            id,
+            target_name: "guest".to_owned(),
+            muted,
+        };
+        server.send(mute(guest.0, true)).await.unwrap();
+        assert_eq!(
+            drain(&guest.1).await,
+            vec!["!!! only the room owner can change it"]
+        );
+        server.send(mute(owner.0, true)).await.unwrap();
+        assert_eq!(drain(&owner.1).await, vec!["guest muted"]);
+        assert_eq!(
+            drain(&guest.1).await,
+            vec!["you are muted by the room owner"]
+        );
+
+        // the muted player still reads the room
+        server.send(say(guest.0, "spam")).await.unwrap();
+        assert!(drain(&owner.1).await.is_empty());
+        assert_eq!(
+            drain(&guest.1).await,
+            vec!["!!! you are muted by the room owner"]
+        );
+        server.send(say(owner.0, "calm down")).await.unwrap();
+        assert_eq!(drain(&guest.1).await, vec!["owner: calm down"]);
+
+        server.send(mute(owner.0, false)).await.unwrap();
+        assert_eq!(
+            drain(&owner.1).await,
+            vec!["owner: calm down", "guest unmuted"]
+        );
+        drain(&guest.1).await;
+        server.send(say(guest.0, "sorry")).await.unwrap();
+        assert_eq!(drain(&owner.1).await, vec!["guest: sorry"]);
+    }
+
+    #[actix_rt::test]
+    async fn test_bots() {
+        let server = ChatServer::new(ServerConfig {
+            bot_delay: Duration::default(),
+            ..ServerConfig::default()
+        })
+        .start();
+        let owner = connect(&server).await;
+        let guest = connect(&server).await;
+        let name = create_room(&server, &owner, 5).await;
+        join_room(&server, &guest, "guest", &name).await;
+        server.send(AddBot { id: guest.0 }).await.unwrap();
+        assert_eq!(
+            drain(&guest.1).await.last().unwrap(),
+            "!!! only the room owner can add bots"
+        );
+        server.send(Leave { id: guest.0 }).await.unwrap();
+        for _ in 0..4 {
+            server.send(AddBot { id: owner.0 }).await.unwrap();
         }
+        drain(&owner.1).await;
 
-        self.remove_user_from_all_rooms(id);
+        server
+            .send(ProposeTeam {
+                id: owner.0,
+                names: vec!["owner".to_owned(), "bot1".to_owned()],
+            })
+            .await
+            .unwrap();
+        server
+            .send(CastVote {
+                id: owner.0,
+                approve: true,
+            })
+            .await
+            .unwrap();
+        actix_rt::time::delay_for(Duration::from_millis(50)).await;
+        let told = drain(&owner.1).await;
+        assert!(told.contains(&"投票通过：5 票赞成，0 票反对，已连续否决 0 次".to_owned()));
 
-        self.send_message_to_user(id, format!("room {} created.", &name));
-        self.send_message_to_user(id, "请把房间号告诉你的小伙伴们".to_owned());
-        let mut sessions = BTreeSet::new();
-        sessions.insert(id);
-        let seats = vec![(id, session_name)];
-        self.rooms.insert(
-            name.clone(),
-            Room {
-                sessions,
-                size,
-                seats,
-            },
+        server
+            .send(SubmitMission {
+                id: owner.0,
+                fail: false,
+            })
+            .await
+            .unwrap();
+        actix_rt::time::delay_for(Duration::from_millis(50)).await;
+        let told = drain(&owner.1).await;
+        assert!(told.iter().any(|m| m.starts_with("任务")));
+        // the next leader is a bot, it proposes on its own
+        assert!(told
+            .iter()
+            .any(|m| m.starts_with("队长提名了 bot1、bot2、bot3")));
+    }
+
+    #[actix_rt::test]
+    async fn test_observer() {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let observed = events.clone();
+        let server = ChatServer::default()
+            .with_observer(Box::new(move |event: &ServerEvent| {
+                observed.borrow_mut().push(event.clone())
+            }))
+            .start();
+        let seats = started_room(&server, 5).await;
+        reject_five(&server, &seats).await;
+
+        let events = events.borrow();
+        assert_eq!(events.len(), 7);
+        let room = match &events[0] {
+            ServerEvent::RoomCreated { room } => room.clone(),
+            event => panic!("unexpected event: {:?}", event),
+        };
+        for (i, event) in events[1..5].iter().enumerate() {
+            assert_eq!(
+                event,
+                &ServerEvent::PlayerJoined {
+                    room: room.clone(),
+                    name: format!("guest{}", i + 1),
+                }
+            );
+        }
+        assert_eq!(events[5], ServerEvent::GameStarted { room: room.clone() });
+        assert_eq!(
+            events[6],
+            ServerEvent::GameEnded {
+                room,
+                winner: Alliance::Spy,
+            }
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_event_channel() {
+        let (sender, mut receiver) = broadcast::channel(16);
+        let server = ChatServer::default().with_event_channel(sender).start();
+        let owner = connect(&server).await;
+        let name = create_room(&server, &owner, 5).await;
+        assert_eq!(
+            receiver.recv().await.unwrap(),
+            ServerEvent::RoomCreated { room: name }
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_stats() {
+        let server = ChatServer::default()
+            .with_outcome_sink(Box::new(Vec::new()))
+            .start();
+        let options = RoomOptions {
+            seed: Some(42),
+            ..RoomOptions::default()
+        };
+        let seats = started_room_with(&server, 5, options).await;
+        reject_five(&server, &seats).await;
+
+        let ((id, sink), _) = &seats[0];
+        drain(sink).await;
+        let role = game::deal_with_seed(5, 42).unwrap()[0];
+        server
+            .send(Stats {
+                id: *id,
+                name: "owner".to_owned(),
+            })
+            .await
+            .unwrap();
+        let expected = match role.alliance() {
+            game::Alliance::Resistance => "games: 1, wins: 0, 好人: 0/1, 坏人: 0/0",
+            game::Alliance::Spy => "games: 1, wins: 1, 好人: 0/0, 坏人: 1/1",
+        };
+        assert_eq!(drain(sink).await, vec![expected]);
+
+        server
+            .send(Stats {
+                id: *id,
+                name: "nobody".to_owned(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            drain(sink).await,
+            vec!["games: 0, wins: 0, 好人: 0/0, 坏人: 0/0"]
         );
     }
+
+    #[actix_rt::test]
+    async fn test_close_room() {
+        let server = ChatServer::default().start();
+        let owner = connect(&server).await;
+        let guest = connect(&server).await;
+        let name = create_room(&server, &owner, 5).await;
+        join_room(&server, &guest, "guest", &name).await;
+        drain(&guest.1).await;
+
+        assert!(server.send(CloseRoom { name: name.clone() }).await.unwrap());
+        assert_eq!(
+            drain(&guest.1).await,
+            vec![format!("room {} is closed by admin", name)]
+        );
+        assert!(server.send(ListRooms).await.unwrap().is_empty());
+        assert!(!server.send(CloseRoom { name }).await.unwrap());
+    }
+
+    #[actix_rt::test]
+    async fn test_authenticate() {
+        let server = ChatServer::default().start();
+        let token = || Authenticate {
+            token: "secret".to_owned(),
+        };
+        assert!(!server.send(token()).await.unwrap());
+
+        let server = ChatServer::new(ServerConfig {
+            admin_token: Some("secret".to_owned()),
+            ..ServerConfig::default()
+        })
+        .start();
+        assert!(server.send(token()).await.unwrap());
+        assert!(!server
+            .send(Authenticate {
+                token: "guess".to_owned()
+            })
+            .await
+            .unwrap());
+    }
 }
@@ -1,19 +1,261 @@
-use std::time::{Duration, Instant};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant, SystemTime};
 
 use actix::*;
+use actix_http::ws::Item;
+use actix_web::{web, HttpRequest, HttpResponse};
 use actix_web_actors::ws;
+use failure::{format_err, Error};
 use log::*;
+use serde::Serialize;
 
+use crate::command::{self, Command};
 use crate::game;
+use crate::locale;
+use crate::log_context::LogContext;
+use crate::name::NamePolicy;
 use crate::server;
 
 /// How often heartbeat pings are sent
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
 /// How long before lack of client response causes a timeout
 const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Max size of a message, the same as a single frame
+const MAX_MESSAGE_SIZE: usize = 65_536;
+
+/// Version of the JSON protocol, bumped on incompatible changes
+const PROTOCOL_VERSION: u32 = 1;
+/// Version of the crate the server is built from
+const SERVER_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Messages sent without hearing a pong, before the peer is dropped
+const DEFAULT_MAX_PENDING: usize = 1024;
+
+const DEFAULT_WELCOME: &str = "欢迎来到阿瓦隆，先用 /name <名字> 设置名字，/help 查看所有命令";
+
+const HELP: &str = "\
+/name <名字>  设置名字
+/create <人数> [种子] [shuffle] [reveal] [onefail] [lake] [simple] [suffix] [manual] [ready] [secret] [min=<人数>] [key=<键>] [lang=<zh|en>]  创建房间，shuffle 打乱座位，reveal 结束时公布所有身份，onefail 第四个任务也只需一张失败，lake 使用湖中仙女，simple 五六人局不用派西维尔和莫甘娜，suffix 重名时自动加上编号而不是拒绝加入，manual 坐满后等房主 /start，ready 坐满后等所有人 /ready，secret 只公布投票的票数，min 为提前开始的最少人数，lang 为提示语言，重试时用同一个键不会重复创建
+/join <房间号>  加入房间
+/watch <房间号>  旁观房间
+/rejoin <房间号>  用同样的名字拿回掉线前的座位
+/reconnect-status <房间号>  查看掉线前的座位是否还保留着
+/review <房间号>  回看房间里刚结束的一局
+/list  列出所有房间
+/open  列出可以加入的房间
+/games  列出正在进行的对局和进度
+/resize <人数>  修改房间人数（房主）
+/host <名字>  把房主交给别人（房主）
+/overflow  把超出人数的座位分到新房间（房主）
+/addbot  让机器人坐一个空位（房主）
+/revealall on|off  结束时是否公布所有身份（房主）
+/pause  暂停游戏（房主）
+/resume  继续游戏（房主）
+/limits  房间人数范围
+/load  服务器上的连接数和房间数
+/roster  列出房间里的座位、房主和准备状态
+/propose <名字> <名字> ...  提名执行任务的队员（队长）
+/vote approve|reject  对提名投票
+/mission success|fail  执行任务（队员）
+/assassinate <名字>  刺杀梅林（刺客）
+/lake <名字>  查验一名玩家的阵营（湖中仙女持有者）
+/say <话>  在房间里说话
+/mute <名字>  禁止玩家在房间里说话（房主）
+/unmute <名字>  允许玩家在房间里说话（房主）
+/stats  查看自己的战绩（按当前名字统计，同名的人共用一份战绩）
+/leave  离开房间
+/whoami  再看一次自己的身份
+/reveal  向房间公开自己的身份
+/need  查看房间还差几人
+/code  再看一次房间号
+/deck  查看房间里有哪些角色
+/rules  查看房间里每个角色的规则
+/preview  试发一次牌，只看角色分布（房主）
+/shuffle  开始前打乱座位顺序（房主）
+/start  人数达到最少开局人数时提前开始（房主）
+/abort  中止这一局，回到等人状态（房主）
+/ready  准备好了，ready 房间坐满且所有人准备后才发牌
+/elapsed  查看房间创建了多久
+/status  查看任务进度、队长和否决次数
+/protocol text|json  服务器消息的格式
+/auth <令牌>  登录管理员
+/close <房间号>  关闭房间（管理员）
+/sandbox <人数>  试发一局牌，列出每个座位的身份和提示
+/see <座位号>  只看上一次试发中一个座位的身份和提示
+/version  查看服务器和协议版本
+/help  显示帮助";
+
+/// Entry point for our route
+pub async fn chat_route(
+    req: HttpRequest,
+    stream: web::Payload,
+    srv: web::Data<Addr<server::ChatServer>>,
+    config: web::Data<SessionConfig>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // a bad handshake fails before the IP is counted, an admitted IP is
+    // always released by its session
+    ws::handshake(&req)?;
+    let ip = client_ip(&req, config.trust_forwarded);
+    if let Some(ip) = &ip {
+        let admitted = srv
+            .send(server::Admit { ip: ip.clone() })
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        if !admitted {
+            warn!("too many connections from {}", ip);
+            return Ok(HttpResponse::TooManyRequests().finish());
+        }
+    }
+
+    let mut session = WsChatSession::new(srv.get_ref().clone(), config.get_ref().clone());
+    session.ip = ip;
+    ws::start(session, &req, stream)
+}
+
+/// IP of the peer, as told by the forwarding headers if `trust_forwarded`.
+/// Clients set these headers at will, only a proxy in front may be trusted.
+fn client_ip(req: &HttpRequest, trust_forwarded: bool) -> Option<String> {
+    if !trust_forwarded {
+        return req.peer_addr().map(|addr| addr.ip().to_string());
+    }
+    let remote = req.connection_info().remote()?.to_owned();
+    // the peer address comes with the port
+    match remote.parse::<SocketAddr>() {
+        Ok(addr) => Some(addr.ip().to_string()),
+        Err(_) => Some(remote),
+    }
+}
+
+/// Settings shared by all sessions
+#[derive(Clone)]
+pub struct SessionConfig {
+    /// Text sent to peer once connected
+    pub welcome: String,
+    /// Blocked words and max length of names
+    pub names: NamePolicy,
+    /// Max number of messages sent to a peer which doesn't answer pings
+    pub max_pending: usize,
+    /// Protocol of new sessions, until the peer changes it
+    pub protocol: Protocol,
+    /// Payload of heartbeat pings, empty by default
+    pub ping_payload: Vec<u8>,
+    /// Take the client IP from the forwarding headers, set by a trusted
+    /// proxy in front
+    pub trust_forwarded: bool,
+}
+
+impl Default for SessionConfig {
+    fn default() -> SessionConfig {
+        SessionConfig {
+            welcome: DEFAULT_WELCOME.to_owned(),
+            names: NamePolicy::default(),
+            max_pending: DEFAULT_MAX_PENDING,
+            protocol: Protocol::default(),
+            ping_payload: Vec::new(),
+            trust_forwarded: false,
+        }
+    }
+}
+
+/// How messages from the chat server are written to the peer
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Protocol {
+    /// Bare text, for humans and legacy clients
+    #[default]
+    Text,
+    /// JSON object with kind and timestamp
+    Json,
+}
+
+/// A server message in JSON protocol
+#[derive(Serialize)]
+struct Envelope<'a> {
+    kind: server::MessageKind,
+    /// Only for errors
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<server::ErrorCode>,
+    text: &'a str,
+    /// RFC 3339 timestamp
+    time: String,
+}
+
+/// What the server supports, told to JSON peers so they don't have to assume
+#[derive(Serialize)]
+pub struct Capabilities {
+    /// Always "capabilities", to tell it from server messages
+    pub kind: &'static str,
+    pub version: u32,
+    pub server_version: &'static str,
+    pub commands: Vec<&'static str>,
+    pub min_players: usize,
+    pub max_players: usize,
+    pub locales: Vec<&'static str>,
+}
+
+impl Capabilities {
+    pub fn current() -> Capabilities {
+        Capabilities {
+            kind: "capabilities",
+            version: PROTOCOL_VERSION,
+            server_version: SERVER_VERSION,
+            commands: HELP
+                .lines()
+                .filter_map(|line| line.split_whitespace().next())
+                .collect(),
+            min_players: game::LOWER_ROOM_SIZE,
+            max_players: game::UPPER_ROOM_SIZE,
+            locales: locale::all_locales().iter().map(|l| l.code()).collect(),
+        }
+    }
+}
+
+/// Format `msg` as the peer expects
+pub fn render(protocol: Protocol, msg: &server::Message) -> String {
+    match protocol {
+        Protocol::Text => msg.text.clone(),
+        // already a JSON object of its own kind
+        Protocol::Json if msg.kind == server::MessageKind::Summary => msg.text.clone(),
+        Protocol::Json => {
+            // humantime can't format times before the epoch
+            let time = msg.time.max(SystemTime::UNIX_EPOCH);
+            let envelope = Envelope {
+                kind: msg.kind,
+                code: msg.code,
+                text: &msg.text,
+                time: humantime::format_rfc3339_millis(time).to_string(),
+            };
+            serde_json::to_string(&envelope).unwrap_or_default()
+        }
+    }
+}
+
+/// Code of a malformed command
+fn parse_error_code(err: &command::ParseError) -> server::ErrorCode {
+    match err {
+        command::ParseError::UnknownCommand(_) => server::ErrorCode::UnknownCommand,
+        command::ParseError::InvalidSize(_)
+        | command::ParseError::UnsupportedSize(_)
+        | command::ParseError::MinStartOverSize { .. } => server::ErrorCode::InvalidSize,
+        command::ParseError::Required(_)
+        | command::ParseError::UnknownOption(_)
+        | command::ParseError::Expected { .. } => server::ErrorCode::InvalidCommand,
+    }
+}
+
+/// What `seat` of a sandbox deal is and sees, e.g. `#2 刺客：#4 都是坏人`
+fn sandbox_view(assignment: &game::Assignment, seat: usize) -> Option<String> {
+    let (name, role) = assignment.get_player(seat)?;
+    let seeing = assignment.see_from_role(role).text();
+    if seeing.is_empty() {
+        Some(format!("{} {}", name, role))
+    } else {
+        Some(format!("{} {}：{}", name, role, seeing))
+    }
+}
 
 pub struct WsChatSession {
-    /// unique session id
+    /// unique session id, `REFUSED_ID` until the chat server takes the
+    /// connect
     pub id: usize,
     /// Client must send ping at least once per 10 seconds (CLIENT_TIMEOUT),
     /// otherwise we drop connection.
@@ -22,6 +264,107 @@ pub struct WsChatSession {
     pub name: Option<String>,
     /// Chat server
     pub addr: Addr<server::ChatServer>,
+    /// Fragmented message being received
+    pub fragments: Fragments,
+    pub config: SessionConfig,
+    /// Format of messages from the chat server
+    pub protocol: Protocol,
+    /// Messages which may not be read yet
+    pub outbox: Outbox,
+    /// Admin commands are allowed once authenticated
+    pub is_admin: bool,
+    /// Client IP counted by the chat server
+    pub ip: Option<String>,
+    /// Last `/sandbox` deal, to look at one seat with `/see`
+    pub sandbox: Option<game::Assignment>,
+}
+
+/// Counts messages sent since the peer last answered a ping.
+///
+/// Frames are written in order, so a pong means everything sent before the
+/// ping has been read. A peer which stops reading stops answering too, and
+/// the count keeps growing.
+pub struct Outbox {
+    pending: usize,
+    limit: usize,
+}
+
+impl Outbox {
+    pub fn new(limit: usize) -> Outbox {
+        Outbox { pending: 0, limit }
+    }
+
+    /// Count a message to be sent, false if the peer is too far behind
+    pub fn send(&mut self) -> bool {
+        if self.pending >= self.limit {
+            return false;
+        }
+        self.pending += 1;
+        true
+    }
+
+    /// The peer has caught up
+    pub fn flush(&mut self) {
+        self.pending = 0;
+    }
+}
+
+/// Reassembly buffer for messages sent in continuation frames
+#[derive(Default)]
+pub struct Fragments {
+    /// Payload received so far, `None` if no fragmented message is in flight
+    buf: Option<Vec<u8>>,
+    /// Binary messages are unexpected, their payload is dropped
+    binary: bool,
+}
+
+impl Fragments {
+    /// Feed a continuation frame, the whole text is returned once the last
+    /// frame arrives.
+    pub fn push(&mut self, item: Item) -> Result<Option<String>, Error> {
+        let (payload, last) = match item {
+            Item::FirstText(_) | Item::FirstBinary(_) if self.buf.is_some() => {
+                self.buf = None;
+                return Err(format_err!("continuation already started"));
+            }
+            Item::FirstText(payload) => {
+                self.binary = false;
+                self.buf = Some(Vec::new());
+                (payload, false)
+            }
+            Item::FirstBinary(payload) => {
+                self.binary = true;
+                self.buf = Some(Vec::new());
+                (payload, false)
+            }
+            Item::Continue(payload) => (payload, false),
+            Item::Last(payload) => (payload, true),
+        };
+
+        let buf = match self.buf.as_mut() {
+            Some(buf) => buf,
+            None => return Err(format_err!("continuation not started")),
+        };
+        if buf.len() + payload.len() > MAX_MESSAGE_SIZE {
+            self.buf = None;
+            return Err(format_err!("message exceeds {} bytes", MAX_MESSAGE_SIZE));
+        }
+        if !self.binary {
+            buf.extend_from_slice(&payload);
+        }
+        if !last {
+            return Ok(None);
+        }
+
+        let buf = self.buf.take().unwrap_or_default();
+        if self.binary {
+            warn!("Unexpected binary");
+            return Ok(None);
+        }
+        String::from_utf8(buf)
+            .map(Some)
+            .map_err(|_| format_err!("invalid utf-8 text"))
+    }
 }
 
 impl Actor for WsChatSession {
@@ -42,11 +385,26 @@ impl Actor for WsChatSession {
         self.addr
             .send(server::Connect {
                 addr: addr.recipient(),
+                ip: self.ip.clone(),
             })
             .into_actor(self)
             .then(|res, act, ctx| {
                 match res {
-                    Ok(res) => act.id = res,
+                    Ok(server::REFUSED_ID) => {
+                        act.send_error(
+                            ctx,
+                            server::ErrorCode::ServerFull,
+                            "!!! server is full, try again later".to_owned(),
+                        );
+                        ctx.stop();
+                    }
+                    Ok(res) => {
+                        act.id = res;
+                        act.send_system(ctx, act.config.welcome.clone());
+                        if act.protocol == Protocol::Json {
+                            act.send_capabilities(ctx);
+                        }
+                    }
                     // something is wrong with chat server
                     _ => ctx.stop(),
                 }
@@ -56,8 +414,15 @@ impl Actor for WsChatSession {
     }
 
     fn stopping(&mut self, _: &mut Self::Context) -> Running {
+        // never connected, the id belongs to nobody
+        if self.id == server::REFUSED_ID {
+            return Running::Stop;
+        }
         // notify chat server
-        self.addr.do_send(server::Disconnect { id: self.id });
+        self.addr.do_send(server::Disconnect {
+            id: self.id,
+            reason: server::DisconnectReason::Left,
+        });
         Running::Stop
     }
 }
@@ -67,7 +432,18 @@ impl Handler<server::Message> for WsChatSession {
     type Result = ();
 
     fn handle(&mut self, msg: server::Message, ctx: &mut Self::Context) {
-        ctx.text(msg.0);
+        if msg.kind == server::MessageKind::Summary && self.protocol == Protocol::Text {
+            return;
+        }
+        if !self.outbox.send() {
+            warn!(
+                "{} stopped reading, disconnecting",
+                LogContext::session(self.id)
+            );
+            ctx.stop();
+            return;
+        }
+        ctx.text(render(self.protocol, &msg));
     }
 }
 
@@ -82,7 +458,11 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsChatSession {
             Ok(msg) => msg,
         };
 
-        debug!("WEBSOCKET MESSAGE: {:?}", msg);
+        debug!(
+            "{} websocket message: {:?}",
+            LogContext::session(self.id),
+            msg
+        );
         match msg {
             ws::Message::Ping(msg) => {
                 self.hb = Instant::now();
@@ -90,124 +470,574 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsChatSession {
             }
             ws::Message::Pong(_) => {
                 self.hb = Instant::now();
+                self.outbox.flush();
             }
-            ws::Message::Text(text) => {
-                let m = text.trim();
-                // we check for /sss type of messages
-                if m.starts_with('/') {
-                    let v: Vec<&str> = m.splitn(2, ' ').collect();
-                    match v[0] {
-                        "/list" => {
-                            // Send ListRooms message to chat server and wait for
-                            // response
-                            info!("List rooms");
-                            self.addr
-                                .send(server::ListRooms)
-                                .into_actor(self)
-                                .then(|res, _, ctx| {
-                                    match res {
-                                        Ok(rooms) => {
-                                            for room in rooms {
-                                                ctx.text(room);
-                                            }
-                                        }
-                                        _ => warn!("Something is wrong"),
-                                    }
-                                    fut::ready(())
-                                })
-                                .wait(ctx)
-                            // .wait(ctx) pauses all events in context,
-                            // so actor wont receive any new messages until it get list
-                            // of rooms back
-                        }
-                        "/join" => {
-                            match (self.name.as_ref(), &v[1..]) {
-                                (Some(session_name), [name]) => {
-                                    self.addr.do_send(server::Join {
-                                        id: self.id,
-                                        name: name.to_string(),
-                                        session_name: session_name.clone(),
-                                    });
+            ws::Message::Text(text) => self.handle_text(&text, ctx),
+            ws::Message::Binary(_) => warn!("Unexpected binary"),
+            ws::Message::Close(_) => {
+                ctx.stop();
+            }
+            ws::Message::Continuation(item) => match self.fragments.push(item) {
+                Ok(Some(text)) => self.handle_text(&text, ctx),
+                Ok(None) => (),
+                Err(err) => {
+                    warn!(
+                        "{} bad fragmented message: {}",
+                        LogContext::session(self.id),
+                        err
+                    );
+                    ctx.stop();
+                }
+            },
+            ws::Message::Nop => (),
+        }
+    }
+}
+
+impl WsChatSession {
+    pub fn new(addr: Addr<server::ChatServer>, config: SessionConfig) -> WsChatSession {
+        WsChatSession {
+            id: server::REFUSED_ID,
+            hb: Instant::now(),
+            name: None,
+            addr,
+            fragments: Fragments::default(),
+            outbox: Outbox::new(config.max_pending),
+            is_admin: false,
+            ip: None,
+            sandbox: None,
+            protocol: config.protocol,
+            config,
+        }
+    }
+
+    fn send_capabilities(&self, ctx: &mut ws::WebsocketContext<Self>) {
+        ctx.text(serde_json::to_string(&Capabilities::current()).unwrap_or_default());
+    }
+
+    /// Tell the peer its request failed, like the chat server does
+    fn send_error(
+        &self,
+        ctx: &mut ws::WebsocketContext<Self>,
+        code: server::ErrorCode,
+        text: String,
+    ) {
+        self.send(ctx, &server::Message::error(code, text));
+    }
+
+    /// Reply the peer directly, rendered like the chat server messages
+    fn send_system(&self, ctx: &mut ws::WebsocketContext<Self>, text: impl Into<String>) {
+        self.send(
+            ctx,
+            &server::Message::new(server::MessageKind::System, text.into()),
+        );
+    }
+
+    fn send(&self, ctx: &mut ws::WebsocketContext<Self>, msg: &server::Message) {
+        ctx.text(render(self.protocol, msg));
+    }
+
+    /// Handle a text message from peer, it should be a command
+    fn handle_text(&mut self, text: &str, ctx: &mut ws::WebsocketContext<Self>) {
+        let command = match command::parse(text) {
+            Ok(command) => command,
+            Err(err) => {
+                self.send_error(ctx, parse_error_code(&err), format!("!!! {}", err));
+                return;
+            }
+        };
+
+        match command {
+            Command::List => {
+                // Send ListRooms message to chat server and wait for
+                // response
+                info!("{} list rooms", LogContext::session(self.id));
+                self.addr
+                    .send(server::ListRooms)
+                    .into_actor(self)
+                    .then(|res, act, ctx| {
+                        match res {
+                            Ok(rooms) => {
+                                for room in rooms {
+                                    act.send_system(ctx, room);
                                 }
-                                (None, _) => {
-                                    ctx.text("!!! session name is required");
+                            }
+                            _ => warn!("{} something is wrong", LogContext::session(act.id)),
+                        }
+                        fut::ready(())
+                    })
+                    .wait(ctx)
+                // .wait(ctx) pauses all events in context,
+                // so actor wont receive any new messages until it get list
+                // of rooms back
+            }
+            Command::Open => {
+                // Same as `/list`, but only rooms that can be joined
+                self.addr
+                    .send(server::ListOpenRooms)
+                    .into_actor(self)
+                    .then(|res, act, ctx| {
+                        match res {
+                            Ok(rooms) => {
+                                for room in rooms {
+                                    act.send_system(ctx, room);
                                 }
-                                (Some(_), []) => {
-                                    ctx.text("!!! room name is required");
+                            }
+                            _ => warn!("{} something is wrong", LogContext::session(act.id)),
+                        }
+                        fut::ready(())
+                    })
+                    .wait(ctx)
+            }
+            Command::Games => self
+                .addr
+                .send(server::ListGames)
+                .into_actor(self)
+                .then(|res, act, ctx| {
+                    match res {
+                        Ok(games) => {
+                            for game in games {
+                                act.send_system(ctx, game);
+                            }
+                        }
+                        _ => warn!("{} something is wrong", LogContext::session(act.id)),
+                    }
+                    fut::ready(())
+                })
+                .wait(ctx),
+            Command::Limits => self
+                .addr
+                .send(server::Limits)
+                .into_actor(self)
+                .then(|res, act, ctx| {
+                    match res {
+                        Ok((lower, upper)) => act.send_system(ctx, format!("{}-{}", lower, upper)),
+                        _ => warn!("{} something is wrong", LogContext::session(act.id)),
+                    }
+                    fut::ready(())
+                })
+                .wait(ctx),
+            Command::Load => self
+                .addr
+                .send(server::Load)
+                .into_actor(self)
+                .then(|res, act, ctx| {
+                    match res {
+                        Ok((sessions, rooms)) => act
+                            .send_system(ctx, format!("sessions: {}, rooms: {}", sessions, rooms)),
+                        _ => warn!("{} something is wrong", LogContext::session(act.id)),
+                    }
+                    fut::ready(())
+                })
+                .wait(ctx),
+            Command::Roster => self
+                .addr
+                .send(server::Roster { id: self.id })
+                .into_actor(self)
+                .then(|res, act, ctx| {
+                    match res {
+                        Ok(Some(roster)) if act.protocol == Protocol::Json => {
+                            let roster = serde_json::json!({ "kind": "roster", "players": roster });
+                            ctx.text(roster.to_string());
+                        }
+                        Ok(Some(roster)) => {
+                            for entry in roster {
+                                let mut line = format!("#{} {}", entry.seat + 1, entry.name);
+                                if entry.owner {
+                                    line.push_str(" [owner]");
                                 }
-                                _ => {
-                                    ctx.text("!!! unknown command");
+                                if entry.ready {
+                                    line.push_str(" [ready]");
                                 }
-                            };
+                                act.send_system(ctx, line);
+                            }
                         }
-                        "/create" => {
-                            match (self.name.as_ref(), &v[1..]) {
-                                (Some(session_name), [size_arg]) => {
-                                    match size_arg.parse::<usize>() {
-                                        Ok(
-                                            size @ game::LOWER_ROOM_SIZE..=game::UPPER_ROOM_SIZE,
-                                        ) => {
-                                            self.addr.do_send(server::Create {
-                                                id: self.id,
-                                                size: size as u8,
-                                                session_name: session_name.clone(),
-                                            });
-                                        }
-                                        Ok(size) => {
-                                            ctx.text(format!(
-                                                "!!! room size {} is not supported. it should be in range {}-{}",
-                                                size, game::LOWER_ROOM_SIZE, game::UPPER_ROOM_SIZE,
-                                            ));
-                                        }
-                                        _ => {
-                                            ctx.text(format!(
-                                                "!!! invalid room size: {}",
-                                                size_arg
-                                            ));
-                                        }
-                                    };
-                                }
-                                (None, _) => {
-                                    ctx.text("!!! session name is required");
+                        Ok(None) => act.send_error(
+                            ctx,
+                            server::ErrorCode::NotInRoom,
+                            "!!! you are not in a room".to_owned(),
+                        ),
+                        _ => warn!("{} something is wrong", LogContext::session(act.id)),
+                    }
+                    fut::ready(())
+                })
+                .wait(ctx),
+            Command::Join { room } => match self.name.as_ref() {
+                Some(session_name) => {
+                    self.addr.do_send(server::Join {
+                        id: self.id,
+                        name: room,
+                        session_name: session_name.clone(),
+                    });
+                }
+                None => {
+                    self.send_error(
+                        ctx,
+                        server::ErrorCode::NameRequired,
+                        "!!! session name is required".to_owned(),
+                    );
+                }
+            },
+            Command::Rejoin { room } => match self.name.as_ref() {
+                Some(session_name) => {
+                    self.addr.do_send(server::Rejoin {
+                        id: self.id,
+                        name: room,
+                        session_name: session_name.clone(),
+                    });
+                }
+                None => {
+                    self.send_error(
+                        ctx,
+                        server::ErrorCode::NameRequired,
+                        "!!! session name is required".to_owned(),
+                    );
+                }
+            },
+            Command::ReconnectStatus { room } => match self.name.as_ref() {
+                Some(session_name) => {
+                    self.addr
+                        .send(server::TokenStatus {
+                            session_name: session_name.clone(),
+                            name: room,
+                        })
+                        .into_actor(self)
+                        .then(|res, act, ctx| {
+                            match res {
+                                Ok(server::TokenState::Valid) => {
+                                    act.send_system(ctx, "seat held, /rejoin to take it back")
                                 }
-                                (Some(_), []) => {
-                                    ctx.text("!!! size is required");
+                                Ok(server::TokenState::Expired) => {
+                                    act.send_system(ctx, "seat hold expired")
                                 }
-                                _ => {
-                                    ctx.text("!!! unknown command");
+                                Ok(server::TokenState::Unknown) => {
+                                    act.send_system(ctx, "no seat held")
                                 }
-                            };
-                        }
-                        "/name" => match &v[1..] {
-                            [name] => {
-                                self.name = Some(name.to_string());
+                                _ => warn!("{} something is wrong", LogContext::session(act.id)),
                             }
-                            [] => {
-                                ctx.text("!!! name is required");
-                            }
-                            _ => {
-                                ctx.text("!!! unknown command");
+                            fut::ready(())
+                        })
+                        .wait(ctx);
+                }
+                None => {
+                    self.send_error(
+                        ctx,
+                        server::ErrorCode::NameRequired,
+                        "!!! session name is required".to_owned(),
+                    );
+                }
+            },
+            Command::Watch { room } => {
+                self.addr.do_send(server::Watch {
+                    id: self.id,
+                    name: room,
+                });
+            }
+            Command::Review { room } => {
+                self.addr.do_send(server::Review {
+                    id: self.id,
+                    name: room,
+                });
+            }
+            Command::Create { size, options, key } => match self.name.as_ref() {
+                Some(session_name) => {
+                    self.addr.do_send(server::Create {
+                        id: self.id,
+                        size,
+                        session_name: session_name.clone(),
+                        options,
+                        key,
+                    });
+                }
+                None => {
+                    self.send_error(
+                        ctx,
+                        server::ErrorCode::NameRequired,
+                        "!!! session name is required".to_owned(),
+                    );
+                }
+            },
+            Command::Help => {
+                self.send_system(ctx, HELP);
+            }
+            Command::Sandbox { size } => {
+                let names = (1..=size as usize).map(|seat| format!("#{}", seat));
+                let deal = game::RoleSet::standard(size as usize).and_then(|role_set| {
+                    game::Assignment::with_role_set(
+                        names,
+                        &role_set,
+                        &game::GameRules::default(),
+                        None,
+                    )
+                });
+                match deal {
+                    Ok(assignment) => {
+                        for seat in 0..assignment.player_number() {
+                            if let Some(view) = sandbox_view(&assignment, seat) {
+                                self.send_system(ctx, view);
                             }
-                        },
-                        _ => ctx.text(format!("!!! unknown command: {:?}", m)),
+                        }
+                        self.sandbox = Some(assignment);
                     }
-                } else {
-                    ctx.text(format!("!!! unknown command: {:?}", m))
+                    Err(err) => self.send_error(
+                        ctx,
+                        server::ErrorCode::of_error(&err),
+                        format!("!!! {}", err),
+                    ),
                 }
             }
-            ws::Message::Binary(_) => warn!("Unexpected binary"),
-            ws::Message::Close(_) => {
-                ctx.stop();
+            Command::See { seat } => {
+                let view = match &self.sandbox {
+                    Some(assignment) => seat
+                        .checked_sub(1)
+                        .and_then(|seat| sandbox_view(assignment, seat))
+                        .ok_or_else(|| {
+                            (
+                                server::ErrorCode::InvalidSeat,
+                                format!("!!! invalid seat: {}", seat),
+                            )
+                        }),
+                    None => Err((
+                        server::ErrorCode::NoSandbox,
+                        "!!! no sandbox deal, /sandbox <人数> first".to_owned(),
+                    )),
+                };
+                match view {
+                    Ok(view) => self.send_system(ctx, view),
+                    Err((code, err)) => self.send_error(ctx, code, err),
+                }
             }
-            ws::Message::Continuation(_) => {
-                ctx.stop();
+            Command::Version => {
+                self.send_system(
+                    ctx,
+                    format!(
+                        "avalon-rs {}, protocol {}",
+                        SERVER_VERSION, PROTOCOL_VERSION
+                    ),
+                );
             }
-            ws::Message::Nop => (),
+            Command::Leave => {
+                self.addr.do_send(server::Leave { id: self.id });
+            }
+            Command::AddBot => {
+                self.addr.do_send(server::AddBot { id: self.id });
+            }
+            Command::Deck => {
+                self.addr.do_send(server::RoomDeck { id: self.id });
+            }
+            Command::Rules => {
+                self.addr.do_send(server::RoomRules { id: self.id });
+            }
+            Command::Preview => {
+                self.addr.do_send(server::PreviewDeal { id: self.id });
+            }
+            Command::Shuffle => {
+                self.addr.do_send(server::ShuffleSeats { id: self.id });
+            }
+            Command::Start => {
+                self.addr.do_send(server::Start { id: self.id });
+            }
+            Command::Abort => {
+                self.addr.do_send(server::AbortGame { id: self.id });
+            }
+            Command::Ready => {
+                self.addr.do_send(server::Ready { id: self.id });
+            }
+            Command::Status => {
+                self.addr.do_send(server::GameStatus { id: self.id });
+            }
+            Command::Need => {
+                self.addr.do_send(server::SeatsNeeded { id: self.id });
+            }
+            Command::WhoAmI => {
+                self.addr.do_send(server::WhoAmI { id: self.id });
+            }
+            Command::Reveal => {
+                self.addr.do_send(server::RevealSelf { id: self.id });
+            }
+            Command::Resize { size } => {
+                self.addr.do_send(server::Resize { id: self.id, size });
+            }
+            Command::Paused(paused) => {
+                self.addr.do_send(server::SetPaused {
+                    id: self.id,
+                    paused,
+                });
+            }
+            Command::RevealOnEnd(enabled) => {
+                self.addr.do_send(server::SetRevealOnEnd {
+                    id: self.id,
+                    enabled,
+                });
+            }
+            Command::Stats => match self.name.as_ref() {
+                Some(name) => {
+                    self.addr.do_send(server::Stats {
+                        id: self.id,
+                        name: name.clone(),
+                    });
+                }
+                None => {
+                    self.send_error(
+                        ctx,
+                        server::ErrorCode::NameRequired,
+                        "!!! session name is required".to_owned(),
+                    );
+                }
+            },
+            Command::Overflow => {
+                self.addr.do_send(server::Overflow { id: self.id });
+            }
+            Command::Host { name } => {
+                self.addr.do_send(server::TransferHost {
+                    id: self.id,
+                    target_name: name,
+                });
+            }
+            Command::Propose { names } => {
+                self.addr
+                    .do_send(server::ProposeTeam { id: self.id, names });
+            }
+            Command::Vote { approve } => {
+                self.addr.do_send(server::CastVote {
+                    id: self.id,
+                    approve,
+                });
+            }
+            Command::Mission { fail } => {
+                self.addr
+                    .do_send(server::SubmitMission { id: self.id, fail });
+            }
+            Command::Assassinate { name } => {
+                self.addr.do_send(server::Assassinate {
+                    id: self.id,
+                    target_name: name,
+                });
+            }
+            Command::Say { text } => {
+                self.addr.do_send(server::ChatText { id: self.id, text });
+            }
+            Command::Mute { name, muted } => {
+                self.addr.do_send(server::Mute {
+                    id: self.id,
+                    target_name: name,
+                    muted,
+                });
+            }
+            Command::Lake { name } => {
+                self.addr.do_send(server::InspectLoyalty {
+                    id: self.id,
+                    target_name: name,
+                });
+            }
+            Command::Protocol(protocol) => {
+                if protocol == Protocol::Json && self.protocol != Protocol::Json {
+                    self.send_capabilities(ctx);
+                }
+                self.protocol = protocol;
+            }
+            Command::Auth { token } => self
+                .addr
+                .send(server::Authenticate { token })
+                .into_actor(self)
+                .then(|res, act, ctx| {
+                    match res {
+                        Ok(true) => {
+                            act.is_admin = true;
+                            act.send_system(ctx, "authenticated");
+                        }
+                        Ok(false) => act.send_error(
+                            ctx,
+                            server::ErrorCode::WrongToken,
+                            "!!! wrong token".to_owned(),
+                        ),
+                        _ => warn!("{} something is wrong", LogContext::session(act.id)),
+                    }
+                    fut::ready(())
+                })
+                .wait(ctx),
+            Command::Code => self
+                .addr
+                .send(server::RoomCode { id: self.id })
+                .into_actor(self)
+                .then(|res, act, ctx| {
+                    match res {
+                        Ok(Some(room)) => act.send_system(ctx, format!("room: {}", room)),
+                        Ok(None) => act.send_error(
+                            ctx,
+                            server::ErrorCode::NotInRoom,
+                            "!!! you are not in a room".to_owned(),
+                        ),
+                        _ => warn!("{} something is wrong", LogContext::session(act.id)),
+                    }
+                    fut::ready(())
+                })
+                .wait(ctx),
+            Command::Elapsed => self
+                .addr
+                .send(server::RoomElapsed { id: self.id })
+                .into_actor(self)
+                .then(|res, act, ctx| {
+                    match res {
+                        Ok(Some(elapsed)) => {
+                            // whole seconds read better
+                            let elapsed = Duration::from_secs(elapsed.as_secs());
+                            act.send_system(
+                                ctx,
+                                format!("elapsed: {}", humantime::format_duration(elapsed)),
+                            )
+                        }
+                        Ok(None) => act.send_error(
+                            ctx,
+                            server::ErrorCode::NotInRoom,
+                            "!!! you are not in a room".to_owned(),
+                        ),
+                        _ => warn!("{} something is wrong", LogContext::session(act.id)),
+                    }
+                    fut::ready(())
+                })
+                .wait(ctx),
+            Command::Close { room } => {
+                if !self.is_admin {
+                    self.send_error(
+                        ctx,
+                        server::ErrorCode::PermissionDenied,
+                        "!!! permission denied".to_owned(),
+                    );
+                    return;
+                }
+                self.addr
+                    .send(server::CloseRoom { name: room })
+                    .into_actor(self)
+                    .then(|res, act, ctx| {
+                        match res {
+                            Ok(true) => act.send_system(ctx, "closed"),
+                            Ok(false) => act.send_error(
+                                ctx,
+                                server::ErrorCode::RoomNotFound,
+                                "!!! room not exist".to_owned(),
+                            ),
+                            _ => warn!("{} something is wrong", LogContext::session(act.id)),
+                        }
+                        fut::ready(())
+                    })
+                    .wait(ctx)
+            }
+            Command::Name { name } => match self.config.names.validate(&name) {
+                Ok(name) => {
+                    self.name = Some(name);
+                }
+                Err(err) => {
+                    self.send_error(
+                        ctx,
+                        server::ErrorCode::InvalidName,
+                        format!("!!! invalid name: {}", err),
+                    );
+                }
+            },
         }
     }
-}
 
-impl WsChatSession {
     /// helper method that sends ping to client every second.
     ///
     /// also this method checks heartbeats from client
@@ -216,10 +1046,16 @@ impl WsChatSession {
             // check client heartbeats
             if Instant::now().duration_since(act.hb) > CLIENT_TIMEOUT {
                 // heartbeat timed out
-                debug!("Websocket Client heartbeat failed, disconnecting!");
+                debug!(
+                    "{} heartbeat failed, disconnecting",
+                    LogContext::session(act.id)
+                );
 
-                // notify chat server
-                act.addr.do_send(server::Disconnect { id: act.id });
+                // notify chat server, before the disconnect of `stopping`
+                act.addr.do_send(server::Disconnect {
+                    id: act.id,
+                    reason: server::DisconnectReason::Timeout,
+                });
 
                 // stop actor
                 ctx.stop();
@@ -228,7 +1064,460 @@ impl WsChatSession {
                 return;
             }
 
-            ctx.ping(b"");
+            ctx.ping(&act.config.ping_payload);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use actix_web::{test, App};
+    use bytes::Bytes;
+    use futures::{SinkExt, StreamExt};
+    use rand::rngs::mock::StepRng;
+
+    #[test]
+    fn test_fragments() {
+        let mut fragments = Fragments::default();
+        assert_eq!(
+            fragments
+                .push(Item::FirstText(Bytes::from_static(b"/l")))
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            fragments
+                .push(Item::Continue(Bytes::from_static(b"is")))
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            fragments
+                .push(Item::Last(Bytes::from_static(b"t")))
+                .unwrap(),
+            Some("/list".to_owned())
+        );
+
+        assert!(fragments
+            .push(Item::Last(Bytes::from_static(b"t")))
+            .is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_fragments_too_large() {
+        let mut srv = test::start(|| {
+            App::new()
+                .data(server::ChatServer::default().start())
+                .data(SessionConfig::default())
+                .service(web::resource("/ws/").to(chat_route))
+        });
+
+        let mut framed = srv.ws_at("/ws/").await.unwrap();
+        framed.next().await.unwrap().unwrap();
+        // a command padded with spaces, split in two halves
+        let send = |size: usize| {
+            let mut text = b"/version".to_vec();
+            text.resize(size, b' ');
+            let last = Bytes::from(text.split_off(size / 2));
+            // actix-http 1.0 writes the opcodes of the first items swapped,
+            // a text frame goes out as `FirstBinary`
+            vec![
+                ws::Message::Continuation(Item::FirstBinary(Bytes::from(text))),
+                ws::Message::Continuation(Item::Last(last)),
+            ]
+        };
+
+        for msg in send(MAX_MESSAGE_SIZE) {
+            framed.send(msg).await.unwrap();
+        }
+        let expected = format!("avalon-rs {}, protocol 1", env!("CARGO_PKG_VERSION"));
+        assert_eq!(
+            framed.next().await.unwrap().unwrap(),
+            ws::Frame::Text(Bytes::from(expected))
+        );
+
+        for msg in send(MAX_MESSAGE_SIZE + 1) {
+            framed.send(msg).await.unwrap();
+        }
+        match framed.next().await {
+            None | Some(Ok(ws::Frame::Close(_))) => (),
+            frame => panic!("unexpected frame: {:?}", frame),
+        }
+    }
+
+    #[test]
+    fn test_outbox() {
+        let mut outbox = Outbox::new(2);
+        assert!(outbox.send());
+        assert!(outbox.send());
+        assert!(!outbox.send());
+
+        outbox.flush();
+        assert!(outbox.send());
+    }
+
+    #[test]
+    fn test_render() {
+        let msg = server::Message {
+            kind: server::MessageKind::Private,
+            code: None,
+            text: "hint".to_owned(),
+            time: SystemTime::UNIX_EPOCH,
+        };
+        assert_eq!(render(Protocol::Text, &msg), "hint");
+        assert_eq!(
+            render(Protocol::Json, &msg),
+            r#"{"kind":"private","text":"hint","time":"1970-01-01T00:00:00.000Z"}"#
+        );
+
+        let msg = server::Message {
+            time: SystemTime::UNIX_EPOCH,
+            ..server::Message::error(
+                server::ErrorCode::InvalidSeat,
+                "!!! invalid seat: 6".to_owned(),
+            )
+        };
+        assert!(render(Protocol::Json, &msg).contains(r#""code":"INVALID_SEAT""#));
+    }
+
+    #[actix_rt::test]
+    async fn test_welcome() {
+        let mut srv = test::start(|| {
+            App::new()
+                .data(server::ChatServer::default().start())
+                .data(SessionConfig {
+                    welcome: "hello".to_owned(),
+                    ..SessionConfig::default()
+                })
+                .service(web::resource("/ws/").to(chat_route))
+        });
+
+        let mut framed = srv.ws_at("/ws/").await.unwrap();
+        let frame = framed.next().await.unwrap().unwrap();
+        assert_eq!(frame, ws::Frame::Text(Bytes::from_static(b"hello")));
+    }
+
+    #[actix_rt::test]
+    async fn test_ping_payload() {
+        let mut srv = test::start(|| {
+            App::new()
+                .data(server::ChatServer::default().start())
+                .data(SessionConfig {
+                    ping_payload: b"keepalive".to_vec(),
+                    ..SessionConfig::default()
+                })
+                .service(web::resource("/ws/").to(chat_route))
+        });
+
+        let mut framed = srv.ws_at("/ws/").await.unwrap();
+        framed.next().await.unwrap().unwrap();
+        let frame = framed.next().await.unwrap().unwrap();
+        assert_eq!(frame, ws::Frame::Ping(Bytes::from_static(b"keepalive")));
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let capabilities = serde_json::to_value(Capabilities::current()).unwrap();
+        assert_eq!(capabilities["kind"], "capabilities");
+        assert_eq!(capabilities["version"], PROTOCOL_VERSION);
+        assert_eq!(capabilities["server_version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(capabilities["min_players"], 5);
+        assert_eq!(capabilities["max_players"], 10);
+        assert_eq!(capabilities["locales"], serde_json::json!(["zh", "en"]));
+        let commands = capabilities["commands"].as_array().unwrap();
+        assert!(commands.contains(&"/create".into()));
+        assert!(commands.contains(&"/help".into()));
+    }
+
+    #[actix_rt::test]
+    async fn test_capabilities_on_connect() {
+        let mut srv = test::start(|| {
+            App::new()
+                .data(server::ChatServer::default().start())
+                .data(SessionConfig {
+                    protocol: Protocol::Json,
+                    ..SessionConfig::default()
+                })
+                .service(web::resource("/ws/").to(chat_route))
+        });
+
+        let mut framed = srv.ws_at("/ws/").await.unwrap();
+        framed.next().await.unwrap().unwrap();
+        let frame = framed.next().await.unwrap().unwrap();
+        let expected = serde_json::to_string(&Capabilities::current()).unwrap();
+        assert_eq!(frame, ws::Frame::Text(Bytes::from(expected)));
+    }
+
+    #[actix_rt::test]
+    async fn test_json_replies() {
+        let mut srv = test::start(|| {
+            let server = server::ChatServer::new(server::ServerConfig {
+                max_sessions: Some(1),
+                ..server::ServerConfig::default()
+            });
+            App::new()
+                .data(server.start())
+                .data(SessionConfig {
+                    welcome: "hello".to_owned(),
+                    protocol: Protocol::Json,
+                    ..SessionConfig::default()
+                })
+                .service(web::resource("/ws/").to(chat_route))
+        });
+
+        fn json(frame: ws::Frame) -> serde_json::Value {
+            match frame {
+                ws::Frame::Text(text) => serde_json::from_slice(&text).unwrap(),
+                frame => panic!("unexpected frame: {:?}", frame),
+            }
+        }
+
+        let mut framed = srv.ws_at("/ws/").await.unwrap();
+        let welcome = json(framed.next().await.unwrap().unwrap());
+        assert_eq!(welcome["kind"], "system");
+        assert_eq!(welcome["text"], "hello");
+        assert_eq!(
+            json(framed.next().await.unwrap().unwrap())["kind"],
+            "capabilities"
+        );
+        framed
+            .send(ws::Message::Text("/version".to_owned()))
+            .await
+            .unwrap();
+        let version = json(framed.next().await.unwrap().unwrap());
+        assert_eq!(version["kind"], "system");
+        assert!(version["text"].as_str().unwrap().starts_with("avalon-rs "));
+
+        let mut refused = srv.ws_at("/ws/").await.unwrap();
+        let full = json(refused.next().await.unwrap().unwrap());
+        assert_eq!(full["kind"], "error");
+        assert_eq!(full["code"], "SERVER_FULL");
+    }
+
+    #[actix_rt::test]
+    async fn test_refused_connect_keeps_session_zero() {
+        let mut srv = test::start(|| {
+            // the first session is drawn id 0
+            let server = server::ChatServer::new(server::ServerConfig {
+                max_sessions: Some(1),
+                ..server::ServerConfig::default()
+            })
+            .with_rng(StepRng::new(0, 0));
+            App::new()
+                .data(server.start())
+                .data(SessionConfig::default())
+                .service(web::resource("/ws/").to(chat_route))
         });
+
+        let mut first = srv.ws_at("/ws/").await.unwrap();
+        first.next().await.unwrap().unwrap();
+        let mut refused = srv.ws_at("/ws/").await.unwrap();
+        refused.next().await.unwrap().unwrap();
+        while refused.next().await.is_some() {}
+        actix_rt::time::delay_for(Duration::from_millis(50)).await;
+
+        first
+            .send(ws::Message::Text("/load".to_owned()))
+            .await
+            .unwrap();
+        assert_eq!(
+            first.next().await.unwrap().unwrap(),
+            ws::Frame::Text(Bytes::from_static(b"sessions: 1, rooms: 0"))
+        );
+    }
+
+    #[test]
+    fn test_sandbox_view() {
+        let assignment = game::Assignment::from_exact(
+            (1..=5).map(|seat| format!("#{}", seat)).collect(),
+            vec![
+                game::Role::Merlin,
+                game::Role::Assassin,
+                game::Role::Loyal,
+                game::Role::Morgana,
+                game::Role::Percival,
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            sandbox_view(&assignment, 1).unwrap(),
+            "#2 刺客：#2、#4 都是坏人"
+        );
+        assert_eq!(sandbox_view(&assignment, 2).unwrap(), "#3 忠臣");
+        assert!(sandbox_view(&assignment, 5).is_none());
+    }
+
+    #[actix_rt::test]
+    async fn test_see() {
+        let mut srv = test::start(|| {
+            App::new()
+                .data(server::ChatServer::default().start())
+                .data(SessionConfig::default())
+                .service(web::resource("/ws/").to(chat_route))
+        });
+
+        let mut framed = srv.ws_at("/ws/").await.unwrap();
+        framed.next().await.unwrap().unwrap();
+        // replies of each command, the sandbox deal is a frame per seat
+        let mut replies = Vec::new();
+        for &(command, frames) in &[
+            ("/see 1", 1),
+            ("/sandbox 5", 5),
+            ("/see 3", 1),
+            ("/see 6", 1),
+            ("/see 0", 1),
+        ] {
+            framed
+                .send(ws::Message::Text(command.to_owned()))
+                .await
+                .unwrap();
+            for _ in 0..frames {
+                match framed.next().await.unwrap().unwrap() {
+                    ws::Frame::Text(text) => {
+                        replies.push(String::from_utf8(text.to_vec()).unwrap())
+                    }
+                    frame => panic!("unexpected frame: {:?}", frame),
+                }
+            }
+        }
+
+        assert_eq!(replies[0], "!!! no sandbox deal, /sandbox <人数> first");
+        assert!(replies[1..6]
+            .iter()
+            .enumerate()
+            .all(|(seat, view)| view.starts_with(&format!("#{} ", seat + 1))));
+        assert_eq!(replies[6], replies[3]);
+        assert_eq!(replies[7], "!!! invalid seat: 6");
+        assert_eq!(replies[8], "!!! invalid seat: 0");
+    }
+
+    #[actix_rt::test]
+    async fn test_version() {
+        let mut srv = test::start(|| {
+            App::new()
+                .data(server::ChatServer::default().start())
+                .data(SessionConfig::default())
+                .service(web::resource("/ws/").to(chat_route))
+        });
+
+        let mut framed = srv.ws_at("/ws/").await.unwrap();
+        framed.next().await.unwrap().unwrap();
+        framed
+            .send(ws::Message::Text("/version".to_owned()))
+            .await
+            .unwrap();
+        let frame = framed.next().await.unwrap().unwrap();
+        let expected = format!("avalon-rs {}, protocol 1", env!("CARGO_PKG_VERSION"));
+        assert_eq!(frame, ws::Frame::Text(Bytes::from(expected)));
+    }
+
+    #[actix_rt::test]
+    async fn test_error_code() {
+        let mut srv = test::start(|| {
+            App::new()
+                .data(server::ChatServer::default().start())
+                .data(SessionConfig {
+                    protocol: Protocol::Json,
+                    ..SessionConfig::default()
+                })
+                .service(web::resource("/ws/").to(chat_route))
+        });
+
+        let mut framed = srv.ws_at("/ws/").await.unwrap();
+        // welcome and capabilities
+        framed.next().await.unwrap().unwrap();
+        framed.next().await.unwrap().unwrap();
+        for command in &["/name guest", "/join missing"] {
+            framed
+                .send(ws::Message::Text((*command).to_owned()))
+                .await
+                .unwrap();
+        }
+        let frame = match framed.next().await.unwrap().unwrap() {
+            ws::Frame::Text(text) => text,
+            frame => panic!("unexpected frame: {:?}", frame),
+        };
+        let error: serde_json::Value = serde_json::from_slice(&frame).unwrap();
+        assert_eq!(error["kind"], "error");
+        assert_eq!(error["code"], "ROOM_NOT_FOUND");
+        assert_eq!(error["text"], "!!! room not exist");
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let req = test::TestRequest::default()
+            .peer_addr("10.0.0.1:4242".parse().unwrap())
+            .to_http_request();
+        assert_eq!(client_ip(&req, false), Some("10.0.0.1".to_owned()));
+
+        let req = test::TestRequest::default()
+            .peer_addr("10.0.0.1:4242".parse().unwrap())
+            .header("x-forwarded-for", "192.168.1.7")
+            .to_http_request();
+        // a client may forge the header
+        assert_eq!(client_ip(&req, false), Some("10.0.0.1".to_owned()));
+        assert_eq!(client_ip(&req, true), Some("192.168.1.7".to_owned()));
+    }
+
+    #[actix_rt::test]
+    async fn test_connections_per_ip() {
+        let mut srv = test::start(|| {
+            let server = server::ChatServer::new(server::ServerConfig {
+                max_connections_per_ip: Some(2),
+                ..server::ServerConfig::default()
+            });
+            App::new()
+                .data(server.start())
+                .data(SessionConfig::default())
+                .service(web::resource("/ws/").to(chat_route))
+        });
+
+        let mut first = srv.ws_at("/ws/").await.unwrap();
+        first.next().await.unwrap().unwrap();
+        let mut second = srv.ws_at("/ws/").await.unwrap();
+        second.next().await.unwrap().unwrap();
+        let rejected = srv.ws_at("/ws/").await.err().unwrap();
+        assert!(format!("{:?}", rejected).contains("429"), "{:?}", rejected);
+
+        // a closed connection frees its slot
+        first.send(ws::Message::Close(None)).await.unwrap();
+        while first.next().await.is_some() {}
+        actix_rt::time::delay_for(Duration::from_millis(50)).await;
+        let mut third = srv.ws_at("/ws/").await.unwrap();
+        third.next().await.unwrap().unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn test_admin_commands_need_auth() {
+        let mut srv = test::start(|| {
+            let server = server::ChatServer::new(server::ServerConfig {
+                admin_token: Some("secret".to_owned()),
+                ..server::ServerConfig::default()
+            });
+            App::new()
+                .data(server.start())
+                .data(SessionConfig::default())
+                .service(web::resource("/ws/").to(chat_route))
+        });
+
+        let mut framed = srv.ws_at("/ws/").await.unwrap();
+        framed.next().await.unwrap().unwrap();
+        let replies = [
+            ("/close 42", "!!! permission denied"),
+            ("/auth guess", "!!! wrong token"),
+            ("/close 42", "!!! permission denied"),
+            ("/auth secret", "authenticated"),
+            ("/close 42", "!!! room not exist"),
+        ];
+        for &(command, reply) in &replies {
+            framed
+                .send(ws::Message::Text(command.to_owned()))
+                .await
+                .unwrap();
+            let frame = framed.next().await.unwrap().unwrap();
+            assert_eq!(frame, ws::Frame::Text(Bytes::from(reply)), "{}", command);
+        }
     }
 }